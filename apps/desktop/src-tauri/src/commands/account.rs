@@ -0,0 +1,431 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use async_imap::Session;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State, Wry};
+use tauri_plugin_store::{Store, StoreExt};
+use tokio::sync::Mutex;
+
+use super::auth::SessionError;
+use super::idle::IdleManager;
+use super::imap::{create_imap_session, CombinedStream};
+use super::oauth::{refresh_access_token, OAuthSettings};
+use super::pool::ImapPool;
+use super::token_store::{AccountSecret, TokenStore};
+
+/// Store con gli account registrati, senza segreti
+const ACCOUNTS_STORE: &str = "accounts.json";
+
+/// L'access token viene rinnovato se scade entro questo margine (secondi)
+const REFRESH_MARGIN: i64 = 5 * 60;
+
+/// Cifratura della connessione verso il server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// TLS implicito dall'apertura della connessione (IMAP 993, SMTP 465)
+    Tls,
+    /// Connessione in chiaro promossa a TLS con STARTTLS (IMAP 143, SMTP 587)
+    StartTls,
+    /// Nessuna cifratura: consentita solo verso localhost, per i test
+    Plain,
+}
+
+/// Metodo di autenticazione dell'account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    /// SASL XOAUTH2 con access token OAuth2 (Gmail, Outlook)
+    Xoauth2,
+    /// SASL OAUTHBEARER (RFC 7628) con access token OAuth2
+    OauthBearer,
+    /// SASL PLAIN con password o password per le app
+    Plain,
+    /// Comando LOGIN (IMAP) o SASL LOGIN (SMTP) con password o password per le app
+    Login,
+}
+
+impl AuthMethod {
+    /// true se il segreto dell'account è un access token OAuth2 invece di una password
+    pub fn is_oauth(self) -> bool {
+        matches!(self, AuthMethod::Xoauth2 | AuthMethod::OauthBearer)
+    }
+}
+
+/// Indirizzo e cifratura di un server IMAP o SMTP
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: Security,
+}
+
+impl ServerConfig {
+    /// Rifiuta le connessioni in chiaro verso host remoti: le credenziali viaggerebbero senza cifratura
+    pub fn check_security(&self) -> Result<(), String> {
+        if self.security == Security::Plain && !is_loopback(&self.host) {
+            return Err(format!(
+                "Connessione non cifrata consentita solo verso localhost: {}",
+                self.host
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Configurazione di un account di posta.
+/// Il segreto (access token OAuth2 o password) è conservato separatamente nel `TokenStore`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountConfig {
+    pub email: String,
+    /// Nome utente per l'autenticazione, se diverso dall'indirizzo email
+    #[serde(default)]
+    pub username: Option<String>,
+    pub imap: ServerConfig,
+    pub smtp: ServerConfig,
+    pub auth: AuthMethod,
+}
+
+impl AccountConfig {
+    /// Nome utente usato nell'autenticazione: quello configurato o, in mancanza, l'indirizzo email
+    pub fn username(&self) -> &str {
+        self.username
+            .as_deref()
+            .filter(|u| !u.trim().is_empty())
+            .unwrap_or(&self.email)
+    }
+}
+
+/// Account registrato nel backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredAccount {
+    pub id: String,
+    pub config: AccountConfig,
+    /// Parametri per il refresh dei token, per gli account OAuth2
+    #[serde(default)]
+    pub oauth: Option<OAuthSettings>,
+}
+
+/// Account e credenziali gestiti dal backend, registrato nello stato Tauri con `app.manage`.
+/// I comandi ricevono solo l'`account_id`: configurazione e segreto vengono letti qui.
+pub struct AccountManager {
+    app: AppHandle,
+    tokens: TokenStore,
+    /// Serializza i refresh: le richieste concorrenti usano il token appena ottenuto
+    refresh_lock: Mutex<()>,
+}
+
+impl AccountManager {
+    pub fn new(app: AppHandle) -> Self {
+        AccountManager {
+            tokens: TokenStore::new(&app),
+            app,
+            refresh_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn account(&self, account_id: &str) -> Result<StoredAccount, String> {
+        let value = self
+            .store()?
+            .get(account_id)
+            .ok_or_else(|| format!("Account non trovato: {}", account_id))?;
+        serde_json::from_value(value).map_err(|e| format!("Account salvato non valido: {}", e))
+    }
+
+    /// Salva la configurazione dell'account e, se indicato, il nuovo segreto.
+    /// Senza segreto mantiene quello salvato, solo se server e impostazioni OAuth2 non cambiano.
+    pub fn register(&self, account: &StoredAccount, secret: Option<&AccountSecret>) -> Result<(), String> {
+        match secret {
+            Some(secret) => {
+                check_secret_method(account.config.auth, secret)?;
+                self.tokens.save(&account.id, secret)?;
+            }
+            None => {
+                let stored = self
+                    .tokens
+                    .load(&account.id)?
+                    .ok_or_else(|| format!("Credenziali mancanti per l'account {}", account.id))?;
+                let previous = self.account(&account.id).ok();
+                check_kept_secret(previous.as_ref(), account, &stored)?;
+            }
+        }
+
+        let value = serde_json::to_value(account).map_err(|e| format!("Errore nella serializzazione dell'account: {}", e))?;
+        let store = self.store()?;
+        store.set(account.id.clone(), value);
+        store
+            .save()
+            .map_err(|e| format!("Errore nel salvataggio dell'account: {}", e))
+    }
+
+    /// Configurazione e segreto validi per l'account, rinnovando l'access token se sta per scadere
+    pub async fn credentials(&self, account_id: &str) -> Result<(AccountConfig, String), String> {
+        self.resolve(account_id, None).await
+    }
+
+    /// Rinnova l'access token rifiutato dal server. Se nel frattempo un'altra richiesta
+    /// lo ha già rinnovato, restituisce quello nuovo senza un secondo refresh.
+    pub async fn refresh_rejected(&self, account_id: &str, rejected: &str) -> Result<(AccountConfig, String), String> {
+        self.resolve(account_id, Some(rejected)).await
+    }
+
+    /// Apre una sessione IMAP autenticata. Se il server rifiuta l'access token OAuth2
+    /// (AUTHENTICATIONFAILED) lo rinnova e riprova una volta.
+    pub(crate) async fn open_session(&self, account_id: &str) -> Result<(AccountConfig, Session<CombinedStream>), SessionError> {
+        let (config, secret) = self.credentials(account_id).await?;
+        match create_imap_session(&config, &secret).await {
+            Ok(session) => Ok((config, session)),
+            Err(SessionError::Authentication(_)) if config.auth.is_oauth() => {
+                println!("[ACCOUNTS] Token rifiutato dal server IMAP per account {}, riprovo dopo il refresh", account_id);
+                let (config, secret) = self.refresh_rejected(account_id, &secret).await?;
+                let session = create_imap_session(&config, &secret).await?;
+                Ok((config, session))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn resolve(&self, account_id: &str, rejected: Option<&str>) -> Result<(AccountConfig, String), String> {
+        let account = self.account(account_id)?;
+        let needs_refresh = |secret: &AccountSecret| match secret {
+            AccountSecret::Password { .. } => false,
+            AccountSecret::Oauth { access_token, expires_at, .. } => {
+                rejected == Some(access_token.as_str()) || expires_at - Utc::now().timestamp() < REFRESH_MARGIN
+            }
+        };
+
+        let secret = self.secret(account_id)?;
+        if !needs_refresh(&secret) {
+            return Ok((account.config, secret.into_value()));
+        }
+
+        let _refresh = self.refresh_lock.lock().await;
+        // Un'altra richiesta può aver rinnovato il token mentre attendevamo il lock
+        let secret = self.secret(account_id)?;
+        if !needs_refresh(&secret) {
+            return Ok((account.config, secret.into_value()));
+        }
+        let AccountSecret::Oauth { refresh_token: Some(refresh_token), .. } = secret else {
+            return Err("Token scaduto e nessun refresh token disponibile: è necessario rifare il login".to_string());
+        };
+        let settings = account
+            .oauth
+            .as_ref()
+            .ok_or_else(|| format!("Impostazioni OAuth2 mancanti per l'account {}", account_id))?;
+
+        println!("[ACCOUNTS] Refresh dell'access token per account: {}", account_id);
+        let tokens = refresh_access_token(settings, &refresh_token).await?;
+        self.tokens.save(
+            account_id,
+            &AccountSecret::Oauth {
+                access_token: tokens.access_token.clone(),
+                refresh_token: tokens.refresh_token.or(Some(refresh_token)),
+                expires_at: tokens.expires_at,
+            },
+        )?;
+        Ok((account.config, tokens.access_token))
+    }
+
+    fn secret(&self, account_id: &str) -> Result<AccountSecret, String> {
+        self.tokens
+            .load(account_id)?
+            .ok_or_else(|| format!("Credenziali non trovate per l'account {}", account_id))
+    }
+
+    fn store(&self) -> Result<Arc<Store<Wry>>, String> {
+        self.app
+            .store(ACCOUNTS_STORE)
+            .map_err(|e| format!("Errore nell'apertura dello store degli account: {}", e))
+    }
+}
+
+/// Il segreto deve corrispondere al metodo: access token per OAuth2, password altrimenti
+fn check_secret_method(auth: AuthMethod, secret: &AccountSecret) -> Result<(), String> {
+    let is_oauth = matches!(secret, AccountSecret::Oauth { .. });
+    if is_oauth != auth.is_oauth() {
+        return Err(format!("Credenziali non compatibili con il metodo di autenticazione {:?}", auth));
+    }
+    Ok(())
+}
+
+/// Verifica che il segreto salvato possa essere riutilizzato con la nuova configurazione.
+/// Con server, metodo di autenticazione o endpoint OAuth2 diversi il segreto verrebbe inviato
+/// a destinazioni scelte dal webview: in questi casi servono credenziali nuove.
+fn check_kept_secret(previous: Option<&StoredAccount>, account: &StoredAccount, stored: &AccountSecret) -> Result<(), String> {
+    check_secret_method(account.config.auth, stored)?;
+    let unchanged = previous.is_some_and(|previous| {
+        previous.config.imap == account.config.imap
+            && previous.config.smtp == account.config.smtp
+            && previous.config.auth == account.config.auth
+            && previous.oauth == account.oauth
+    });
+    if !unchanged {
+        return Err(format!(
+            "Server o impostazioni di autenticazione modificati per l'account {}: inserire di nuovo le credenziali",
+            account.id
+        ));
+    }
+    Ok(())
+}
+
+/// Verifica le credenziali aprendo e chiudendo una sessione IMAP
+pub(crate) async fn verify_login(config: &AccountConfig, secret: &AccountSecret) -> Result<(), SessionError> {
+    let mut session = create_imap_session(config, &secret.clone().into_value()).await?;
+    let _ = session.logout().await;
+    Ok(())
+}
+
+/// Registra o aggiorna un account. Senza `secret` mantiene le credenziali già salvate.
+/// Le nuove credenziali vengono verificate con il server IMAP prima del salvataggio:
+/// un rifiuto restituisce al frontend i dettagli di ogni metodo provato.
+#[tauri::command]
+pub async fn save_account(
+    accounts: State<'_, AccountManager>,
+    account: StoredAccount,
+    secret: Option<AccountSecret>,
+) -> Result<(), SessionError> {
+    println!("[ACCOUNTS] Salvataggio account: {} ({})", account.id, account.config.email);
+    if let Some(secret) = &secret {
+        verify_login(&account.config, secret).await?;
+    }
+    Ok(accounts.register(&account, secret.as_ref())?)
+}
+
+/// Elenca gli account registrati nel backend
+#[tauri::command]
+pub async fn list_accounts(accounts: State<'_, AccountManager>) -> Result<Vec<StoredAccount>, String> {
+    Ok(accounts
+        .store()?
+        .values()
+        .into_iter()
+        .filter_map(|value| match serde_json::from_value(value) {
+            Ok(account) => Some(account),
+            Err(e) => {
+                println!("[ACCOUNTS] Account salvato non valido: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Rimuove un account e le sue credenziali, chiudendo le sue sessioni IMAP
+#[tauri::command]
+pub async fn remove_account(
+    accounts: State<'_, AccountManager>,
+    pool: State<'_, ImapPool>,
+    idle: State<'_, IdleManager>,
+    account_id: String,
+) -> Result<(), String> {
+    println!("[ACCOUNTS] Rimozione account: {}", account_id);
+    // Prima le sessioni: il watcher IDLE non deve riconnettersi con le credenziali rimosse
+    idle.stop(&account_id).await;
+    pool.remove(&account_id).await;
+    accounts.tokens.delete(&account_id)?;
+    let store = accounts.store()?;
+    store.delete(&account_id);
+    store
+        .save()
+        .map_err(|e| format!("Errore nel salvataggio degli account: {}", e))
+}
+
+fn is_loopback(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().map(|ip| ip.is_loopback()).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::local_account;
+
+    fn stored(config: AccountConfig, oauth: Option<OAuthSettings>) -> StoredAccount {
+        StoredAccount {
+            id: "test".to_string(),
+            config,
+            oauth,
+        }
+    }
+
+    fn password() -> AccountSecret {
+        AccountSecret::Password {
+            password: "segreto".to_string(),
+        }
+    }
+
+    fn token() -> AccountSecret {
+        AccountSecret::Oauth {
+            access_token: "token".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: 0,
+        }
+    }
+
+    fn oauth_account(token_url: &str) -> StoredAccount {
+        let config = AccountConfig {
+            auth: AuthMethod::Xoauth2,
+            ..local_account(993)
+        };
+        let settings = OAuthSettings {
+            token_url: token_url.to_string(),
+            client_id: "client".to_string(),
+            client_secret: None,
+        };
+        stored(config, Some(settings))
+    }
+
+    #[test]
+    fn keeps_the_secret_when_only_the_display_settings_change() {
+        let previous = stored(local_account(993), None);
+        let account = stored(
+            AccountConfig {
+                email: "altro@example.com".to_string(),
+                username: Some("altro".to_string()),
+                ..local_account(993)
+            },
+            None,
+        );
+        assert!(check_kept_secret(Some(&previous), &account, &password()).is_ok());
+
+        let previous = oauth_account("https://oauth2.example.com/token");
+        assert!(check_kept_secret(Some(&previous), &previous.clone(), &token()).is_ok());
+    }
+
+    #[test]
+    fn requires_new_credentials_when_servers_or_oauth_settings_change() {
+        let previous = stored(local_account(993), None);
+
+        let mut imap = previous.clone();
+        imap.config.imap.host = "imap.attaccante.example".to_string();
+        assert!(check_kept_secret(Some(&previous), &imap, &password()).is_err());
+
+        let mut smtp = previous.clone();
+        smtp.config.smtp.port = 2525;
+        assert!(check_kept_secret(Some(&previous), &smtp, &password()).is_err());
+
+        let mut security = previous.clone();
+        security.config.imap.security = Security::StartTls;
+        assert!(check_kept_secret(Some(&previous), &security, &password()).is_err());
+
+        let mut auth = previous.clone();
+        auth.config.auth = AuthMethod::Plain;
+        assert!(check_kept_secret(Some(&previous), &auth, &password()).is_err());
+
+        let previous = oauth_account("https://oauth2.example.com/token");
+        let token_url = oauth_account("https://attaccante.example/token");
+        assert!(check_kept_secret(Some(&previous), &token_url, &token()).is_err());
+    }
+
+    #[test]
+    fn requires_new_credentials_without_a_saved_account_or_with_the_wrong_secret() {
+        let account = stored(local_account(993), None);
+        assert!(check_kept_secret(None, &account, &password()).is_err());
+
+        // Il metodo non cambia ma il segreto salvato non è una password
+        assert!(check_kept_secret(Some(&account), &account, &token()).is_err());
+        let oauth = oauth_account("https://oauth2.example.com/token");
+        assert!(check_kept_secret(Some(&oauth), &oauth, &password()).is_err());
+    }
+}
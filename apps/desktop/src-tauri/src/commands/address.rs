@@ -0,0 +1,179 @@
+use imap_proto::types::Address;
+use mailparse::{MailAddr, MailHeader, MailHeaderMap, SingleInfo};
+use serde::{Deserialize, Serialize};
+
+use super::mime::decode_header_value;
+
+/// Indirizzo email con nome visualizzato già decodificato (RFC 2047)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailAddress {
+    pub name: Option<String>,
+    pub address: String,
+    /// Nome del gruppo RFC 5322 di appartenenza (es. "undisclosed-recipients")
+    pub group: Option<String>,
+}
+
+impl EmailAddress {
+    fn from_single(info: &SingleInfo, group: Option<&str>) -> Self {
+        EmailAddress {
+            name: info.display_name.clone().filter(|n| !n.trim().is_empty()),
+            // mailparse lascia la virgola che segue la chiusura di un gruppo ("...;, b@x") nell'indirizzo successivo
+            address: info.addr.trim_start_matches(|c: char| c == ',' || c.is_whitespace()).to_string(),
+            group: group.map(|g| g.to_string()),
+        }
+    }
+}
+
+/// Indirizzi di un header: dagli header originali se presenti e validi, altrimenti da ENVELOPE
+pub fn message_addresses(headers: &[MailHeader], name: &str, envelope: Option<&Vec<Address>>) -> Vec<EmailAddress> {
+    header_addresses(headers, name).unwrap_or_else(|| envelope_addresses(envelope))
+}
+
+/// Legge gli indirizzi di un header (From, To, Cc, ...) con il parser RFC 5322 di mailparse.
+/// Restituisce None se l'header manca o non è interpretabile, per ricadere su ENVELOPE.
+fn header_addresses(headers: &[MailHeader], name: &str) -> Option<Vec<EmailAddress>> {
+    let header = headers.get_first_header(name)?;
+    let list = match mailparse::addrparse_header(header) {
+        Ok(list) => list,
+        Err(e) => {
+            println!("[MIME] Header {} non interpretabile: {}", name, e);
+            return None;
+        }
+    };
+
+    let mut addresses = Vec::new();
+    for address in list.iter() {
+        match address {
+            MailAddr::Single(info) => addresses.push(EmailAddress::from_single(info, None)),
+            MailAddr::Group(group) => addresses.extend(
+                group
+                    .addrs
+                    .iter()
+                    .map(|info| EmailAddress::from_single(info, Some(&group.group_name))),
+            ),
+        }
+    }
+    Some(addresses)
+}
+
+/// Converte una lista di indirizzi ENVELOPE.
+/// RFC 3501: un elemento con host NIL apre un gruppo (mailbox = nome del gruppo),
+/// uno con mailbox e host NIL lo chiude.
+fn envelope_addresses(addresses: Option<&Vec<Address>>) -> Vec<EmailAddress> {
+    let mut result = Vec::new();
+    let mut group: Option<String> = None;
+
+    for address in addresses.into_iter().flatten() {
+        let mailbox = address.mailbox.as_deref().map(|m| String::from_utf8_lossy(m).into_owned());
+        match (mailbox, address.host.as_deref()) {
+            (Some(name), None) => group = Some(decode_header_value(name.as_bytes())),
+            (None, None) => group = None,
+            (mailbox, Some(host)) => result.push(EmailAddress {
+                name: address
+                    .name
+                    .as_deref()
+                    .map(decode_header_value)
+                    .filter(|n| !n.trim().is_empty()),
+                address: format!("{}@{}", mailbox.unwrap_or_default(), String::from_utf8_lossy(host)),
+                group: group.clone(),
+            }),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    fn headers(raw: &str) -> Vec<MailHeader<'_>> {
+        mailparse::parse_headers(raw.as_bytes()).expect("header validi").0
+    }
+
+    fn envelope_address<'a>(name: Option<&'a str>, mailbox: Option<&'a str>, host: Option<&'a str>) -> Address<'a> {
+        Address {
+            name: name.map(|n| Cow::Borrowed(n.as_bytes())),
+            adl: None,
+            mailbox: mailbox.map(|m| Cow::Borrowed(m.as_bytes())),
+            host: host.map(|h| Cow::Borrowed(h.as_bytes())),
+        }
+    }
+
+    fn address(name: Option<&str>, address: &str, group: Option<&str>) -> EmailAddress {
+        EmailAddress {
+            name: name.map(str::to_string),
+            address: address.to_string(),
+            group: group.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn decodes_rfc2047_display_names() {
+        let headers = headers(
+            "From: =?UTF-8?B?R2l1c2VwcGUgVmVyZGk=?= <giuseppe@example.com>\r\n\
+             To: =?ISO-8859-1?Q?Nicol=F2_Rossi?= <nicolo@example.com>, \"Bianchi, Anna\" <anna@example.com>\r\n\r\n",
+        );
+
+        assert_eq!(
+            message_addresses(&headers, "From", None),
+            vec![address(Some("Giuseppe Verdi"), "giuseppe@example.com", None)]
+        );
+        assert_eq!(
+            message_addresses(&headers, "To", None),
+            vec![
+                address(Some("Nicolò Rossi"), "nicolo@example.com", None),
+                address(Some("Bianchi, Anna"), "anna@example.com", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_group_of_each_address() {
+        let headers = headers(
+            "To: undisclosed-recipients:;\r\n\
+             Cc: Squadra: anna@example.com, Bruno <bruno@example.com>;, carla@example.com\r\n\r\n",
+        );
+
+        assert!(message_addresses(&headers, "To", None).is_empty());
+        assert_eq!(
+            message_addresses(&headers, "Cc", None),
+            vec![
+                address(None, "anna@example.com", Some("Squadra")),
+                address(Some("Bruno"), "bruno@example.com", Some("Squadra")),
+                address(None, "carla@example.com", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_envelope_when_the_header_cannot_be_parsed() {
+        let headers = headers("To: \"Anna <anna@example.com\r\n\r\n");
+        let envelope = vec![envelope_address(Some("=?UTF-8?Q?Anna_M=C3=BCller?="), Some("anna"), Some("example.com"))];
+
+        assert_eq!(
+            message_addresses(&headers, "To", Some(&envelope)),
+            vec![address(Some("Anna Müller"), "anna@example.com", None)]
+        );
+    }
+
+    #[test]
+    fn uses_envelope_when_the_header_is_missing() {
+        // RFC 3501: host NIL apre il gruppo, mailbox e host NIL lo chiudono
+        let envelope = vec![
+            envelope_address(None, Some("Squadra"), None),
+            envelope_address(None, Some("anna"), Some("example.com")),
+            envelope_address(None, None, None),
+            envelope_address(Some(""), Some("bruno"), Some("example.com")),
+        ];
+
+        assert_eq!(
+            message_addresses(&[], "To", Some(&envelope)),
+            vec![
+                address(None, "anna@example.com", Some("Squadra")),
+                address(None, "bruno@example.com", None),
+            ]
+        );
+        assert!(message_addresses(&[], "To", None).is_empty());
+    }
+}
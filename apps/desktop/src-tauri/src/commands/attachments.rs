@@ -0,0 +1,105 @@
+use std::path::Path;
+use imap_proto::types::SectionPath;
+use tauri::ipc::Response;
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+use super::account::AccountManager;
+use super::imap::fetch_one;
+use super::mime::{decode_transfer_encoding, find_part, part_encoding};
+use super::pool::{ImapPool, PooledConnection};
+
+/// Scarica un allegato e lo restituisce come dati binari (ArrayBuffer nel frontend)
+#[tauri::command]
+pub async fn fetch_attachment(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uid: u32,
+    part: String,
+) -> Result<Response, String> {
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let data = download_part(&mut conn, &folder_path, uid, &part).await?;
+    Ok(Response::new(data))
+}
+
+/// Scarica un allegato e lo salva nel percorso scelto dall'utente.
+/// Restituisce il percorso del file, oppure None se l'utente annulla.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn save_attachment(
+    app: AppHandle,
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uid: u32,
+    part: String,
+    filename: String,
+) -> Result<Option<String>, String> {
+    // Il nome proposto non deve contenere percorsi
+    let filename = Path::new(&filename)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "allegato".to_string());
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_file_name(&filename)
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    let path = match rx.await.map_err(|e| format!("Errore nella finestra di salvataggio: {}", e))? {
+        Some(path) => path
+            .into_path()
+            .map_err(|e| format!("Percorso non valido: {}", e))?,
+        None => {
+            println!("[IMAP] Salvataggio allegato annullato");
+            return Ok(None);
+        }
+    };
+
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let data = download_part(&mut conn, &folder_path, uid, &part).await?;
+    tokio::fs::write(&path, data)
+        .await
+        .map_err(|e| format!("Errore nel salvataggio di {}: {}", path.display(), e))?;
+
+    println!("[IMAP] Allegato salvato in {}", path.display());
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+/// Legge una parte MIME con BODY.PEEK[part] e rimuove base64/quoted-printable
+pub(crate) async fn download_part(
+    conn: &mut PooledConnection,
+    folder_path: &str,
+    uid: u32,
+    part: &str,
+) -> Result<Vec<u8>, String> {
+    println!("[IMAP] Fetch allegato {} del messaggio {} in {}", part, uid, folder_path);
+
+    let path: Vec<u32> = part
+        .split('.')
+        .map(|n| n.parse().map_err(|_| format!("Parte MIME non valida: {}", part)))
+        .collect::<Result<_, _>>()?;
+
+    conn.select(folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+
+    // BODYSTRUCTURE nello stesso FETCH per conoscere la codifica della parte
+    let fetched = fetch_one(conn, uid, &format!("(UID BODYSTRUCTURE BODY.PEEK[{}])", part)).await?;
+    let encoding = fetched
+        .bodystructure()
+        .and_then(|structure| find_part(structure, &path))
+        .map(part_encoding)
+        .ok_or_else(|| format!("Parte {} non trovata nel messaggio {}", part, uid))?;
+    let raw = fetched
+        .section(&SectionPath::Part(path, None))
+        .ok_or_else(|| format!("Contenuto della parte {} non ricevuto", part))?;
+
+    Ok(decode_transfer_encoding(raw, encoding))
+}
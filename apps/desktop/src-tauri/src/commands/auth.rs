@@ -0,0 +1,352 @@
+use std::fmt;
+
+use async_imap::Authenticator;
+use serde::Serialize;
+
+use super::account::{AuthMethod, ServerConfig};
+
+/// Authenticator SASL con risposta iniziale (XOAUTH2, OAUTHBEARER, PLAIN).
+/// async-imap codifica in base64 la risposta restituita da `process`.
+pub struct SaslAuthenticator {
+    response: Option<String>,
+    /// Dettagli dell'errore inviati dal server in una seconda richiesta, già decodificati
+    error_payload: Option<Vec<u8>>,
+}
+
+impl SaslAuthenticator {
+    pub fn new(response: String) -> Self {
+        SaslAuthenticator {
+            response: Some(response),
+            error_payload: None,
+        }
+    }
+
+    pub fn error_payload(&self) -> Option<&[u8]> {
+        self.error_payload.as_deref()
+    }
+}
+
+impl Authenticator for &mut SaslAuthenticator {
+    type Response = String;
+
+    fn process(&mut self, challenge: &[u8]) -> Self::Response {
+        // Alla prima richiesta inviamo le credenziali; una seconda richiesta contiene
+        // i dettagli dell'errore (XOAUTH2/OAUTHBEARER) e va chiusa con una risposta vuota
+        match self.response.take() {
+            Some(response) => response,
+            None => {
+                self.error_payload = Some(challenge.to_vec());
+                String::new()
+            }
+        }
+    }
+}
+
+/// Meccanismo SASL e risposta iniziale (non ancora codificata in base64)
+pub fn sasl_initial_response(
+    method: AuthMethod,
+    username: &str,
+    secret: &str,
+    server: &ServerConfig,
+) -> (&'static str, String) {
+    match method {
+        // Formato: user=email\1auth=Bearer access_token\1\1
+        AuthMethod::Xoauth2 => ("XOAUTH2", format!("user={}\x01auth=Bearer {}\x01\x01", username, secret)),
+        // RFC 7628: gs2-header con authzid, poi host, porta e token separati da \1
+        AuthMethod::OauthBearer => (
+            "OAUTHBEARER",
+            format!(
+                "n,a={},\x01host={}\x01port={}\x01auth=Bearer {}\x01\x01",
+                username.replace('=', "=3D").replace(',', "=2C"),
+                server.host,
+                server.port,
+                secret
+            ),
+        ),
+        // RFC 4616: authzid vuoto, authcid e password separati da NUL
+        AuthMethod::Plain | AuthMethod::Login => ("PLAIN", format!("\0{}\0{}", username, secret)),
+    }
+}
+
+/// Metodi da provare, in ordine, scelti tra quelli annunciati da CAPABILITY.
+/// Il metodo configurato viene prima; il segreto dell'account decide la famiglia:
+/// un access token si usa solo con XOAUTH2/OAUTHBEARER, una password con PLAIN/LOGIN.
+pub fn negotiate(configured: AuthMethod, capabilities: &[String]) -> Vec<AuthMethod> {
+    let has = |capability: &str| capabilities.iter().any(|c| c.eq_ignore_ascii_case(capability));
+    let family = match configured {
+        AuthMethod::Xoauth2 | AuthMethod::OauthBearer => [AuthMethod::Xoauth2, AuthMethod::OauthBearer],
+        AuthMethod::Plain | AuthMethod::Login => [AuthMethod::Plain, AuthMethod::Login],
+    };
+
+    let mut methods = Vec::new();
+    for method in std::iter::once(configured).chain(family) {
+        let advertised = match method {
+            AuthMethod::Xoauth2 => has("AUTH=XOAUTH2"),
+            AuthMethod::OauthBearer => has("AUTH=OAUTHBEARER"),
+            AuthMethod::Plain => has("AUTH=PLAIN"),
+            // RFC 3501: LOGINDISABLED vieta il comando LOGIN
+            AuthMethod::Login => !has("LOGINDISABLED"),
+        };
+        if advertised && !methods.contains(&method) {
+            methods.push(method);
+        }
+    }
+
+    // Alcuni server non annunciano i meccanismi SASL: proviamo comunque quello configurato
+    if methods.is_empty() {
+        methods.push(configured);
+    }
+    methods
+}
+
+/// Rifiuto dell'autenticazione con i dettagli restituiti dal server
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthError {
+    pub method: AuthMethod,
+    /// Testo della risposta NO/BAD (es. "Invalid credentials")
+    pub message: String,
+    /// Payload SASL dell'errore (JSON per XOAUTH2 e OAUTHBEARER, RFC 7628 sezione 3.2.2)
+    pub payload: Option<String>,
+    /// Campo "status" del payload (es. "401" per Gmail, "invalid_token" per OAUTHBEARER)
+    pub status: Option<String>,
+    /// Campo "scope" del payload: lo scope OAuth2 richiesto dal server
+    pub scope: Option<String>,
+}
+
+impl AuthError {
+    pub fn new(method: AuthMethod, error: &async_imap::error::Error, payload: Option<&[u8]>) -> Self {
+        let message = match error {
+            async_imap::error::Error::No(text) | async_imap::error::Error::Bad(text) => server_text(text),
+            other => other.to_string(),
+        };
+        let payload = payload
+            .map(|p| String::from_utf8_lossy(p).trim().to_string())
+            .filter(|p| !p.is_empty());
+        let json = payload
+            .as_deref()
+            .and_then(|p| serde_json::from_str::<serde_json::Value>(p).ok());
+        let field = |name: &str| {
+            json.as_ref().and_then(|j| j.get(name)).map(|value| match value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            })
+        };
+
+        AuthError {
+            method,
+            status: field("status"),
+            scope: field("scope"),
+            message,
+            payload,
+        }
+    }
+}
+
+/// Testo della risposta NO/BAD. async-imap lo riporta come `code: .., info: Some("..")`,
+/// con il testo del server formattato da `Debug`: estraiamo solo quest'ultimo.
+fn server_text(error: &str) -> String {
+    let info = error
+        .split_once(", info: Some(\"")
+        .filter(|(code, _)| code.starts_with("code: "))
+        .and_then(|(_, info)| info.strip_suffix("\")"));
+    match info {
+        Some(info) => info.replace("\\\"", "\"").replace("\\\\", "\\").trim().to_string(),
+        None => error.trim().to_string(),
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} rifiutato: {}", self.method, self.message)?;
+        match (&self.status, &self.scope, &self.payload) {
+            (Some(status), Some(scope), _) => write!(f, " (status {}, scope {})", status, scope),
+            (Some(status), None, _) => write!(f, " (status {})", status),
+            // Payload non JSON: lo riportiamo così com'è
+            (None, _, Some(payload)) => write!(f, " ({})", payload),
+            (None, _, None) => Ok(()),
+        }
+    }
+}
+
+/// Errore nell'apertura di una sessione IMAP.
+/// Serializzato come `{ kind, details }` per i comandi che lo restituiscono al frontend.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "details", rename_all = "snake_case")]
+pub enum SessionError {
+    /// Connessione, TLS o saluto del server non riusciti
+    Connection(String),
+    /// Tutti i metodi di autenticazione provati sono stati rifiutati
+    Authentication(Vec<AuthError>),
+    /// Account, credenziali salvate o configurazione non validi
+    Other(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Connection(message) | SessionError::Other(message) => write!(f, "{}", message),
+            SessionError::Authentication(failures) => {
+                write!(f, "Errore nell'autenticazione")?;
+                for (index, failure) in failures.iter().enumerate() {
+                    write!(f, "{} {}", if index == 0 { ":" } else { ";" }, failure)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<String> for SessionError {
+    fn from(message: String) -> Self {
+        SessionError::Other(message)
+    }
+}
+
+impl From<SessionError> for String {
+    fn from(error: SessionError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+    use base64::Engine;
+
+    use super::*;
+    use crate::commands::imap::create_imap_session;
+    use crate::commands::test_support::FakeImapServer;
+
+    fn capabilities(list: &[&str]) -> Vec<String> {
+        list.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn negotiate_tries_the_configured_method_first() {
+        let oauth = capabilities(&["IMAP4REV1", "AUTH=XOAUTH2", "AUTH=OAUTHBEARER"]);
+        assert_eq!(negotiate(AuthMethod::OauthBearer, &oauth), vec![AuthMethod::OauthBearer, AuthMethod::Xoauth2]);
+        assert_eq!(negotiate(AuthMethod::Xoauth2, &oauth), vec![AuthMethod::Xoauth2, AuthMethod::OauthBearer]);
+
+        // Le password non si provano mai con i meccanismi OAuth2, e viceversa
+        let plain = capabilities(&["IMAP4REV1", "AUTH=PLAIN", "AUTH=XOAUTH2"]);
+        assert_eq!(negotiate(AuthMethod::Login, &plain), vec![AuthMethod::Login, AuthMethod::Plain]);
+        assert_eq!(negotiate(AuthMethod::Plain, &plain), vec![AuthMethod::Plain, AuthMethod::Login]);
+    }
+
+    #[test]
+    fn negotiate_falls_back_to_the_configured_method() {
+        let none = capabilities(&["IMAP4REV1"]);
+        assert_eq!(negotiate(AuthMethod::Xoauth2, &none), vec![AuthMethod::Xoauth2]);
+        assert_eq!(negotiate(AuthMethod::Plain, &none), vec![AuthMethod::Login]);
+    }
+
+    #[test]
+    fn negotiate_skips_login_when_logindisabled() {
+        let disabled = capabilities(&["IMAP4REV1", "LOGINDISABLED", "AUTH=PLAIN"]);
+        assert_eq!(negotiate(AuthMethod::Login, &disabled), vec![AuthMethod::Plain]);
+        assert_eq!(negotiate(AuthMethod::Plain, &disabled), vec![AuthMethod::Plain]);
+    }
+
+    #[test]
+    fn auth_error_reads_status_and_scope_from_the_json_payload() {
+        let rejected = async_imap::error::Error::No(
+            "code: Some(AuthenticationFailed), info: Some(\"Invalid credentials \\\"utente\\\"\")".to_string(),
+        );
+        let payload = br#"{"status":401,"schemes":"Bearer","scope":"https://mail.google.com/"}"#;
+        let error = AuthError::new(AuthMethod::Xoauth2, &rejected, Some(payload));
+        assert_eq!(error.message, "Invalid credentials \"utente\"");
+        assert_eq!(error.status.as_deref(), Some("401"));
+        assert_eq!(error.scope.as_deref(), Some("https://mail.google.com/"));
+        assert_eq!(
+            error.to_string(),
+            "Xoauth2 rifiutato: Invalid credentials \"utente\" (status 401, scope https://mail.google.com/)"
+        );
+
+        // Un payload non JSON viene riportato così com'è
+        let error = AuthError::new(AuthMethod::OauthBearer, &rejected, Some(b"token scaduto\n"));
+        assert_eq!(error.status, None);
+        assert_eq!(error.payload.as_deref(), Some("token scaduto"));
+        assert!(error.to_string().ends_with("(token scaduto)"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn logindisabled_server_authenticates_with_plain() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 LOGINDISABLED AUTH=PLAIN".to_string()]),
+            ("AUTHENTICATE PLAIN", vec!["+ ".to_string()]),
+        ])
+        .await;
+
+        let session = create_imap_session(&server.account(), "segreto").await;
+        assert!(session.is_ok(), "{:?}", session.err());
+        assert_eq!(
+            server.commands(),
+            vec![
+                "CAPABILITY".to_string(),
+                "AUTHENTICATE PLAIN".to_string(),
+                BASE64_STANDARD.encode("\0test@example.com\0segreto"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_mechanism_is_retried_on_a_new_connection() {
+        let error = BASE64_STANDARD.encode(r#"{"status":"401","scope":"https://mail.google.com/"}"#);
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 AUTH=XOAUTH2 AUTH=OAUTHBEARER".to_string()]),
+            ("AUTHENTICATE XOAUTH2", vec![
+                "+ ".to_string(),
+                format!("+ {}", error),
+                "$tag NO [AUTHENTICATIONFAILED] Invalid credentials".to_string(),
+            ]),
+            ("AUTHENTICATE OAUTHBEARER", vec!["+ ".to_string()]),
+        ])
+        .await;
+
+        let mut account = server.account();
+        account.auth = AuthMethod::Xoauth2;
+        let session = create_imap_session(&account, "token").await;
+        assert!(session.is_ok(), "{:?}", session.err());
+
+        // Dopo il rifiuto la risposta vuota chiude lo scambio SASL, poi si riparte da CAPABILITY
+        let commands = server.commands();
+        assert_eq!(commands[..4], [
+            "CAPABILITY".to_string(),
+            "AUTHENTICATE XOAUTH2".to_string(),
+            BASE64_STANDARD.encode("user=test@example.com\x01auth=Bearer token\x01\x01"),
+            String::new(),
+        ]);
+        assert_eq!(commands[4..6], ["CAPABILITY".to_string(), "AUTHENTICATE OAUTHBEARER".to_string()]);
+        assert_eq!(commands.len(), 7);
+    }
+
+    #[tokio::test]
+    async fn every_rejection_is_reported_with_its_payload() {
+        let error = BASE64_STANDARD.encode(r#"{"status":"invalid_token","scope":"mail"}"#);
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 AUTH=XOAUTH2 AUTH=OAUTHBEARER".to_string()]),
+            ("AUTHENTICATE XOAUTH2", vec!["$tag NO token non valido".to_string()]),
+            ("AUTHENTICATE OAUTHBEARER", vec![
+                "+ ".to_string(),
+                format!("+ {}", error),
+                "$tag NO [AUTHENTICATIONFAILED] accesso negato".to_string(),
+            ]),
+        ])
+        .await;
+
+        let mut account = server.account();
+        account.auth = AuthMethod::OauthBearer;
+        let Err(SessionError::Authentication(failures)) = create_imap_session(&account, "token").await else {
+            panic!("l'autenticazione doveva essere rifiutata");
+        };
+
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].method, AuthMethod::OauthBearer);
+        assert_eq!(failures[0].message, "[AUTHENTICATIONFAILED] accesso negato");
+        assert_eq!(failures[0].status.as_deref(), Some("invalid_token"));
+        assert_eq!(failures[0].scope.as_deref(), Some("mail"));
+        assert_eq!(failures[1].method, AuthMethod::Xoauth2);
+        assert_eq!(failures[1].message, "token non valido");
+        assert_eq!(failures[1].payload, None);
+    }
+}
@@ -0,0 +1,655 @@
+use serde::{Deserialize, Serialize};
+
+use super::account::AccountManager;
+use super::address::EmailAddress;
+use super::attachments::download_part;
+use super::imap::{fetch_body, fetch_one, parse_fetched_message, uid_store, MailMessage, MessageBody, MESSAGE_FETCH_QUERY};
+use super::pool::ImapPool;
+use super::smtp::{Attachment, ComposeMessage};
+use super::threading::{parse_message_ids, strip_subject_prefixes};
+
+/// Numero massimo di Message-ID nell'header References: oltre si tengono il primo e gli ultimi
+const MAX_REFERENCES: usize = 20;
+
+/// Tipo di messaggio composto a partire da un originale
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComposeKind {
+    Reply,
+    ReplyAll,
+    Forward,
+}
+
+/// Riferimento al messaggio a cui si risponde o che si inoltra
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OriginalMessage {
+    pub kind: ComposeKind,
+    pub folder_path: String,
+    pub uid: u32,
+    /// Inoltra il messaggio intero come allegato message/rfc822 invece che nel corpo
+    #[serde(default)]
+    pub forward_as_attachment: bool,
+}
+
+/// Messaggio originale letto dal server
+pub struct LoadedOriginal {
+    message: MailMessage,
+    body: MessageBody,
+    /// Allegati originali (inoltro) o il messaggio intero come .eml
+    attachments: Vec<Attachment>,
+}
+
+/// Legge dal server intestazioni, corpo ed eventuali allegati del messaggio originale
+pub async fn load_original(
+    pool: &ImapPool,
+    accounts: &AccountManager,
+    account_id: &str,
+    original: &OriginalMessage,
+) -> Result<LoadedOriginal, String> {
+    println!("[SMTP] Lettura messaggio originale {} in {}", original.uid, original.folder_path);
+
+    let mut conn = pool.acquire(accounts, account_id).await?;
+    conn.select(&original.folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+
+    let folder_id = format!("{}-{}", account_id, original.folder_path);
+    let fetched = fetch_one(&mut conn, original.uid, MESSAGE_FETCH_QUERY).await?;
+    let message = parse_fetched_message(account_id, &folder_id, &fetched)
+        .ok_or_else(|| format!("Messaggio originale {} non leggibile", original.uid))?;
+    let body = fetch_body(&mut conn, original.uid).await?;
+
+    let mut attachments = Vec::new();
+    if original.kind == ComposeKind::Forward {
+        if original.forward_as_attachment {
+            let raw = fetch_one(&mut conn, original.uid, "(UID BODY.PEEK[])").await?;
+            let content = raw
+                .body()
+                .ok_or_else(|| format!("Contenuto del messaggio {} non ricevuto", original.uid))?
+                .to_vec();
+            attachments.push(Attachment {
+                filename: format!("{}.eml", sanitize_filename(&message.subject)),
+                content_type: "message/rfc822".to_string(),
+                content,
+            });
+        } else {
+            for attachment in &message.attachments {
+                let content = download_part(&mut conn, &original.folder_path, original.uid, &attachment.part).await?;
+                attachments.push(Attachment {
+                    filename: attachment.filename.clone().unwrap_or_else(|| "allegato".to_string()),
+                    content_type: attachment.mime_type.clone(),
+                    content,
+                });
+            }
+        }
+    }
+
+    Ok(LoadedOriginal { message, body, attachments })
+}
+
+/// Completa il messaggio da inviare: oggetto, destinatari (se non indicati),
+/// In-Reply-To/References, corpo citato e allegati inoltrati
+pub fn prepare_message(message: &mut ComposeMessage, original: &LoadedOriginal, own_email: &str, kind: ComposeKind) {
+    let source = &original.message;
+
+    // Oggetto: un solo prefisso "Re:" o "Fwd:"
+    let base = if message.subject.trim().is_empty() { &source.subject } else { &message.subject };
+    let prefix = if kind == ComposeKind::Forward { "Fwd" } else { "Re" };
+    message.subject = format!("{}: {}", prefix, strip_subject_prefixes(base).0);
+
+    if kind != ComposeKind::Forward {
+        if message.to.is_empty() {
+            let (to, cc) = reply_recipients(source, own_email, kind == ComposeKind::ReplyAll);
+            message.to = to;
+            if !cc.is_empty() && message.cc.is_none() {
+                message.cc = Some(cc);
+            }
+        }
+        // Senza un Message-ID reale dell'originale le intestazioni di threading restano vuote
+        let message_id = original_message_id(source);
+        if message.in_reply_to.is_none() {
+            message.in_reply_to = message_id.clone();
+        }
+        if message.references.is_none() {
+            message.references = Some(reply_references(source, message_id)).filter(|r| !r.is_empty());
+        }
+    }
+
+    let (text, html) = match kind {
+        ComposeKind::Forward if original.attachments.iter().any(|a| a.content_type == "message/rfc822") => {
+            (message.body_text.clone(), message.body_html.clone())
+        }
+        ComposeKind::Forward => forward_bodies(message, source, &original.body),
+        _ => reply_bodies(message, source, &original.body),
+    };
+    message.body_text = text;
+    message.body_html = html;
+
+    message
+        .attachments
+        .get_or_insert_with(Vec::new)
+        .extend(original.attachments.iter().cloned());
+}
+
+/// Flag da impostare sul messaggio originale dopo l'invio
+pub async fn mark_original(
+    pool: &ImapPool,
+    accounts: &AccountManager,
+    account_id: &str,
+    original: &OriginalMessage,
+) -> Result<(), String> {
+    let flag = match original.kind {
+        ComposeKind::Forward => "+FLAGS.SILENT ($Forwarded)",
+        _ => "+FLAGS.SILENT (\\Answered)",
+    };
+
+    let mut conn = pool.acquire(accounts, account_id).await?;
+    conn.select(&original.folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    uid_store(&mut conn, &original.uid.to_string(), flag)
+        .await
+        .map_err(|e| format!("Errore nel UID STORE: {}", e))
+}
+
+/// Destinatari di una risposta: Reply-To (o From) e, per "rispondi a tutti",
+/// gli altri destinatari in Cc, escluso l'account che risponde e chi è già in To
+fn reply_recipients(source: &MailMessage, own_email: &str, reply_all: bool) -> (Vec<String>, Vec<String>) {
+    let to: Vec<EmailAddress> = match &source.reply_to {
+        Some(reply_to) if !reply_to.is_empty() => reply_to.clone(),
+        // Risposta a un proprio messaggio: si scrive ai destinatari originali
+        _ if source.from_address.eq_ignore_ascii_case(own_email) => source.to_addresses.clone(),
+        _ => vec![EmailAddress {
+            name: source.from_name.clone(),
+            address: source.from_address.clone(),
+            group: None,
+        }],
+    };
+
+    let mut cc = Vec::new();
+    if reply_all {
+        let mut seen: Vec<String> = vec![own_email.to_lowercase(), source.from_address.to_lowercase()];
+        seen.extend(to.iter().map(|address| address.address.to_lowercase()));
+        for address in source.to_addresses.iter().chain(source.cc_addresses.iter().flatten()) {
+            let key = address.address.to_lowercase();
+            if !seen.contains(&key) {
+                seen.push(key);
+                cc.push(format_address(address));
+            }
+        }
+    }
+
+    (to.iter().map(format_address).collect(), cc)
+}
+
+/// Message-ID dell'originale da usare in In-Reply-To/References. parse_fetched_message
+/// assegna un identificativo sintetico ("msg-<uid>") ai messaggi senza Message-ID:
+/// solo un id nella forma `<...>` identifica davvero il messaggio per gli altri client.
+fn original_message_id(source: &MailMessage) -> Option<String> {
+    parse_message_ids(&source.message_id).into_iter().next()
+}
+
+/// References della risposta: quelli dell'originale più il suo Message-ID (RFC 5322, 3.6.4)
+fn reply_references(source: &MailMessage, message_id: Option<String>) -> Vec<String> {
+    let mut references = source.references.clone().unwrap_or_default();
+    if let Some(in_reply_to) = &source.in_reply_to {
+        if references.is_empty() {
+            references.push(in_reply_to.clone());
+        }
+    }
+    references.extend(message_id);
+
+    if references.len() > MAX_REFERENCES {
+        let tail = references.split_off(references.len() - (MAX_REFERENCES - 1));
+        references.truncate(1);
+        references.extend(tail);
+    }
+    references
+}
+
+/// Corpo della risposta con il testo originale citato ("> ") e in <blockquote>
+fn reply_bodies(message: &ComposeMessage, source: &MailMessage, body: &MessageBody) -> (Option<String>, Option<String>) {
+    let attribution = format!("Il {}, {} ha scritto:", format_date(source.date), sender(source));
+    let original_text = original_text(body);
+
+    let quoted: String = original_text
+        .lines()
+        .map(|line| if line.starts_with('>') { format!(">{}", line) } else { format!("> {}", line) })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = format!(
+        "{}\n\n{}\n{}\n",
+        message.body_text.as_deref().unwrap_or(""),
+        attribution,
+        quoted
+    );
+
+    let html = (message.body_html.is_some() || body.html.is_some()).then(|| {
+        format!(
+            "{}<br><div>{}</div><blockquote type=\"cite\" style=\"margin:0 0 0 .8ex;border-left:1px solid #ccc;padding-left:1ex\">{}</blockquote>",
+            user_html(message),
+            escape_html(&attribution),
+            original_html(body)
+        )
+    });
+
+    (Some(text), html)
+}
+
+/// Corpo dell'inoltro con le intestazioni del messaggio originale
+fn forward_bodies(message: &ComposeMessage, source: &MailMessage, body: &MessageBody) -> (Option<String>, Option<String>) {
+    let mut headers = vec![
+        ("Da", sender(source)),
+        ("Data", format_date(source.date)),
+        ("Oggetto", source.subject.clone()),
+        ("A", source.to_addresses.iter().map(format_address).collect::<Vec<_>>().join(", ")),
+    ];
+    if let Some(cc) = source.cc_addresses.as_ref().filter(|cc| !cc.is_empty()) {
+        headers.push(("Cc", cc.iter().map(format_address).collect::<Vec<_>>().join(", ")));
+    }
+
+    let separator = "---------- Messaggio inoltrato ----------";
+    let header_text: String = headers.iter().map(|(name, value)| format!("{}: {}\n", name, value)).collect();
+    let text = format!(
+        "{}\n\n{}\n{}\n{}",
+        message.body_text.as_deref().unwrap_or(""),
+        separator,
+        header_text,
+        original_text(body)
+    );
+
+    let html = (message.body_html.is_some() || body.html.is_some()).then(|| {
+        let header_html: String = headers
+            .iter()
+            .map(|(name, value)| format!("<b>{}:</b> {}<br>", name, escape_html(value)))
+            .collect();
+        format!(
+            "{}<br><div>{}<br>{}</div><br>{}",
+            user_html(message),
+            separator,
+            header_html,
+            original_html(body)
+        )
+    });
+
+    (Some(text), html)
+}
+
+/// Testo dell'utente in HTML: quello scritto dal frontend o il testo semplice convertito
+fn user_html(message: &ComposeMessage) -> String {
+    message
+        .body_html
+        .clone()
+        .unwrap_or_else(|| text_to_html(message.body_text.as_deref().unwrap_or("")))
+}
+
+fn original_text(body: &MessageBody) -> String {
+    body.text
+        .clone()
+        .or_else(|| body.html.as_deref().map(html_to_text))
+        .unwrap_or_default()
+}
+
+/// HTML dell'originale da citare, senza contenuti attivi né stili che si applicherebbero
+/// all'intero messaggio di risposta
+fn original_html(body: &MessageBody) -> String {
+    body.html
+        .as_deref()
+        .map(sanitize_html)
+        .unwrap_or_else(|| text_to_html(body.text.as_deref().unwrap_or("")))
+}
+
+fn sender(source: &MailMessage) -> String {
+    match &source.from_name {
+        Some(name) => format!("{} <{}>", name, source.from_address),
+        None => source.from_address.clone(),
+    }
+}
+
+/// Indirizzo nel formato accettato da lettre ("Nome <indirizzo>")
+fn format_address(address: &EmailAddress) -> String {
+    match &address.name {
+        Some(name) => format!("\"{}\" <{}>", name.replace('"', "'"), address.address),
+        None => address.address.clone(),
+    }
+}
+
+fn format_date(timestamp_millis: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp_millis)
+        .map(|date| date.with_timezone(&chrono::Local).format("%d/%m/%Y %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn text_to_html(text: &str) -> String {
+    escape_html(text).replace("\r\n", "\n").replace('\n', "<br>")
+}
+
+/// Conversione minima da HTML a testo per citare messaggi senza parte text/plain
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    let mut tag = String::new();
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag.trim_start_matches('/').split_whitespace().next().unwrap_or("").to_lowercase();
+                if matches!(name.as_str(), "br" | "br/" | "p" | "div" | "tr" | "li") {
+                    text.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => text.push(c),
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// Elementi rimossi insieme al contenuto dall'HTML citato
+const REMOVED_ELEMENTS: [&str; 6] = ["head", "script", "style", "iframe", "object", "embed"];
+
+/// Tag rimossi mantenendo il contenuto: l'involucro del documento e i riferimenti esterni
+const REMOVED_TAGS: [&str; 5] = ["html", "body", "meta", "link", "base"];
+
+/// Attributi che contengono un URL, da scartare se usano uno schema eseguibile
+const URL_ATTRIBUTES: [&str; 5] = ["href", "src", "action", "formaction", "xlink:href"];
+
+/// Ripulisce l'HTML del messaggio originale prima di inserirlo nella citazione:
+/// rimuove script, stili, frame e commenti, gli attributi on* e gli URL javascript:
+fn sanitize_html(html: &str) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|end| &comment[end + 3..]).unwrap_or("");
+            continue;
+        }
+        // Un "<" che non apre un tag è testo
+        if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?') {
+            result.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        }
+        // Un tag non chiuso viene scartato insieme al resto del documento
+        let Some(end) = tag_end(rest) else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[..end + 1];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with("</");
+        let name = tag_name(tag);
+        if tag.starts_with("<!") || tag.starts_with("<?") || REMOVED_TAGS.contains(&name.as_str()) {
+            continue;
+        }
+        if REMOVED_ELEMENTS.contains(&name.as_str()) {
+            if !closing && !tag.ends_with("/>") {
+                rest = skip_element(rest, &name);
+            }
+            continue;
+        }
+        if closing {
+            result.push_str(tag);
+        } else {
+            push_clean_tag(&mut result, tag, &name);
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Posizione del ">" che chiude il tag, ignorando quelli dentro i valori tra virgolette
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Nome del tag in minuscolo (es. "script" per `<SCRIPT type=...>` o `</script>`)
+fn tag_name(tag: &str) -> String {
+    tag.trim_start_matches('<')
+        .trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// Testo successivo al tag di chiusura dell'elemento; se manca, l'elemento arriva fino alla fine
+fn skip_element<'a>(html: &'a str, name: &str) -> &'a str {
+    let lower = html.to_ascii_lowercase();
+    let closing = format!("</{}", name);
+    let mut from = 0;
+    while let Some(position) = lower[from..].find(&closing) {
+        let after = from + position + closing.len();
+        if lower[after..].starts_with(|c: char| c.is_whitespace() || c == '>') {
+            return match tag_end(&html[after..]) {
+                Some(end) => &html[after + end + 1..],
+                None => "",
+            };
+        }
+        from = after;
+    }
+    ""
+}
+
+/// Copia un tag di apertura senza gli attributi che eseguono codice
+fn push_clean_tag(result: &mut String, tag: &str, name: &str) {
+    let inner = tag[1..tag.len() - 1].trim_end();
+    let (inner, self_closing) = match inner.strip_suffix('/') {
+        Some(inner) => (inner, true),
+        None => (inner, false),
+    };
+    let mut rest = inner[name.len()..].trim_start();
+
+    result.push('<');
+    result.push_str(&inner[..name.len()]);
+    while !rest.is_empty() {
+        let name_end = rest.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(rest.len());
+        let attribute = rest[..name_end].to_ascii_lowercase();
+        let mut end = name_end;
+        let mut value = "";
+        let after_name = rest[name_end..].trim_start();
+        if let Some(after_equals) = after_name.strip_prefix('=') {
+            let value_start = rest.len() - after_equals.trim_start().len();
+            let raw = &rest[value_start..];
+            end = match raw.chars().next() {
+                Some(quote @ ('"' | '\'')) => raw[1..].find(quote).map(|i| value_start + i + 2).unwrap_or(rest.len()),
+                _ => value_start + raw.find(char::is_whitespace).unwrap_or(raw.len()),
+            };
+            value = rest[value_start..end].trim_matches(|c| c == '"' || c == '\'');
+        }
+
+        let scheme: String = value.chars().filter(|c| !c.is_whitespace() && !c.is_control()).collect();
+        let scheme = scheme.to_ascii_lowercase();
+        let executable_url = URL_ATTRIBUTES.contains(&attribute.as_str())
+            && (scheme.starts_with("javascript:") || scheme.starts_with("vbscript:"));
+        if !attribute.starts_with("on") && !executable_url {
+            result.push(' ');
+            result.push_str(&rest[..end]);
+        }
+        rest = rest[end..].trim_start();
+    }
+    result.push_str(if self_closing { "/>" } else { ">" });
+}
+
+/// Nome di file sicuro a partire dall'oggetto del messaggio
+fn sanitize_filename(subject: &str) -> String {
+    let name: String = subject
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control() { '_' } else { c })
+        .collect();
+    let name = name.trim();
+    if name.is_empty() { "messaggio".to_string() } else { name.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(address: &str) -> EmailAddress {
+        EmailAddress { name: None, address: address.to_string(), group: None }
+    }
+
+    fn original(from: &str, reply_to: &[&str], to: &[&str], cc: &[&str]) -> MailMessage {
+        let list = |addresses: &[&str]| addresses.iter().map(|a| address(a)).collect::<Vec<_>>();
+        MailMessage {
+            id: "account-msg-42".to_string(),
+            account_id: "account".to_string(),
+            folder_id: "account-INBOX".to_string(),
+            uid: 42,
+            message_id: "<originale@example.com>".to_string(),
+            subject: "Riunione".to_string(),
+            from_name: None,
+            from_address: from.to_string(),
+            to_addresses: list(to),
+            cc_addresses: Some(list(cc)).filter(|cc| !cc.is_empty()),
+            bcc_addresses: None,
+            reply_to: Some(list(reply_to)).filter(|r| !r.is_empty()),
+            sender: None,
+            date: 0,
+            size: None,
+            attachments: Vec::new(),
+            text: None,
+            html: None,
+            flags: Vec::new(),
+            keywords: Vec::new(),
+            is_read: true,
+            is_starred: false,
+            is_important: false,
+            thread_id: None,
+            in_reply_to: None,
+            references: None,
+            synced_at: 0,
+        }
+    }
+
+    fn reply(message: MailMessage, html: Option<&str>) -> ComposeMessage {
+        let loaded = LoadedOriginal {
+            message,
+            body: MessageBody { uid: 42, text: Some("Testo originale".to_string()), html: html.map(str::to_string) },
+            attachments: Vec::new(),
+        };
+        let mut compose = ComposeMessage {
+            to: Vec::new(),
+            cc: None,
+            bcc: None,
+            subject: String::new(),
+            body_html: None,
+            body_text: Some("Risposta".to_string()),
+            attachments: None,
+            in_reply_to: None,
+            references: None,
+            original: None,
+        };
+        prepare_message(&mut compose, &loaded, "io@example.com", ComposeKind::ReplyAll);
+        compose
+    }
+
+    #[test]
+    fn reply_all_does_not_copy_reply_to_addresses_in_cc() {
+        let source = original(
+            "autore@example.com",
+            &["lista@example.com"],
+            &["io@example.com", "Lista@example.com", "bob@example.com"],
+            &["carol@example.com", "autore@example.com"],
+        );
+
+        let (to, cc) = reply_recipients(&source, "IO@example.com", true);
+        assert_eq!(to, vec!["lista@example.com"]);
+        assert_eq!(cc, vec!["bob@example.com", "carol@example.com"]);
+    }
+
+    #[test]
+    fn reply_all_to_own_message_does_not_repeat_to_in_cc() {
+        let source = original("io@example.com", &[], &["bob@example.com"], &["carol@example.com"]);
+
+        let (to, cc) = reply_recipients(&source, "io@example.com", true);
+        assert_eq!(to, vec!["bob@example.com"]);
+        assert_eq!(cc, vec!["carol@example.com"]);
+    }
+
+    #[test]
+    fn threading_headers_use_the_real_message_id() {
+        let mut source = original("autore@example.com", &[], &["io@example.com"], &[]);
+        source.in_reply_to = Some("<primo@example.com>".to_string());
+        source.references = Some(vec!["<radice@example.com>".to_string(), "<primo@example.com>".to_string()]);
+
+        let compose = reply(source, None);
+        assert_eq!(compose.subject, "Re: Riunione");
+        assert_eq!(compose.in_reply_to.as_deref(), Some("<originale@example.com>"));
+        assert_eq!(
+            compose.references,
+            Some(vec![
+                "<radice@example.com>".to_string(),
+                "<primo@example.com>".to_string(),
+                "<originale@example.com>".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn threading_headers_skip_synthetic_message_id() {
+        // Messaggio senza Message-ID: parse_fetched_message assegna "msg-<uid>"
+        let mut source = original("autore@example.com", &[], &["io@example.com"], &[]);
+        source.message_id = "msg-42".to_string();
+        let compose = reply(source, None);
+        assert_eq!(compose.in_reply_to, None);
+        assert_eq!(compose.references, None);
+
+        // I riferimenti già presenti nell'originale restano validi
+        let mut source = original("autore@example.com", &[], &["io@example.com"], &[]);
+        source.message_id = "msg-42".to_string();
+        source.in_reply_to = Some("<primo@example.com>".to_string());
+        let compose = reply(source, None);
+        assert_eq!(compose.in_reply_to, None);
+        assert_eq!(compose.references, Some(vec!["<primo@example.com>".to_string()]));
+    }
+
+    #[test]
+    fn quoted_html_is_sanitized() {
+        let html = r#"<!DOCTYPE html><html><head><title>T</title><style>body { display: none }</style>
+<script src="x.js"></script></head><body onload="rubaDati()"><!-- <script>nascosto()</script> -->
+<p class="testo" onclick='alert(1)'>Ciao <b>Anna</b> &amp; 3 < 4</p>
+<SCRIPT type="text/javascript">document.write("<p>")</SCRIPT >
+<a href=" javascript:alert(1)" title="link">esca</a> <a href="https://example.com/?a=1&b=2">sito</a>
+<img src="cid:logo@example.com" alt="a > b" ONERROR=alert(1) /><iframe src="https://example.com"></iframe>
+</body></html>"#;
+
+        let compose = reply(original("autore@example.com", &[], &["io@example.com"], &[]), Some(html));
+        let quoted = compose.body_html.expect("corpo HTML");
+        let lower = quoted.to_ascii_lowercase();
+        for forbidden in ["<script", "<style", "<iframe", "<head", "<html", "<body", "<!--", "onload", "onclick", "onerror", "javascript:", "nascosto", "display: none"] {
+            assert!(!lower.contains(forbidden), "{} in {}", forbidden, quoted);
+        }
+        assert!(quoted.contains(r#"<p class="testo">Ciao <b>Anna</b> &amp; 3 &lt; 4</p>"#), "{}", quoted);
+        assert!(quoted.contains(r#"<a title="link">esca</a> <a href="https://example.com/?a=1&b=2">sito</a>"#), "{}", quoted);
+        assert!(quoted.contains(r#"<img src="cid:logo@example.com" alt="a > b"/>"#), "{}", quoted);
+    }
+}
@@ -0,0 +1,672 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+
+use super::account::{AccountConfig, AuthMethod, Security, ServerConfig};
+use super::imap::probe_capabilities;
+use super::smtp::probe_smtp;
+
+/// Tempo massimo per ogni richiesta HTTP e per ogni verifica di connessione
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Origine di una configurazione, in ordine di affidabilità
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscoverySource {
+    /// File autoconfig pubblicato dal dominio (formato Thunderbird)
+    Autoconfig,
+    /// Database ISPDB di Thunderbird, per il dominio o per quello dei suoi MX
+    Ispdb,
+    /// Autodiscover JSON di Microsoft
+    Autodiscover,
+    /// Record SRV (RFC 6186 e RFC 8314)
+    Srv,
+    /// Nomi host comuni (imap.dominio, mail.dominio, ...)
+    Guess,
+}
+
+/// Configurazione proposta al frontend, già verificata con una connessione di prova
+#[derive(Debug, Serialize)]
+pub struct DiscoveredSettings {
+    pub config: AccountConfig,
+    pub source: DiscoverySource,
+    /// Il server IMAP ha risposto a CAPABILITY con la cifratura indicata
+    pub imap_verified: bool,
+    /// Il server SMTP ha risposto con la cifratura indicata
+    pub smtp_verified: bool,
+    pub imap_capabilities: Vec<String>,
+}
+
+/// Endpoint interrogati dalla discovery. I template accettano `{domain}`, `{email}` e,
+/// per autodiscover, `{protocol}`: puntandoli a un server HTTP locale e impostando
+/// `dns_server` la discovery si può provare senza rete.
+#[derive(Debug, Clone)]
+pub struct DiscoveryEndpoints {
+    pub autoconfig_urls: Vec<String>,
+    pub ispdb_url: String,
+    pub autodiscover_urls: Vec<String>,
+    /// Server DNS da usare al posto di quello di sistema
+    pub dns_server: Option<SocketAddr>,
+}
+
+impl Default for DiscoveryEndpoints {
+    fn default() -> Self {
+        DiscoveryEndpoints {
+            autoconfig_urls: vec![
+                "https://autoconfig.{domain}/mail/config-v1.1.xml?emailaddress={email}".to_string(),
+                "https://{domain}/.well-known/autoconfig/mail/config-v1.1.xml?emailaddress={email}".to_string(),
+            ],
+            ispdb_url: "https://autoconfig.thunderbird.net/v1.1/{domain}".to_string(),
+            autodiscover_urls: vec![
+                "https://autodiscover.{domain}/autodiscover/autodiscover.json/v1.0/{email}?Protocol={protocol}"
+                    .to_string(),
+            ],
+            dns_server: None,
+        }
+    }
+}
+
+/// Server suggeriti da una singola fonte, in ordine di preferenza
+struct Candidate {
+    source: DiscoverySource,
+    imap: Vec<ServerConfig>,
+    smtp: Vec<ServerConfig>,
+    /// La fonte indica l'autenticazione OAuth2
+    oauth: bool,
+    username: Option<String>,
+}
+
+impl Candidate {
+    fn new(source: DiscoverySource) -> Self {
+        Candidate {
+            source,
+            imap: Vec::new(),
+            smtp: Vec::new(),
+            oauth: false,
+            username: None,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        !self.imap.is_empty() && !self.smtp.is_empty()
+    }
+}
+
+/// Risposta di Microsoft autodiscover v2 per un protocollo
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AutodiscoverResponse {
+    url: String,
+}
+
+/// Cerca le impostazioni IMAP/SMTP di un indirizzo email.
+/// Le configurazioni sono ordinate dalla più affidabile: prima quelle verificate,
+/// poi per fonte e infine per cifratura (TLS implicito prima di STARTTLS).
+#[tauri::command]
+pub async fn discover_account_settings(email: String) -> Result<Vec<DiscoveredSettings>, String> {
+    println!("[DISCOVERY] Ricerca configurazione per: {}", email);
+    discover(&email, &DiscoveryEndpoints::default()).await
+}
+
+async fn discover(email: &str, endpoints: &DiscoveryEndpoints) -> Result<Vec<DiscoveredSettings>, String> {
+    let email = email.trim();
+    let (local_part, domain) = email
+        .rsplit_once('@')
+        .filter(|(local, domain)| !local.is_empty() && is_valid_domain(domain))
+        .ok_or_else(|| format!("Indirizzo email non valido: {}", email))?;
+    let domain = domain.to_ascii_lowercase();
+
+    let http = reqwest::Client::builder()
+        .timeout(DISCOVERY_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Errore nella creazione del client HTTP: {}", e))?;
+    let resolver = match endpoints.dns_server {
+        Some(server) => TokioAsyncResolver::tokio(
+            ResolverConfig::from_parts(
+                None,
+                Vec::new(),
+                NameServerConfigGroup::from_ips_clear(&[server.ip()], server.port(), true),
+            ),
+            ResolverOpts::default(),
+        ),
+        None => TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(|e| format!("Errore nella configurazione DNS: {}", e))?,
+    };
+    let placeholders = Placeholders { email, local_part, domain: &domain };
+
+    let (autoconfig, ispdb, autodiscover, srv) = tokio::join!(
+        autoconfig_candidate(&http, endpoints, &placeholders),
+        ispdb_candidate(&http, &resolver, endpoints, &placeholders),
+        autodiscover_candidate(&http, endpoints, &placeholders),
+        srv_candidate(&resolver, &domain),
+    );
+    let mut candidates: Vec<Candidate> = [autoconfig, ispdb, autodiscover, srv]
+        .into_iter()
+        .flatten()
+        .filter(Candidate::is_complete)
+        .collect();
+
+    // I nomi host comuni si provano solo se nessuna fonte ha dato una configurazione completa
+    if candidates.is_empty() {
+        println!("[DISCOVERY] Nessuna configurazione pubblicata per {}, provo i nomi host comuni", domain);
+        candidates.push(guess_candidate(&domain));
+    }
+
+    let settings = probe_candidates(email, candidates).await;
+    println!("[DISCOVERY] {} configurazioni trovate per {}", settings.len(), email);
+    Ok(settings)
+}
+
+/// Valori con cui completare i template degli URL e i segnaposto dell'autoconfig
+struct Placeholders<'a> {
+    email: &'a str,
+    local_part: &'a str,
+    domain: &'a str,
+}
+
+impl Placeholders<'_> {
+    fn url(&self, template: &str, domain: &str) -> String {
+        template
+            .replace("{domain}", domain)
+            .replace("{email}", &percent_encode(self.email))
+    }
+
+    /// Segnaposto del formato autoconfig (%EMAILADDRESS%, %EMAILLOCALPART%, %EMAILDOMAIN%)
+    fn expand(&self, value: &str) -> String {
+        value
+            .replace("%EMAILADDRESS%", self.email)
+            .replace("%EMAILLOCALPART%", self.local_part)
+            .replace("%EMAILDOMAIN%", self.domain)
+    }
+}
+
+/// Autoconfig pubblicato dal dominio stesso: vale il primo URL che risponde
+async fn autoconfig_candidate(
+    http: &reqwest::Client,
+    endpoints: &DiscoveryEndpoints,
+    placeholders: &Placeholders<'_>,
+) -> Option<Candidate> {
+    for template in &endpoints.autoconfig_urls {
+        let url = placeholders.url(template, placeholders.domain);
+        if let Some(xml) = fetch_text(http, &url).await {
+            if let Some(candidate) = parse_autoconfig(&xml, DiscoverySource::Autoconfig, placeholders) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+/// ISPDB per il dominio e, se diverso, per il dominio del server MX
+/// (copre i domini personalizzati ospitati da Google Workspace, Microsoft 365, ...)
+async fn ispdb_candidate(
+    http: &reqwest::Client,
+    resolver: &TokioAsyncResolver,
+    endpoints: &DiscoveryEndpoints,
+    placeholders: &Placeholders<'_>,
+) -> Option<Candidate> {
+    let mut domains = vec![placeholders.domain.to_string()];
+    if let Some(mx_domain) = mx_domain(resolver, placeholders.domain).await {
+        if !domains.contains(&mx_domain) {
+            domains.push(mx_domain);
+        }
+    }
+
+    for domain in domains {
+        let url = placeholders.url(&endpoints.ispdb_url, &domain);
+        if let Some(xml) = fetch_text(http, &url).await {
+            if let Some(candidate) = parse_autoconfig(&xml, DiscoverySource::Ispdb, placeholders) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+/// Dominio del server MX con priorità più alta, ridotto agli ultimi due livelli
+/// (es. "aspmx.l.google.com" -> "google.com"). È un'approssimazione: non conosce i suffissi
+/// pubblici composti come "co.uk".
+async fn mx_domain(resolver: &TokioAsyncResolver, domain: &str) -> Option<String> {
+    let lookup = resolver.mx_lookup(format!("{}.", domain)).await.ok()?;
+    let exchange = lookup.iter().min_by_key(|mx| mx.preference())?.exchange().to_utf8();
+    let labels: Vec<&str> = exchange.trim_end_matches('.').split('.').collect();
+    (labels.len() >= 2).then(|| labels[labels.len() - 2..].join(".").to_ascii_lowercase())
+}
+
+/// Legge incomingServer IMAP e outgoingServer SMTP da un documento autoconfig v1.1
+fn parse_autoconfig(xml: &str, source: DiscoverySource, placeholders: &Placeholders<'_>) -> Option<Candidate> {
+    let document = match Document::parse(xml) {
+        Ok(document) => document,
+        Err(e) => {
+            println!("[DISCOVERY] XML autoconfig non valido: {}", e);
+            return None;
+        }
+    };
+    let provider = document.descendants().find(|n| n.has_tag_name("emailProvider"))?;
+
+    let mut candidate = Candidate::new(source);
+    for server in provider.children().filter(Node::is_element) {
+        let servers = match (server.tag_name().name(), server.attribute("type")) {
+            ("incomingServer", Some("imap")) => &mut candidate.imap,
+            ("outgoingServer", Some("smtp")) => &mut candidate.smtp,
+            _ => continue,
+        };
+        let security = match child_text(server, "socketType") {
+            Some("SSL") => Security::Tls,
+            Some("STARTTLS") => Security::StartTls,
+            Some("plain") => Security::Plain,
+            _ => continue,
+        };
+        let (Some(host), Some(port)) = (
+            child_text(server, "hostname"),
+            child_text(server, "port").and_then(|p| p.parse().ok()),
+        ) else {
+            continue;
+        };
+
+        if server
+            .children()
+            .filter(|n| n.has_tag_name("authentication"))
+            .any(|n| n.text().map(str::trim) == Some("OAuth2"))
+        {
+            candidate.oauth = true;
+        }
+        if candidate.username.is_none() {
+            candidate.username = child_text(server, "username").map(|u| placeholders.expand(u));
+        }
+        servers.push(ServerConfig {
+            host: placeholders.expand(host),
+            port,
+            security,
+        });
+    }
+    Some(candidate)
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|n| n.has_tag_name(name))
+        .and_then(|n| n.text())
+        .map(str::trim)
+}
+
+/// Microsoft autodiscover v2: un URL per IMAP e uno per SMTP.
+/// Le caselle Exchange Online accettano solo OAuth2.
+async fn autodiscover_candidate(
+    http: &reqwest::Client,
+    endpoints: &DiscoveryEndpoints,
+    placeholders: &Placeholders<'_>,
+) -> Option<Candidate> {
+    for template in &endpoints.autodiscover_urls {
+        let mut candidate = Candidate::new(DiscoverySource::Autodiscover);
+        candidate.oauth = true;
+        for (protocol, servers) in [("IMAP", &mut candidate.imap), ("SMTP", &mut candidate.smtp)] {
+            let url = placeholders.url(&template.replace("{protocol}", protocol), placeholders.domain);
+            let Some(body) = fetch_text(http, &url).await else {
+                continue;
+            };
+            match serde_json::from_str::<AutodiscoverResponse>(&body) {
+                Ok(response) => servers.extend(autodiscover_server(&response.url, protocol)),
+                Err(e) => println!("[DISCOVERY] Risposta autodiscover non valida da {}: {}", url, e),
+            }
+        }
+        if candidate.is_complete() {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+/// Interpreta l'URL restituito da autodiscover ("host", "host:porta" o con schema).
+/// Senza porta si usano 993 (IMAP, TLS) e 587 (SMTP, STARTTLS).
+fn autodiscover_server(url: &str, protocol: &str) -> Option<ServerConfig> {
+    let address = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let address = address.split('/').next()?;
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None if protocol == "IMAP" => (address, 993),
+        None => (address, 587),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let security = match port {
+        143 | 587 | 25 => Security::StartTls,
+        _ => Security::Tls,
+    };
+    Some(ServerConfig {
+        host: host.to_string(),
+        port,
+        security,
+    })
+}
+
+/// Record SRV: _imaps e _submissions con TLS implicito, _imap e _submission con STARTTLS
+async fn srv_candidate(resolver: &TokioAsyncResolver, domain: &str) -> Option<Candidate> {
+    let (imaps, imap, submissions, submission) = tokio::join!(
+        srv_servers(resolver, "_imaps._tcp", domain, Security::Tls),
+        srv_servers(resolver, "_imap._tcp", domain, Security::StartTls),
+        srv_servers(resolver, "_submissions._tcp", domain, Security::Tls),
+        srv_servers(resolver, "_submission._tcp", domain, Security::StartTls),
+    );
+    let mut candidate = Candidate::new(DiscoverySource::Srv);
+    candidate.imap = imaps.into_iter().chain(imap).collect();
+    candidate.smtp = submissions.into_iter().chain(submission).collect();
+    Some(candidate)
+}
+
+async fn srv_servers(resolver: &TokioAsyncResolver, service: &str, domain: &str, security: Security) -> Vec<ServerConfig> {
+    let Ok(lookup) = resolver.srv_lookup(format!("{}.{}.", service, domain)).await else {
+        return Vec::new();
+    };
+    let mut records: Vec<_> = lookup
+        .iter()
+        // RFC 2782: il target "." indica che il servizio non è disponibile
+        .filter(|record| !record.target().is_root())
+        .collect();
+    records.sort_by_key(|record| (record.priority(), Reverse(record.weight())));
+    records
+        .into_iter()
+        .map(|record| ServerConfig {
+            host: record.target().to_utf8().trim_end_matches('.').to_string(),
+            port: record.port(),
+            security,
+        })
+        .collect()
+}
+
+/// Nomi host comuni, sia con TLS implicito sia con STARTTLS
+fn guess_candidate(domain: &str) -> Candidate {
+    let mut candidate = Candidate::new(DiscoverySource::Guess);
+    for prefix in ["imap.", "mail.", ""] {
+        let host = format!("{}{}", prefix, domain);
+        candidate.imap.push(ServerConfig { host: host.clone(), port: 993, security: Security::Tls });
+        candidate.imap.push(ServerConfig { host, port: 143, security: Security::StartTls });
+    }
+    for prefix in ["smtp.", "mail.", ""] {
+        let host = format!("{}{}", prefix, domain);
+        candidate.smtp.push(ServerConfig { host: host.clone(), port: 465, security: Security::Tls });
+        candidate.smtp.push(ServerConfig { host, port: 587, security: Security::StartTls });
+    }
+    candidate
+}
+
+/// Verifica in parallelo tutti i server candidati e costruisce una configurazione per fonte,
+/// scegliendo per ciascuna il primo server IMAP e SMTP che ha risposto
+async fn probe_candidates(email: &str, mut candidates: Vec<Candidate>) -> Vec<DiscoveredSettings> {
+    // Le connessioni in chiaro verso host remoti verrebbero comunque rifiutate
+    for candidate in &mut candidates {
+        candidate.imap.retain(|server| server.check_security().is_ok());
+        candidate.smtp.retain(|server| server.check_security().is_ok());
+    }
+
+    let mut imap_servers: Vec<ServerConfig> = Vec::new();
+    let mut smtp_servers: Vec<ServerConfig> = Vec::new();
+    for candidate in &candidates {
+        for server in &candidate.imap {
+            if !imap_servers.contains(server) {
+                imap_servers.push(server.clone());
+            }
+        }
+        for server in &candidate.smtp {
+            if !smtp_servers.contains(server) {
+                smtp_servers.push(server.clone());
+            }
+        }
+    }
+
+    let imap_results: HashMap<ServerConfig, Vec<String>> = join_all(imap_servers.into_iter().map(|server| async move {
+        match tokio::time::timeout(DISCOVERY_TIMEOUT, probe_capabilities(&server)).await {
+            Ok(Ok(capabilities)) => Some((server, capabilities)),
+            Ok(Err(e)) => {
+                println!("[DISCOVERY] IMAP {}:{} non raggiungibile: {}", server.host, server.port, e);
+                None
+            }
+            Err(_) => {
+                println!("[DISCOVERY] IMAP {}:{} non risponde", server.host, server.port);
+                None
+            }
+        }
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let smtp_results: Vec<ServerConfig> = join_all(smtp_servers.into_iter().map(|server| async move {
+        match tokio::time::timeout(DISCOVERY_TIMEOUT, probe_smtp(&server)).await {
+            Ok(Ok(())) => Some(server),
+            Ok(Err(e)) => {
+                println!("[DISCOVERY] {}", e);
+                None
+            }
+            Err(_) => {
+                println!("[DISCOVERY] SMTP {}:{} non risponde", server.host, server.port);
+                None
+            }
+        }
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let mut settings: Vec<DiscoveredSettings> = Vec::new();
+    for candidate in candidates {
+        let imap = candidate
+            .imap
+            .iter()
+            .find(|server| imap_results.contains_key(server))
+            .or(candidate.imap.first());
+        let smtp = candidate
+            .smtp
+            .iter()
+            .find(|server| smtp_results.contains(server))
+            .or(candidate.smtp.first());
+        let (Some(imap), Some(smtp)) = (imap, smtp) else {
+            continue;
+        };
+
+        let capabilities = imap_results.get(imap).cloned().unwrap_or_default();
+        let config = AccountConfig {
+            email: email.to_string(),
+            // Il nome utente uguale all'indirizzo è già il comportamento predefinito
+            username: candidate.username.clone().filter(|u| !u.eq_ignore_ascii_case(email)),
+            imap: imap.clone(),
+            smtp: smtp.clone(),
+            auth: auth_method(candidate.oauth, &capabilities),
+        };
+        // La stessa configurazione da più fonti compare una volta sola, con la fonte migliore
+        if settings.iter().any(|s| s.config == config) {
+            continue;
+        }
+        settings.push(DiscoveredSettings {
+            config,
+            source: candidate.source,
+            imap_verified: imap_results.contains_key(imap),
+            smtp_verified: smtp_results.contains(smtp),
+            imap_capabilities: capabilities,
+        });
+    }
+
+    settings.sort_by_key(|s| {
+        (
+            !s.imap_verified,
+            !s.smtp_verified,
+            s.source,
+            s.config.imap.security != Security::Tls,
+        )
+    });
+    settings
+}
+
+/// Metodo di autenticazione suggerito: XOAUTH2 se la fonte indica OAuth2 (il più diffuso;
+/// per IMAP la negoziazione ripiega su OAUTHBEARER se è il solo annunciato),
+/// altrimenti password con AUTH=PLAIN quando annunciato e il comando LOGIN negli altri casi
+fn auth_method(oauth: bool, capabilities: &[String]) -> AuthMethod {
+    let has = |capability: &str| capabilities.iter().any(|c| c == capability);
+    if oauth {
+        AuthMethod::Xoauth2
+    } else if has("AUTH=PLAIN") {
+        AuthMethod::Plain
+    } else {
+        AuthMethod::Login
+    }
+}
+
+/// Scarica un documento di configurazione; errori e risposte non 2xx significano "non disponibile"
+async fn fetch_text(http: &reqwest::Client, url: &str) -> Option<String> {
+    let response = match http.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("[DISCOVERY] {} non disponibile: {}", url, e);
+            return None;
+        }
+    };
+    if !response.status().is_success() {
+        println!("[DISCOVERY] {} ha risposto {}", url, response.status());
+        return None;
+    }
+    response.text().await.ok()
+}
+
+/// Nome di dominio plausibile, da inserire senza rischi negli URL e nelle query DNS
+fn is_valid_domain(domain: &str) -> bool {
+    domain.contains('.')
+        && domain
+            .split('.')
+            .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+}
+
+/// Percent-encoding per l'indirizzo email nei parametri degli URL
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::{closed_port, FakeDnsServer, FakeHttpServer, FakeImapServer, FakeSmtpServer};
+    use hickory_resolver::proto::rr::rdata::{MX, SRV};
+    use hickory_resolver::proto::rr::{Name, RData, Record};
+
+    /// Documento autoconfig con IMAP e SMTP in chiaro su 127.0.0.1
+    fn autoconfig_xml(imap_port: u16, smtp_port: u16) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<clientConfig version="1.1">
+  <emailProvider id="example.test">
+    <domain>example.test</domain>
+    <incomingServer type="imap">
+      <hostname>127.0.0.1</hostname>
+      <port>{}</port>
+      <socketType>plain</socketType>
+      <authentication>password-cleartext</authentication>
+      <username>%EMAILLOCALPART%</username>
+    </incomingServer>
+    <outgoingServer type="smtp">
+      <hostname>127.0.0.1</hostname>
+      <port>{}</port>
+      <socketType>plain</socketType>
+      <authentication>password-cleartext</authentication>
+      <username>%EMAILLOCALPART%</username>
+    </outgoingServer>
+  </emailProvider>
+</clientConfig>"#,
+            imap_port, smtp_port
+        )
+    }
+
+    fn record(name: &str, rdata: RData) -> Record {
+        Record::from_rdata(Name::from_ascii(name).unwrap(), 300, rdata)
+    }
+
+    #[tokio::test]
+    async fn queries_every_source_and_ranks_verified_settings_first() {
+        let imap = FakeImapServer::start(vec![("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 AUTH=PLAIN".to_string()])]).await;
+        let smtp = FakeSmtpServer::start(&["AUTH PLAIN LOGIN"], vec![]).await;
+        let closed = closed_port().await;
+
+        // Autoconfig: il primo URL non esiste, il secondo indica un SMTP che non risponde.
+        // ISPDB: niente per il dominio, una configurazione funzionante per il dominio dell'MX.
+        // Autodiscover e SRV: server TLS irraggiungibili.
+        let endpoint = |protocol: &str| format!(r#"{{"Protocol":"{}","Url":"127.0.0.1:{}"}}"#, protocol, closed);
+        let http = FakeHttpServer::start(vec![
+            ("/autoconfig/example.test".to_string(), autoconfig_xml(imap.port, closed)),
+            ("/ispdb/mxhost.test".to_string(), autoconfig_xml(imap.port, smtp.port)),
+            ("/autodiscover/IMAP/anna%40example.test".to_string(), endpoint("IMAP")),
+            ("/autodiscover/SMTP/anna%40example.test".to_string(), endpoint("SMTP")),
+        ])
+        .await;
+        let localhost = Name::from_ascii("localhost.").unwrap();
+        let dns = FakeDnsServer::start(vec![
+            record("example.test.", RData::MX(MX::new(10, Name::from_ascii("mx1.mxhost.test.").unwrap()))),
+            record("_imaps._tcp.example.test.", RData::SRV(SRV::new(0, 0, closed, localhost.clone()))),
+            record("_submissions._tcp.example.test.", RData::SRV(SRV::new(0, 0, closed, localhost))),
+        ])
+        .await;
+
+        let endpoints = DiscoveryEndpoints {
+            autoconfig_urls: vec![
+                http.url("/missing/{domain}"),
+                http.url("/autoconfig/{domain}?emailaddress={email}"),
+            ],
+            ispdb_url: http.url("/ispdb/{domain}"),
+            autodiscover_urls: vec![http.url("/autodiscover/{protocol}/{email}")],
+            dns_server: Some(dns.address),
+        };
+        let settings = discover("anna@example.test", &endpoints).await.expect("discovery riuscita");
+
+        let requests = http.requests();
+        for expected in [
+            "/missing/example.test",
+            "/autoconfig/example.test?emailaddress=anna%40example.test",
+            "/ispdb/example.test",
+            "/ispdb/mxhost.test",
+            "/autodiscover/IMAP/anna%40example.test",
+            "/autodiscover/SMTP/anna%40example.test",
+        ] {
+            assert!(requests.iter().any(|r| r == expected), "{} non richiesto: {:?}", expected, requests);
+        }
+
+        // Prima le configurazioni verificate, poi l'ordine delle fonti; nessun nome host indovinato
+        let sources: Vec<DiscoverySource> = settings.iter().map(|s| s.source).collect();
+        assert_eq!(
+            sources,
+            vec![DiscoverySource::Ispdb, DiscoverySource::Autoconfig, DiscoverySource::Autodiscover, DiscoverySource::Srv]
+        );
+
+        let ispdb = &settings[0];
+        assert!(ispdb.imap_verified && ispdb.smtp_verified);
+        assert_eq!((ispdb.config.imap.port, ispdb.config.smtp.port), (imap.port, smtp.port));
+        assert_eq!(ispdb.config.username.as_deref(), Some("anna"));
+        assert_eq!(ispdb.config.auth, AuthMethod::Plain);
+        assert!(ispdb.imap_capabilities.iter().any(|c| c == "AUTH=PLAIN"));
+
+        let autoconfig = &settings[1];
+        assert!(autoconfig.imap_verified && !autoconfig.smtp_verified);
+        assert_eq!(autoconfig.config.smtp.port, closed);
+
+        let autodiscover = &settings[2];
+        assert!(!autodiscover.imap_verified && !autodiscover.smtp_verified);
+        assert_eq!(autodiscover.config.auth, AuthMethod::Xoauth2);
+        assert_eq!(autodiscover.config.imap.security, Security::Tls);
+
+        let srv = &settings[3];
+        assert_eq!(srv.config.imap.host, "localhost");
+        assert_eq!((srv.config.imap.port, srv.config.smtp.security), (closed, Security::Tls));
+    }
+}
@@ -0,0 +1,109 @@
+use async_imap::types::Flag;
+
+/// Keyword usata da Thunderbird e da molti server per i messaggi importanti
+const IMPORTANT_KEYWORD: &str = "$Important";
+
+/// Keyword di classificazione spam (RFC 5788): aggiungerne una rimuove l'altra
+const JUNK_KEYWORD: &str = "$Junk";
+const NOT_JUNK_KEYWORD: &str = "$NotJunk";
+
+/// Flag di sistema che i client possono impostare (RFC 3501, sezione 2.3.2)
+const SYSTEM_FLAGS: &[&str] = &["\\Seen", "\\Answered", "\\Flagged", "\\Draft"];
+
+/// Flag di un messaggio letti da FETCH FLAGS
+#[derive(Debug, Default)]
+pub struct MessageFlags {
+    /// Tutti i flag nel formato IMAP (es. "\Seen", "$Important")
+    pub flags: Vec<String>,
+    /// Solo le keyword (flag senza "\"), incluse le etichette dell'utente
+    pub keywords: Vec<String>,
+    pub is_read: bool,
+    pub is_starred: bool,
+    pub is_important: bool,
+}
+
+impl MessageFlags {
+    pub fn from_flags<'a>(flags: impl Iterator<Item = Flag<'a>>) -> Self {
+        let mut result = MessageFlags::default();
+        for flag in flags {
+            match flag {
+                Flag::Seen => result.is_read = true,
+                Flag::Flagged => result.is_starred = true,
+                Flag::Custom(ref keyword) if !keyword.starts_with('\\') => {
+                    if keyword.eq_ignore_ascii_case(IMPORTANT_KEYWORD) {
+                        result.is_important = true;
+                    }
+                    result.keywords.push(keyword.to_string());
+                }
+                _ => {}
+            }
+            result.flags.push(flag_name(&flag));
+        }
+        result
+    }
+}
+
+/// Nome IMAP di un flag (es. Flag::Seen -> "\Seen")
+pub fn flag_name(flag: &Flag) -> String {
+    match flag {
+        Flag::Seen => "\\Seen".to_string(),
+        Flag::Answered => "\\Answered".to_string(),
+        Flag::Flagged => "\\Flagged".to_string(),
+        Flag::Deleted => "\\Deleted".to_string(),
+        Flag::Draft => "\\Draft".to_string(),
+        Flag::Recent => "\\Recent".to_string(),
+        Flag::MayCreate => "\\*".to_string(),
+        Flag::Custom(name) => name.to_string(),
+    }
+}
+
+/// Normalizza un flag richiesto dal frontend e rifiuta quelli che non vanno impostati
+/// direttamente (\Deleted e \Recent) o che non sono atomi IMAP validi
+pub fn normalize_flag(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if let Some(system) = SYSTEM_FLAGS.iter().find(|f| f.eq_ignore_ascii_case(name)) {
+        return Ok(system.to_string());
+    }
+    if name.starts_with('\\') {
+        return Err(format!("Flag non modificabile: {}", name));
+    }
+
+    // atom-specials (RFC 3501): niente spazi, controlli, parentesi, wildcard e virgolette
+    let valid = !name.is_empty()
+        && name.chars().all(|c| {
+            c.is_ascii_graphic() && !matches!(c, '(' | ')' | '{' | '%' | '*' | '"' | '\\' | ']')
+        });
+    if !valid {
+        return Err(format!("Keyword non valida: {}", name));
+    }
+
+    // Le keyword standard mantengono la grafia attesa dagli altri client
+    let known = [IMPORTANT_KEYWORD, JUNK_KEYWORD, NOT_JUNK_KEYWORD, "$Forwarded"];
+    Ok(known
+        .iter()
+        .find(|k| k.eq_ignore_ascii_case(name))
+        .map(|k| k.to_string())
+        .unwrap_or_else(|| name.to_string()))
+}
+
+/// Aggiunge alla lista da rimuovere la keyword opposta di $Junk/$NotJunk
+pub fn exclusive_removals(add: &[String], remove: &mut Vec<String>) {
+    for (keyword, opposite) in [(JUNK_KEYWORD, NOT_JUNK_KEYWORD), (NOT_JUNK_KEYWORD, JUNK_KEYWORD)] {
+        let opposite = opposite.to_string();
+        if add.iter().any(|f| f == keyword) && !add.contains(&opposite) && !remove.contains(&opposite) {
+            remove.push(opposite);
+        }
+    }
+}
+
+/// Verifica che il flag possa essere salvato in modo permanente secondo PERMANENTFLAGS.
+/// Se il server non invia PERMANENTFLAGS tutti i flag sono considerati permanenti.
+pub fn is_permanent(flag: &str, permanent_flags: &[Flag]) -> bool {
+    if permanent_flags.is_empty() {
+        return true;
+    }
+    // "\*" consente di creare nuove keyword
+    let may_create = permanent_flags.iter().any(|f| matches!(f, Flag::MayCreate));
+    (may_create && !flag.starts_with('\\'))
+        || permanent_flags.iter().any(|f| flag_name(f).eq_ignore_ascii_case(flag))
+}
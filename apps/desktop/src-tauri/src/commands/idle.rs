@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use async_imap::extensions::idle::IdleResponse;
+use async_imap::Session;
+use futures_util::StreamExt;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_notification::NotificationExt;
+use tokio::sync::{watch, Mutex};
+
+use super::account::{AccountConfig, AccountManager};
+use super::auth::SessionError;
+use super::imap::{parse_fetched_message, CombinedStream, MailMessage, MESSAGE_FETCH_QUERY};
+
+/// Evento emesso verso il frontend per ogni nuovo messaggio in INBOX
+const NEW_MESSAGE_EVENT: &str = "mail://new-message";
+
+/// RFC 2177: il client deve rinnovare IDLE prima dei 30 minuti di timeout del server
+const IDLE_TIMEOUT: Duration = Duration::from_secs(29 * 60);
+
+/// Intervallo di polling con NOOP quando il server non supporta IDLE
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Attesa prima di riconnettersi dopo un errore
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Task IDLE in esecuzione per un account
+struct IdleWatcher {
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+/// Gestore dei task IDLE in background, uno per account
+#[derive(Default)]
+pub struct IdleManager {
+    watchers: Mutex<HashMap<String, IdleWatcher>>,
+}
+
+impl IdleManager {
+    /// Ferma il watcher dell'account e attende la chiusura della sua sessione
+    pub async fn stop(&self, account_id: &str) {
+        let watcher = self.watchers.lock().await.remove(account_id);
+        if let Some(watcher) = watcher {
+            stop_watcher(watcher).await;
+        }
+    }
+}
+
+/// Avvia il monitoraggio di INBOX con IMAP IDLE per un account
+#[tauri::command]
+pub async fn start_idle(
+    app: AppHandle,
+    manager: State<'_, IdleManager>,
+    account_id: String,
+) -> Result<(), String> {
+    println!("[IDLE] Avvio monitoraggio INBOX per account: {}", account_id);
+
+    let mut watchers = manager.watchers.lock().await;
+
+    // Un eventuale watcher precedente usa una sessione con configurazione vecchia: lo sostituiamo
+    if let Some(previous) = watchers.remove(&account_id) {
+        stop_watcher(previous).await;
+    }
+
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let handle = tauri::async_runtime::spawn(watch_inbox(
+        app,
+        account_id.clone(),
+        stop_rx,
+    ));
+
+    watchers.insert(account_id, IdleWatcher { stop: stop_tx, handle });
+    Ok(())
+}
+
+/// Ferma il monitoraggio IDLE di un account
+#[tauri::command]
+pub async fn stop_idle(
+    manager: State<'_, IdleManager>,
+    account_id: String,
+) -> Result<(), String> {
+    println!("[IDLE] Stop monitoraggio INBOX per account: {}", account_id);
+    manager.stop(&account_id).await;
+    Ok(())
+}
+
+async fn stop_watcher(watcher: IdleWatcher) {
+    let _ = watcher.stop.send(true);
+    if let Err(e) = watcher.handle.await {
+        println!("[IDLE] Errore nella chiusura del task: {}", e);
+    }
+}
+
+/// Ciclo principale del task: riconnette dopo ogni errore finché non viene fermato
+async fn watch_inbox(
+    app: AppHandle,
+    account_id: String,
+    stop: watch::Receiver<bool>,
+) {
+    let accounts = app.state::<AccountManager>();
+    // Ogni riconnessione legge credenziali aggiornate, rinnovando il token se necessario
+    let open = || accounts.open_session(&account_id);
+    let notify = |message: &MailMessage| show_new_message(&app, message);
+    watch_inbox_with(&account_id, stop, open, notify).await;
+}
+
+/// Come `watch_inbox`, con `open` che apre la sessione dedicata e `notify` chiamata
+/// per ogni nuovo messaggio. Permette di collegare il watcher a un server di test.
+async fn watch_inbox_with<F, Fut, N>(
+    account_id: &str,
+    mut stop: watch::Receiver<bool>,
+    open: F,
+    notify: N,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<(AccountConfig, Session<CombinedStream>), SessionError>>,
+    N: Fn(&MailMessage),
+{
+    // Conservato tra le riconnessioni: i messaggi arrivati mentre la connessione
+    // era assente vengono notificati dopo la nuova SELECT
+    let mut cursor = None;
+    loop {
+        match run_watcher(account_id, &mut stop, &open, &notify, &mut cursor).await {
+            Ok(()) => break,
+            Err(e) => {
+                println!("[IDLE] Errore per account {}: {}, riconnessione tra {:?}", account_id, e, RECONNECT_DELAY);
+                tokio::select! {
+                    _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+                    _ = stop.changed() => break,
+                }
+            }
+        }
+    }
+    println!("[IDLE] Task terminato per account: {}", account_id);
+}
+
+/// Ultima posizione nota di INBOX
+#[derive(Debug, Clone, Copy)]
+struct InboxCursor {
+    uid_validity: Option<u32>,
+    uid_next: u32,
+}
+
+/// Apre una sessione dedicata e attende nuovi messaggi in INBOX.
+/// Restituisce `Ok(())` solo quando viene richiesto lo stop.
+async fn run_watcher<F, Fut, N>(
+    account_id: &str,
+    stop: &mut watch::Receiver<bool>,
+    open: &F,
+    notify: &N,
+    cursor: &mut Option<InboxCursor>,
+) -> Result<(), String>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<(AccountConfig, Session<CombinedStream>), SessionError>>,
+    N: Fn(&MailMessage),
+{
+    // IDLE occupa la connessione, quindi non usiamo il pool condiviso
+    let (_, mut session) = open().await?;
+
+    let supports_idle = session
+        .capabilities()
+        .await
+        .map_err(|e| format!("Errore nel CAPABILITY: {}", e))?
+        .has_str("IDLE");
+    if !supports_idle {
+        println!("[IDLE] Il server non supporta IDLE, uso polling NOOP ogni {:?}", POLL_INTERVAL);
+    }
+
+    let mailbox = session
+        .select("INBOX")
+        .await
+        .map_err(|e| format!("Errore nella selezione INBOX: {}", e))?;
+    let selected = InboxCursor {
+        uid_validity: mailbox.uid_validity,
+        uid_next: mailbox.uid_next.unwrap_or(1),
+    };
+    let mut uid_next = match *cursor {
+        // Stessa INBOX della connessione precedente: recupera i messaggi arrivati nel frattempo
+        Some(previous) if previous.uid_validity == selected.uid_validity && previous.uid_next < selected.uid_next => {
+            println!(
+                "[IDLE] Recupero messaggi arrivati durante la riconnessione: UID {}..{}",
+                previous.uid_next, selected.uid_next
+            );
+            notify_new_messages(notify, account_id, &mut session, previous.uid_next).await?
+        }
+        Some(previous) if previous.uid_validity == selected.uid_validity => previous.uid_next.max(selected.uid_next),
+        // Prima connessione o UIDVALIDITY cambiata: i vecchi UID non sono più confrontabili
+        _ => selected.uid_next,
+    };
+    *cursor = Some(InboxCursor { uid_next, ..selected });
+
+    loop {
+        if *stop.borrow() {
+            let _ = session.logout().await;
+            return Ok(());
+        }
+
+        if supports_idle {
+            let mut handle = session.idle();
+            handle
+                .init()
+                .await
+                .map_err(|e| format!("Errore nell'avvio di IDLE: {}", e))?;
+
+            let (idle_wait, interrupt) = handle.wait_with_timeout(IDLE_TIMEOUT);
+            let response = tokio::select! {
+                response = idle_wait => Some(response),
+                _ = stop.changed() => None,
+            };
+            drop(interrupt);
+
+            // DONE termina IDLE e restituisce la sessione
+            session = handle
+                .done()
+                .await
+                .map_err(|e| format!("Errore nella chiusura di IDLE: {}", e))?;
+
+            match response {
+                None => {
+                    let _ = session.logout().await;
+                    return Ok(());
+                }
+                Some(Ok(IdleResponse::NewData(_))) => {
+                    println!("[IDLE] Nuovi dati in INBOX per account: {}", account_id);
+                }
+                Some(Ok(IdleResponse::Timeout)) => {
+                    // Rinnova IDLE prima che il server chiuda la connessione
+                    continue;
+                }
+                Some(Ok(IdleResponse::ManualInterrupt)) => {}
+                Some(Err(e)) => return Err(format!("Errore durante IDLE: {}", e)),
+            }
+        } else {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = stop.changed() => {
+                    let _ = session.logout().await;
+                    return Ok(());
+                }
+            }
+
+            session
+                .noop()
+                .await
+                .map_err(|e| format!("Errore nel NOOP: {}", e))?;
+        }
+
+        uid_next = notify_new_messages(notify, account_id, &mut session, uid_next).await?;
+        *cursor = Some(InboxCursor { uid_next, ..selected });
+    }
+}
+
+/// Recupera gli header dei messaggi con UID >= `uid_next` e li passa a `notify`.
+/// Restituisce il nuovo UIDNEXT.
+async fn notify_new_messages<N: Fn(&MailMessage)>(
+    notify: &N,
+    account_id: &str,
+    session: &mut Session<CombinedStream>,
+    uid_next: u32,
+) -> Result<u32, String> {
+    let uids = session
+        .uid_search(format!("UID {}:*", uid_next))
+        .await
+        .map_err(|e| format!("Errore nel UID SEARCH: {}", e))?;
+
+    // "n:*" include sempre l'ultimo messaggio, anche se ha UID < n
+    let mut new_uids: Vec<u32> = uids.into_iter().filter(|uid| *uid >= uid_next).collect();
+    if new_uids.is_empty() {
+        return Ok(uid_next);
+    }
+    new_uids.sort();
+
+    let uid_set: Vec<String> = new_uids.iter().map(|u| u.to_string()).collect();
+    let folder_id = format!("{}-INBOX", account_id);
+    let mut messages = Vec::new();
+
+    let mut stream = session
+        .uid_fetch(uid_set.join(","), MESSAGE_FETCH_QUERY)
+        .await
+        .map_err(|e| format!("Errore nel UID FETCH: {}", e))?;
+    while let Some(msg_result) = stream.next().await {
+        match msg_result {
+            Ok(msg) => {
+                if let Some(message) = parse_fetched_message(account_id, &folder_id, &msg) {
+                    messages.push(message);
+                }
+            }
+            Err(e) => println!("[IDLE] Errore nel fetch del messaggio: {}", e),
+        }
+    }
+    drop(stream);
+
+    for message in &messages {
+        println!("[IDLE] Nuovo messaggio: {} (UID: {})", message.subject, message.uid);
+        notify(message);
+    }
+
+    Ok(new_uids.last().map(|uid| uid + 1).unwrap_or(uid_next))
+}
+
+/// Emette l'evento verso il frontend e mostra la notifica di sistema
+fn show_new_message(app: &AppHandle, message: &MailMessage) {
+    if let Err(e) = app.emit(NEW_MESSAGE_EVENT, message) {
+        println!("[IDLE] Errore nell'emissione dell'evento: {}", e);
+    }
+
+    let title = message
+        .from_name
+        .clone()
+        .unwrap_or_else(|| message.from_address.clone());
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title(title)
+        .body(&message.subject)
+        .show()
+    {
+        println!("[IDLE] Errore nella notifica: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::imap::create_imap_session;
+    use crate::commands::test_support::FakeImapServer;
+
+    const ENVELOPE: &str = "(\"Mon, 7 Feb 2022 21:52:25 +0100\" \"Nuovo\" ((\"Anna\" NIL \"anna\" \"example.com\")) NIL NIL ((NIL NIL \"test\" \"example.com\")) NIL NIL NIL \"<nuovo@example.com>\")";
+
+    fn fetch_line(seq: u32, uid: u32) -> String {
+        format!("* {} FETCH (UID {} FLAGS () RFC822.SIZE 120 ENVELOPE {})", seq, uid, ENVELOPE)
+    }
+
+    /// Esegue il watcher finché non ha notificato `expected` messaggi, poi lo ferma.
+    /// Restituisce gli UID notificati.
+    async fn watch_until(server: &FakeImapServer, cursor: Option<InboxCursor>, expected: usize) -> Vec<u32> {
+        let account = server.account();
+        let open = || {
+            let account = account.clone();
+            async move {
+                let session = create_imap_session(&account, "segreto").await?;
+                Ok::<_, SessionError>((account, session))
+            }
+        };
+        let notified = std::sync::Mutex::new(Vec::new());
+        let (stop_tx, mut stop_rx) = watch::channel(false);
+        let notify = |message: &MailMessage| {
+            let mut notified = notified.lock().unwrap();
+            notified.push(message.uid);
+            if notified.len() >= expected {
+                let _ = stop_tx.send(true);
+            }
+        };
+
+        let mut cursor = cursor;
+        let watcher = run_watcher("test", &mut stop_rx, &open, &notify, &mut cursor);
+        tokio::time::timeout(Duration::from_secs(10), watcher)
+            .await
+            .expect("il watcher non ha notificato i messaggi attesi")
+            .unwrap();
+        notified.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn exists_during_idle_notifies_the_new_message() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 IDLE".to_string()]),
+            ("SELECT", vec![
+                "* 4 EXISTS".to_string(),
+                "* OK [UIDVALIDITY 7] UID validi".to_string(),
+                "* OK [UIDNEXT 5] prossimo UID".to_string(),
+            ]),
+            ("IDLE", vec!["+ idling".to_string(), "* 5 EXISTS".to_string()]),
+            // "5:*" include sempre l'ultimo messaggio: gli UID già noti vanno scartati
+            ("UID SEARCH", vec!["* SEARCH 4 5".to_string()]),
+            ("UID FETCH", vec![fetch_line(5, 5)]),
+        ])
+        .await;
+
+        let notified = watch_until(&server, None, 1).await;
+
+        assert_eq!(notified, vec![5]);
+        let commands = server.commands();
+        assert!(commands.contains(&"UID SEARCH UID 5:*".to_string()), "{:?}", commands);
+        assert!(commands.contains(&format!("UID FETCH 5 {}", MESSAGE_FETCH_QUERY)), "{:?}", commands);
+        assert!(commands.iter().any(|c| c == "LOGOUT"), "{:?}", commands);
+    }
+
+    #[tokio::test]
+    async fn reconnect_notifies_messages_that_arrived_while_disconnected() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 IDLE".to_string()]),
+            ("SELECT", vec![
+                "* 6 EXISTS".to_string(),
+                "* OK [UIDVALIDITY 7] UID validi".to_string(),
+                "* OK [UIDNEXT 7] prossimo UID".to_string(),
+            ]),
+            ("UID SEARCH", vec!["* SEARCH 5 6".to_string()]),
+            ("UID FETCH", vec![fetch_line(5, 5), fetch_line(6, 6)]),
+        ])
+        .await;
+
+        // La connessione precedente aveva visto UIDNEXT 5
+        let previous = InboxCursor { uid_validity: Some(7), uid_next: 5 };
+        let notified = watch_until(&server, Some(previous), 2).await;
+
+        assert_eq!(notified, vec![5, 6]);
+        let commands = server.commands();
+        assert!(commands.contains(&"UID SEARCH UID 5:*".to_string()), "{:?}", commands);
+        // Recuperati subito dopo la SELECT, prima di entrare in IDLE
+        assert!(!commands.iter().any(|c| c == "IDLE"), "{:?}", commands);
+    }
+
+    #[tokio::test]
+    async fn changed_uidvalidity_does_not_replay_old_uids() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 IDLE".to_string()]),
+            ("SELECT", vec![
+                "* 6 EXISTS".to_string(),
+                "* OK [UIDVALIDITY 8] UID validi".to_string(),
+                "* OK [UIDNEXT 7] prossimo UID".to_string(),
+            ]),
+            ("IDLE", vec!["+ idling".to_string(), "* 7 EXISTS".to_string()]),
+            ("UID SEARCH", vec!["* SEARCH 6 7".to_string()]),
+            ("UID FETCH", vec![fetch_line(7, 7)]),
+        ])
+        .await;
+
+        let previous = InboxCursor { uid_validity: Some(7), uid_next: 2 };
+        let notified = watch_until(&server, Some(previous), 1).await;
+
+        assert_eq!(notified, vec![7]);
+        assert!(server.commands().contains(&"UID SEARCH UID 7:*".to_string()));
+    }
+}
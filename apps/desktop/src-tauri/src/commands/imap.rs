@@ -1,21 +1,48 @@
 use serde::{Deserialize, Serialize};
 use async_imap::Session;
-use async_imap::Authenticator;
-use tokio_native_tls::TlsConnector;
+use async_imap::types::{Fetch, UnsolicitedResponse};
+use imap_proto::types::{
+    AttributeValue, MailboxDatum, NameAttribute, Response, ResponseCode, SectionPath, Status, StatusAttribute, UidSetMember,
+};
+use tokio_native_tls::{TlsConnector, TlsStream};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
-use mailparse::*;
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 use futures_util::io::{AsyncRead, AsyncWrite};
 use futures_util::StreamExt;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
-use base64::Engine;
+use mailparse::MailHeaderMap;
+use tauri::State;
 
-// Wrapper per combinare read e write halves di uno stream TLS
-struct CombinedStream {
-    read: tokio_util::compat::Compat<tokio::io::ReadHalf<tokio_native_tls::TlsStream<tokio::net::TcpStream>>>,
-    write: tokio_util::compat::Compat<tokio::io::WriteHalf<tokio_native_tls::TlsStream<tokio::net::TcpStream>>>,
+use super::account::{AccountConfig, AccountManager, AuthMethod, Security, ServerConfig};
+use super::address::{message_addresses, EmailAddress};
+use super::auth::{negotiate, sasl_initial_response, AuthError, SaslAuthenticator, SessionError};
+use super::flags::{exclusive_removals, is_permanent, normalize_flag, MessageFlags};
+use super::mime::{decode_header_value, find_attachments, find_text_parts, Attachment, TextPart};
+use super::pool::{ImapPool, PooledConnection};
+use super::responses::{run_command_with, ResponseRewriter};
+use super::special_use::{assign_roles, list_entry, role_from_attributes, special_use_attributes, FolderRole};
+use super::threading::{
+    parse_message_ids, parse_thread_response, thread_messages, thread_uids, threads_by_id, threads_from_server, MailThread,
+    ThreadNode,
+};
+use super::utf7::decode_mailbox_name;
+
+/// Stream di trasporto della sessione IMAP: TCP in chiaro o cifrato con TLS
+trait ImapIo: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin {}
+
+impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin> ImapIo for T {}
+
+// Wrapper per combinare read e write halves dello stream di trasporto
+pub(crate) struct CombinedStream {
+    read: tokio_util::compat::Compat<tokio::io::ReadHalf<Box<dyn ImapIo>>>,
+    write: tokio_util::compat::Compat<tokio::io::WriteHalf<Box<dyn ImapIo>>>,
+    /// Riscrive le risposte che async-imap non saprebbe interpretare
+    rewriter: ResponseRewriter,
+    /// Dati già riscritti non ancora consegnati ad async-imap
+    pending: Vec<u8>,
 }
 
 impl AsyncRead for CombinedStream {
@@ -24,8 +51,27 @@ impl AsyncRead for CombinedStream {
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<std::io::Result<usize>> {
-        // futures_util::io::AsyncRead usa &mut [u8], non ReadBuf
-        Pin::new(&mut self.read).poll_read(cx, buf)
+        let this = &mut *self;
+        // Il rewriter può trattenere l'inizio di una riga: si legge finché c'è qualcosa da consegnare
+        while this.pending.is_empty() {
+            let mut chunk = [0u8; 8192];
+            let limit = buf.len().clamp(1, chunk.len());
+            // futures_util::io::AsyncRead usa &mut [u8], non ReadBuf
+            let read = std::task::ready!(Pin::new(&mut this.read).poll_read(cx, &mut chunk[..limit]))?;
+            if read == 0 {
+                this.rewriter.finish(&mut this.pending);
+                if this.pending.is_empty() {
+                    return Poll::Ready(Ok(0));
+                }
+                break;
+            }
+            this.rewriter.feed(&chunk[..read], &mut this.pending);
+        }
+        
+        let len = buf.len().min(this.pending.len());
+        buf[..len].copy_from_slice(&this.pending[..len]);
+        this.pending.drain(..len);
+        Poll::Ready(Ok(len))
     }
 }
 
@@ -61,17 +107,17 @@ impl std::fmt::Debug for CombinedStream {
 
 impl std::marker::Unpin for CombinedStream {}
 
-// Implementazione Authenticator per XOAUTH2
-struct XOAuth2Authenticator {
-    encoded: String,
-}
-
-impl Authenticator for XOAuth2Authenticator {
-    type Response = String;
-    
-    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
-        // Per XOAUTH2, restituiamo direttamente la stringa base64 codificata
-        self.encoded.clone()
+impl CombinedStream {
+    // Converti tokio::io::AsyncRead/AsyncWrite in futures_io::AsyncRead/AsyncWrite usando tokio-util
+    // Dividiamo lo stream in read e write halves e li convertiamo separatamente
+    fn new(stream: Box<dyn ImapIo>) -> Self {
+        let (read_half, write_half) = tokio::io::split(stream);
+        CombinedStream {
+            read: read_half.compat(),
+            write: write_half.compat_write(),
+            rewriter: ResponseRewriter::default(),
+            pending: Vec::new(),
+        }
     }
 }
 
@@ -79,13 +125,68 @@ impl Authenticator for XOAuth2Authenticator {
 pub struct MailFolder {
     pub id: String,
     pub account_id: String,
+    /// Nome dell'ultimo livello della gerarchia (es. "ACME" per "Projects/ACME")
     pub name: String,
+    /// Percorso completo usato nei comandi IMAP
     pub path: String,
+    pub delimiter: Option<String>,
+    pub parent_path: Option<String>,
+    /// Attributi LIST (es. \Noselect, \HasChildren)
+    pub attributes: Vec<String>,
+    pub selectable: bool,
+    pub has_children: bool,
+    /// Ruolo special-use (Sent, Drafts, Trash, ...) se individuato
+    pub role: Option<FolderRole>,
+    pub subscribed: bool,
     pub unread_count: i32,
     pub total_count: i32,
+    pub uid_next: Option<u32>,
+    pub uid_validity: Option<u32>,
     pub sync_at: Option<i64>,
+    pub children: Vec<MailFolder>,
+}
+
+impl MailFolder {
+    fn new(account_id: &str, path: &str, delimiter: Option<&str>) -> Self {
+        let (parent_path, name) = match delimiter.and_then(|d| path.rsplit_once(d)) {
+            Some((parent, name)) => (Some(parent.to_string()), name.to_string()),
+            None => (None, path.to_string()),
+        };
+        
+        MailFolder {
+            id: format!("{}-{}", account_id, path),
+            account_id: account_id.to_string(),
+            name,
+            path: path.to_string(),
+            delimiter: delimiter.map(|d| d.to_string()),
+            parent_path,
+            attributes: Vec::new(),
+            selectable: true,
+            has_children: false,
+            role: None,
+            subscribed: false,
+            unread_count: 0,
+            total_count: 0,
+            uid_next: None,
+            uid_validity: None,
+            sync_at: Some(chrono::Utc::now().timestamp_millis()),
+            children: Vec::new(),
+        }
+    }
 }
 
+/// Contatori restituiti da STATUS (o LIST-STATUS) per una cartella
+#[derive(Debug, Default)]
+struct FolderStatus {
+    messages: Option<u32>,
+    unseen: Option<u32>,
+    uid_next: Option<u32>,
+    uid_validity: Option<u32>,
+}
+
+/// Attributi richiesti con STATUS per ogni cartella
+const FOLDER_STATUS_ITEMS: &str = "(MESSAGES UNSEEN UIDNEXT UIDVALIDITY)";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MailMessage {
     pub id: String,
@@ -96,13 +197,19 @@ pub struct MailMessage {
     pub subject: String,
     pub from_name: Option<String>,
     pub from_address: String,
-    pub to_addresses: Vec<String>,
-    pub cc_addresses: Option<Vec<String>>,
-    pub bcc_addresses: Option<Vec<String>>,
+    pub to_addresses: Vec<EmailAddress>,
+    pub cc_addresses: Option<Vec<EmailAddress>>,
+    pub bcc_addresses: Option<Vec<EmailAddress>>,
+    pub reply_to: Option<Vec<EmailAddress>>,
+    pub sender: Option<EmailAddress>,
     pub date: i64,
+    pub size: Option<u32>,
+    pub attachments: Vec<Attachment>,
     pub text: Option<String>,
     pub html: Option<String>,
     pub flags: Vec<String>,
+    /// Keyword IMAP ($Important, $Junk, etichette dell'utente)
+    pub keywords: Vec<String>,
     pub is_read: bool,
     pub is_starred: bool,
     pub is_important: bool,
@@ -112,558 +219,2215 @@ pub struct MailMessage {
     pub synced_at: i64,
 }
 
-/// Helper per creare connessione IMAP con OAuth2 usando XOAUTH2
-async fn create_imap_session(
-    provider: &str,
-    email: &str,
-    access_token: &str,
-) -> Result<Session<CombinedStream>, String> {
-    let (host, port) = match provider {
-        "gmail" => ("imap.gmail.com", 993),
-        "outlook" => ("outlook.office365.com", 993),
-        _ => return Err(format!("Provider non supportato: {}", provider)),
-    };
+/// Corpo di un messaggio scaricato su richiesta
+#[derive(Debug, Serialize)]
+pub struct MessageBody {
+    pub uid: u32,
+    pub text: Option<String>,
+    pub html: Option<String>,
+}
 
-    let tls = native_tls::TlsConnector::builder()
-        .build()
-        .map_err(|e| format!("Errore nella creazione del connector TLS: {}", e))?;
+/// Esito per singolo UID di un'operazione su più messaggi
+#[derive(Debug, Serialize)]
+pub struct UidResult {
+    pub uid: u32,
+    pub success: bool,
+    pub error: Option<String>,
+    /// UID nella cartella di destinazione (spostamenti), se noto
+    pub target_uid: Option<u32>,
+    /// Spostamenti: il messaggio è presente nella destinazione. Con `success` false la copia
+    /// è riuscita ma la rimozione dalla sorgente no: riprovare lo spostamento creerebbe un duplicato.
+    pub copied: bool,
+}
+
+impl UidResult {
+    fn ok(uid: u32) -> Self {
+        UidResult { uid, success: true, error: None, target_uid: None, copied: false }
+    }
+    
+    fn failed(uid: u32, error: String) -> Self {
+        UidResult { uid, success: false, error: Some(error), target_uid: None, copied: false }
+    }
+    
+    /// Esito di uno spostamento: copiato nella destinazione e, se `removal` è un errore,
+    /// non ancora rimosso dalla sorgente
+    fn moved(uid: u32, target_uid: Option<u32>, removal: &Result<(), String>) -> Self {
+        UidResult {
+            target_uid,
+            copied: true,
+            ..UidResult::from_outcome(uid, removal)
+        }
+    }
     
-    let tls = TlsConnector::from(tls);
+    /// Esito di un comando eseguito su un insieme di UID, riportato sul singolo UID
+    fn from_outcome(uid: u32, outcome: &Result<(), String>) -> Self {
+        match outcome {
+            Ok(()) => UidResult::ok(uid),
+            Err(e) => UidResult::failed(uid, e.clone()),
+        }
+    }
+}
 
-    let tcp_stream = TcpStream::connect((host, port))
-        .await
-        .map_err(|e| format!("Errore nella connessione TCP: {}", e))?;
+/// Esito di uno spostamento: UIDVALIDITY della destinazione ed esito per ogni UID
+#[derive(Debug, Serialize)]
+pub struct MoveResult {
+    pub target_uid_validity: Option<u32>,
+    pub results: Vec<UidResult>,
+}
 
-    let tls_stream = tls
-        .connect(host, tcp_stream)
-        .await
-        .map_err(|e| format!("Errore nella connessione TLS: {}", e))?;
+/// Stato di sincronizzazione di una cartella, conservato dal frontend tra una sync e l'altra
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderSyncState {
+    pub uid_validity: u32,
+    pub uid_next: u32,
+    pub highest_modseq: Option<u64>,
+}
 
-    // Converti tokio::io::AsyncRead/AsyncWrite in futures_io::AsyncRead/AsyncWrite usando tokio-util
-    // Dividiamo lo stream in read e write halves e li convertiamo separatamente
-    let (read_half, write_half) = tokio::io::split(tls_stream);
-    let compat_read = read_half.compat();
-    let compat_write = write_half.compat_write();
-    
-    // Combina read e write in un unico stream
-    let combined = CombinedStream {
-        read: compat_read,
-        write: compat_write,
-    };
-    
-    let client = async_imap::Client::new(combined);
+/// Flag aggiornati di un messaggio già presente nella cache locale
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlagUpdate {
+    pub uid: u32,
+    pub flags: Vec<String>,
+    pub keywords: Vec<String>,
+    pub is_read: bool,
+    pub is_starred: bool,
+    pub is_important: bool,
+}
+
+/// Differenze rispetto allo stato precedente della cartella.
+/// Se `full_resync` è true la cache locale della cartella va sostituita con `new_messages`.
+#[derive(Debug, Serialize)]
+pub struct SyncDelta {
+    pub state: FolderSyncState,
+    pub full_resync: bool,
+    pub new_messages: Vec<MailMessage>,
+    pub changed_flags: Vec<FlagUpdate>,
+    pub vanished: Vec<u32>,
+}
+
+/// Apre una connessione IMAP verso il server dell'account e si autentica.
+/// I metodi vengono scelti in base a CAPABILITY, partendo da quello configurato;
+/// se il server ne rifiuta uno si riprova con il successivo su una nuova connessione.
+pub(crate) async fn create_imap_session(
+    account: &AccountConfig,
+    secret: &str,
+) -> Result<Session<CombinedStream>, SessionError> {
+    let server = &account.imap;
+    let (stream, capabilities) = open_connection(server).await.map_err(SessionError::Connection)?;
+    let mut stream = Some(stream);
+    let username = account.username();
+    let mut failures = Vec::new();
     
-    // Usa XOAUTH2 per Gmail, fallback a login normale per altri provider
-    let session = if provider == "gmail" {
-        // Costruisci la stringa XOAUTH2 nel formato richiesto da Gmail
-        // Formato: user=email\1auth=Bearer access_token\1\1
-        let xoauth2_string = format!("user={}\x01auth=Bearer {}\x01\x01", email, access_token);
-        
-        // Codifica in base64
-        let xoauth2_encoded = BASE64_STANDARD.encode(xoauth2_string.as_bytes());
-        
-        println!("[IMAP] Tentativo autenticazione XOAUTH2 per Gmail");
-        
-        // Crea l'authenticator XOAUTH2
-        let authenticator = XOAuth2Authenticator {
-            encoded: xoauth2_encoded,
+    for method in negotiate(account.auth, &capabilities) {
+        // Dopo un rifiuto alcuni server chiudono la connessione: ogni nuovo tentativo parte da zero
+        let stream = match stream.take() {
+            Some(stream) => stream,
+            None => open_connection(server).await.map_err(SessionError::Connection)?.0,
         };
         
-        // Usa authenticate con XOAUTH2
-        let session_result = client
-            .authenticate("XOAUTH2", authenticator)
-            .await;
-        
-        match session_result {
+        println!("[IMAP] Autenticazione {:?} per {} su {}", method, username, server.host);
+        match authenticate(stream, method, username, secret, server).await {
             Ok(session) => {
-                println!("[IMAP] Autenticazione XOAUTH2 riuscita per Gmail!");
-                session
+                println!("[IMAP] Autenticazione {:?} riuscita per {}", method, username);
+                return Ok(session);
             }
-            Err((err, _client)) => {
-                println!("[IMAP] ===== ERRORE AUTENTICAZIONE XOAUTH2 =====");
-                println!("[IMAP] Errore completo: {:?}", err);
-                println!("[IMAP] Email: {}", email);
-                println!("[IMAP] Access token length: {} chars", access_token.len());
-                println!("[IMAP] Access token preview: {}...", &access_token.chars().take(20).collect::<String>());
-                return Err(format!("Errore nell'autenticazione XOAUTH2: {:?}", err));
+            Err(error) => {
+                println!("[IMAP] Autenticazione {:?} fallita per {}: {}", method, username, error);
+                failures.push(error);
             }
         }
-    } else {
-        // Per Outlook, prova prima XOAUTH2
-        // Outlook supporta XOAUTH2 ma potrebbe richiedere un formato diverso
-        let xoauth2_string = format!("user={}\x01auth=Bearer {}\x01\x01", email, access_token);
-        let xoauth2_encoded = BASE64_STANDARD.encode(xoauth2_string.as_bytes());
-        
-        println!("[IMAP] Tentativo autenticazione XOAUTH2 per Outlook");
-        
-        // Crea l'authenticator XOAUTH2
-        let authenticator = XOAuth2Authenticator {
-            encoded: xoauth2_encoded,
-        };
-        
-        // Prova XOAUTH2 per Outlook
-        match client.authenticate("XOAUTH2", authenticator).await {
-            Ok(session) => session,
-            Err(_) => {
-                println!("[IMAP] XOAUTH2 fallito per Outlook");
-                // Per il fallback, dobbiamo riconnetterci perché il client è stato spostato
-                // Per ora restituiamo un errore - in futuro possiamo implementare riconnessione
-                return Err("XOAUTH2 fallito per Outlook e login normale non supportato".to_string());
-            }
+    }
+    
+    Err(SessionError::Authentication(failures))
+}
+
+/// Un singolo tentativo di autenticazione con il metodo indicato
+async fn authenticate(
+    stream: Box<dyn ImapIo>,
+    method: AuthMethod,
+    username: &str,
+    secret: &str,
+    server: &ServerConfig,
+) -> Result<Session<CombinedStream>, AuthError> {
+    let client = async_imap::Client::new(CombinedStream::new(stream));
+    match method {
+        AuthMethod::Login => client
+            .login(username, secret)
+            .await
+            .map_err(|(error, _client)| AuthError::new(method, &error, None)),
+        _ => {
+            let (mechanism, response) = sasl_initial_response(method, username, secret, server);
+            let mut authenticator = SaslAuthenticator::new(response);
+            let result = client.authenticate(mechanism, &mut authenticator).await;
+            result.map_err(|(error, _client)| AuthError::new(method, &error, authenticator.error_payload()))
         }
-    };
+    }
+}
+
+/// Apre la connessione, legge il saluto e le capability annunciate prima dell'autenticazione
+async fn open_connection(server: &ServerConfig) -> Result<(Box<dyn ImapIo>, Vec<String>), String> {
+    let mut reader = BufReader::new(connect_transport(server).await?);
+    // Con STARTTLS il saluto è già stato letto prima dell'handshake
+    if server.security != Security::StartTls {
+        read_greeting(&mut reader).await?;
+    }
+    
+    let capabilities = raw_command(&mut reader, "C1", "CAPABILITY")
+        .await?
+        .iter()
+        .filter_map(|line| {
+            let (name, rest) = line.strip_prefix("* ")?.split_once(' ')?;
+            name.eq_ignore_ascii_case("CAPABILITY").then_some(rest)
+        })
+        .flat_map(|rest| rest.split_whitespace().map(|c| c.to_ascii_uppercase()))
+        .collect();
+    
+    // Il server non invia nulla finché non riceve un comando: il buffer deve essere vuoto
+    if !reader.buffer().is_empty() {
+        return Err("Dati inattesi dal server dopo CAPABILITY".to_string());
+    }
+    Ok((reader.into_inner(), capabilities))
+}
+
+/// Apre la connessione TCP verso il server e la cifra secondo la modalità configurata
+async fn connect_transport(server: &ServerConfig) -> Result<Box<dyn ImapIo>, String> {
+    server.check_security()?;
+
+    let tcp_stream = TcpStream::connect((server.host.as_str(), server.port))
+        .await
+        .map_err(|e| format!("Errore nella connessione TCP a {}:{}: {}", server.host, server.port, e))?;
+
+    match server.security {
+        Security::Tls => Ok(Box::new(tls_connect(&server.host, tcp_stream).await?)),
+        Security::StartTls => {
+            let tcp_stream = starttls(tcp_stream).await?;
+            Ok(Box::new(tls_connect(&server.host, tcp_stream).await?))
+        }
+        Security::Plain => {
+            println!("[IMAP] Connessione non cifrata a {}:{}", server.host, server.port);
+            Ok(Box::new(tcp_stream))
+        }
+    }
+}
+
+/// Handshake TLS sulla connessione TCP, verificando il certificato per `host`
+async fn tls_connect(host: &str, tcp_stream: TcpStream) -> Result<TlsStream<TcpStream>, String> {
+    let tls = native_tls::TlsConnector::builder()
+        .build()
+        .map_err(|e| format!("Errore nella creazione del connector TLS: {}", e))?;
+    
+    TlsConnector::from(tls)
+        .connect(host, tcp_stream)
+        .await
+        .map_err(|e| format!("Errore nella connessione TLS: {}", e))
+}
+
+/// Richiede STARTTLS (RFC 3501, sezione 6.2.1) dopo il saluto del server.
+/// La sessione autenticata riparte dopo l'handshake TLS, senza un nuovo saluto.
+async fn starttls(tcp_stream: TcpStream) -> Result<TcpStream, String> {
+    let mut reader = BufReader::new(tcp_stream);
+    read_greeting(&mut reader).await?;
+    raw_command(&mut reader, "S1", "STARTTLS").await?;
+    
+    // Dati ricevuti prima dell'handshake non sono protetti da TLS e potrebbero essere iniettati:
+    // li rifiutiamo invece di passarli alla sessione cifrata
+    if !reader.buffer().is_empty() {
+        return Err("Dati inattesi dal server dopo STARTTLS".to_string());
+    }
+    Ok(reader.into_inner())
+}
+
+/// Apre una connessione senza autenticarsi e legge le capability annunciate dal server.
+/// Usata dalla discovery per verificare host, porta e cifratura di una configurazione candidata.
+pub(crate) async fn probe_capabilities(server: &ServerConfig) -> Result<Vec<String>, String> {
+    let (stream, capabilities) = open_connection(server).await?;
+    // La chiusura è di cortesia: un errore non invalida le capability già lette
+    let _ = raw_command(&mut BufReader::new(stream), "P1", "LOGOUT").await;
+    Ok(capabilities)
+}
+
+/// Legge il saluto iniziale del server (RFC 3501, sezione 7.1)
+async fn read_greeting<S: tokio::io::AsyncRead + Unpin>(reader: &mut BufReader<S>) -> Result<(), String> {
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .await
+        .map_err(|e| format!("Errore nella lettura del saluto IMAP: {}", e))?;
+    if !line.to_ascii_uppercase().starts_with("* OK") {
+        return Err(format!("Saluto IMAP inatteso: {}", line.trim_end()));
+    }
+    Ok(())
+}
 
-    Ok(session)
+/// Invia un comando IMAP senza passare da async-imap, prima che la sessione esista.
+/// Restituisce le risposte non taggate ricevute prima della risposta OK al comando.
+async fn raw_command<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>(
+    reader: &mut BufReader<S>,
+    tag: &str,
+    command: &str,
+) -> Result<Vec<String>, String> {
+    reader
+        .get_mut()
+        .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+        .await
+        .map_err(|e| format!("Errore nell'invio di {}: {}", command, e))?;
+    
+    let prefix = format!("{} ", tag);
+    let mut untagged = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| format!("Errore nella lettura della risposta a {}: {}", command, e))?;
+        if read == 0 {
+            return Err(format!("Connessione chiusa durante {}", command));
+        }
+        match line.strip_prefix(&prefix) {
+            Some(status) if status.to_ascii_uppercase().starts_with("OK") => return Ok(untagged),
+            Some(status) => return Err(format!("{} rifiutato: {}", command, status.trim_end())),
+            None => untagged.push(line.trim_end().to_string()),
+        }
+    }
 }
 
-/// Sincronizza le cartelle di un account IMAP
+/// Sincronizza le cartelle di un account IMAP.
+/// Restituisce l'albero delle cartelle (solo le radici, con i figli annidati in `children`)
+/// con i contatori letti da STATUS, o da LIST-STATUS quando il server lo supporta.
+/// Un rifiuto delle credenziali viene restituito con i dettagli di ogni metodo provato.
 #[tauri::command]
 pub async fn sync_folders(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
     account_id: String,
-    email: String,
-    provider: String,
-    access_token: String,
-) -> Result<Vec<MailFolder>, String> {
-    println!("[IMAP] Sync folders per account: {} ({})", account_id, email);
-    
-    // Prova a connettere al server IMAP reale
-    match create_imap_session(&provider, &email, &access_token).await {
-        Ok(mut session) => {
-            println!("[IMAP] Sessione IMAP creata con successo, eseguo LIST...");
-            // Esegui LIST per ottenere le cartelle
-            let result = match session.list(None, Some("*")).await {
-                Ok(mut folders_stream) => {
-                    println!("[IMAP] LIST eseguito con successo, leggo cartelle...");
-                    let mut result = Vec::new();
-                    while let Some(folder_result) = folders_stream.next().await {
-                        match folder_result {
-                            Ok(folder) => {
-                                let name = folder.name().to_string();
-                                let path = folder.name().to_string();
-                                println!("[IMAP] Cartella trovata: {} ({})", name, path);
-                                
-                                result.push(MailFolder {
-                                    id: format!("{}-{}", account_id, name.replace("/", "-").replace(" ", "-")),
-                                    account_id: account_id.clone(),
-                                    name: name.clone(),
-                                    path: path.clone(),
-                                    unread_count: 0, // TODO: Ottenere conteggio reale
-                                    total_count: 0,  // TODO: Ottenere conteggio reale
-                                    sync_at: Some(chrono::Utc::now().timestamp_millis()),
-                                });
-                            }
-                            Err(ref e) => {
-                                println!("[IMAP] Errore nel parsing cartella: {}", e);
-                            }
-                        }
-                    }
-                    
-                    println!("[IMAP] Trovate {} cartelle", result.len());
-                    
-                    // Aggiungi cartelle standard se non presenti
-                    let has_inbox = result.iter().any(|f| f.name == "INBOX" || f.path == "INBOX");
-                    if !has_inbox {
-                        println!("[IMAP] INBOX non trovata, aggiungo manualmente");
-                        result.insert(0, MailFolder {
-                            id: format!("{}-inbox", account_id),
-                            account_id: account_id.clone(),
-                            name: "INBOX".to_string(),
-                            path: "INBOX".to_string(),
-                            unread_count: 0,
-                            total_count: 0,
-                            sync_at: Some(chrono::Utc::now().timestamp_millis()),
-                        });
-                    }
-                    
-                    Ok(result)
-                }
-                Err(ref e) => {
-                    println!("[IMAP] Errore nel LIST: {}, uso mock data", e);
-                    // Fallback a mock data
-                    get_mock_folders(&account_id, &provider)
-                }
-            };
-            println!("[IMAP] Logout dalla sessione IMAP...");
-            let _ = session.logout().await;
-            result
-        }
+) -> Result<Vec<MailFolder>, SessionError> {
+    println!("[IMAP] Sync folders per account: {}", account_id);
+    
+    // Usa la sessione del pool, creandola se necessario
+    let mut conn = match pool.acquire(&accounts, &account_id).await {
+        Ok(conn) => conn,
+        // Credenziali rifiutate: i dati di esempio nasconderebbero il problema all'utente
+        Err(e @ SessionError::Authentication(_)) => return Err(e),
         Err(e) => {
             println!("[IMAP] Errore nella connessione: {}, uso mock data", e);
             // Fallback a mock data se la connessione fallisce
-            get_mock_folders(&account_id, &provider)
+            return Ok(get_mock_folders(&account_id)?);
+        }
+    };
+    
+    println!("[IMAP] Sessione IMAP pronta, eseguo LIST...");
+    let mut folders = match list_folders_with_roles(&mut conn, &account_id).await {
+        Ok(folders) => folders,
+        Err(e) => {
+            println!("[IMAP] Errore nel LIST: {}, uso mock data", e);
+            // Fallback a mock data
+            return Ok(get_mock_folders(&account_id)?);
+        }
+    };
+    
+    // Stato di sottoscrizione: LIST-EXTENDED (RETURN SUBSCRIBED) oppure LSUB
+    match subscribed_folders(&mut conn).await {
+        Ok(subscribed) => {
+            for folder in folders.iter_mut() {
+                folder.subscribed = subscribed.contains(&folder.path);
+            }
         }
+        // Una cartella non sottoscritta non blocca la sincronizzazione, una connessione persa sì
+        Err(e) if conn.is_alive() => println!("[IMAP] Errore nella lettura delle sottoscrizioni: {}", e),
+        Err(e) => return Err(e.into()),
     }
-}
-
-fn get_mock_folders(account_id: &str, provider: &str) -> Result<Vec<MailFolder>, String> {
-    Ok(vec![
-        MailFolder {
-            id: format!("{}-inbox", account_id),
-            account_id: account_id.to_string(),
-            name: "INBOX".to_string(),
-            path: "INBOX".to_string(),
-            unread_count: 0,
-            total_count: 0,
-            sync_at: Some(chrono::Utc::now().timestamp_millis()),
-        },
-        MailFolder {
-            id: format!("{}-sent", account_id),
-            account_id: account_id.to_string(),
-            name: "Sent".to_string(),
-            path: if provider == "gmail" {
-                "[Gmail]/Sent Mail".to_string()
-            } else {
-                "Sent Items".to_string()
-            },
-            unread_count: 0,
-            total_count: 0,
-            sync_at: Some(chrono::Utc::now().timestamp_millis()),
-        },
-        MailFolder {
-            id: format!("{}-drafts", account_id),
-            account_id: account_id.to_string(),
-            name: "Drafts".to_string(),
-            path: if provider == "gmail" {
-                "[Gmail]/Drafts".to_string()
-            } else {
-                "Drafts".to_string()
-            },
-            unread_count: 0,
-            total_count: 0,
-            sync_at: Some(chrono::Utc::now().timestamp_millis()),
-        },
-        MailFolder {
-            id: format!("{}-archive", account_id),
-            account_id: account_id.to_string(),
-            name: "Archive".to_string(),
-            path: if provider == "gmail" {
-                "[Gmail]/All Mail".to_string()
-            } else {
-                "Archive".to_string()
+    
+    // Contatori: LIST-STATUS in un solo round trip, STATUS per le cartelle mancanti
+    let mut statuses = if conn.has_capability("LIST-STATUS") {
+        list_status(&mut conn).await?
+    } else {
+        HashMap::new()
+    };
+    
+    for folder in folders.iter_mut().filter(|f| f.selectable) {
+        let status = match statuses.remove(&folder.path) {
+            Some(status) => status,
+            None => match folder_status(&mut conn, &folder.path).await {
+                Ok(status) => status,
+                Err(e) if conn.is_alive() => {
+                    println!("[IMAP] Errore nello STATUS di {}: {}", folder.path, e);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
             },
-            unread_count: 0,
-            total_count: 0,
-            sync_at: Some(chrono::Utc::now().timestamp_millis()),
-        },
-    ])
+        };
+        
+        folder.total_count = status.messages.unwrap_or(0) as i32;
+        folder.unread_count = status.unseen.unwrap_or(0) as i32;
+        folder.uid_next = status.uid_next;
+        folder.uid_validity = status.uid_validity;
+    }
+    
+    Ok(build_folder_tree(folders))
 }
 
-/// Sincronizza i messaggi di una cartella IMAP
-#[tauri::command]
-pub async fn sync_messages(
-    account_id: String,
-    folder_id: String,
-    folder_path: String,
-    email: String,
-    provider: String,
-    access_token: String,
-    since: Option<i64>, // Timestamp opzionale per sincronizzazione incrementale
-) -> Result<Vec<MailMessage>, String> {
-    println!(
-        "[IMAP] Sync messages per cartella: {} ({})",
-        folder_id, folder_path
-    );
+/// Elenca le cartelle (INBOX inclusa) con il ruolo special-use assegnato
+async fn list_folders_with_roles(conn: &mut PooledConnection, account_id: &str) -> Result<Vec<MailFolder>, String> {
+    let mut folders = list_folders(conn, account_id).await?;
+    println!("[IMAP] Trovate {} cartelle", folders.len());
     
-    if let Some(since_ts) = since {
-        println!("[IMAP] Sincronizzazione incrementale da: {}", since_ts);
-    }
-    
-    // Prova a connettere al server IMAP reale
-    match create_imap_session(&provider, &email, &access_token).await {
-        Ok(mut session) => {
-            println!("[IMAP] Sessione IMAP creata con successo per sync_messages");
-            // Seleziona la cartella
-            println!("[IMAP] Selezione cartella: {}", folder_path);
-            match session.select(&folder_path).await {
-                Ok(_) => {
-                    println!("[IMAP] Cartella selezionata con successo");
-                    // Costruisci query SEARCH
-                    let search_query = if let Some(since_ts) = since {
-                        let since_date = chrono::DateTime::from_timestamp(since_ts / 1000, 0)
-                            .ok_or_else(|| "Data non valida".to_string())?;
-                        format!("SINCE {}", since_date.format("%d-%b-%Y"))
-                    } else {
-                        "ALL".to_string()
-                    };
-                    
-                    println!("[IMAP] Eseguo SEARCH con query: {}", search_query);
-                    // Esegui SEARCH
-                    match session.search(search_query).await {
-                        Ok(uids) => {
-                            println!("[IMAP] SEARCH completato, trovati {} UID", uids.len());
-                            if uids.is_empty() {
-                                println!("[IMAP] Nessun messaggio trovato");
-                                let _ = session.logout().await;
-                                return Ok(vec![]);
-                            }
-                            
-                            let mut messages = Vec::new();
-                            
-                            // Converti HashSet in Vec e ordina
-                            let mut uid_vec: Vec<u32> = uids.into_iter().collect();
-                            uid_vec.sort();
-                            
-                            println!("[IMAP] Recupero {} messaggi in batch da 50", uid_vec.len());
-                            // Fetch messaggi in batch (max 50 alla volta)
-                            for chunk in uid_vec.chunks(50) {
-                                let uid_set: Vec<String> = chunk.iter().map(|u| u.to_string()).collect();
-                                let fetch_query = format!("{} (RFC822)", uid_set.join(","));
-                                
-                                match session.fetch(fetch_query, "RFC822").await {
-                                    Ok(mut fetched_stream) => {
-                                        while let Some(msg_result) = fetched_stream.next().await {
-                                            match msg_result {
-                                                Ok(msg) => {
-                                                    if let Some(body) = msg.body() {
-                                                        match parse_mail(body) {
-                                                            Ok(parsed) => {
-                                                                let subject = parsed.headers
-                                                                    .get_first_value("Subject")
-                                                                    .unwrap_or_else(|| "No Subject".to_string());
-                                                                let from = parsed.headers
-                                                                    .get_first_value("From")
-                                                                    .unwrap_or_else(|| "unknown@example.com".to_string());
-                                                                let date_str = parsed.headers
-                                                                    .get_first_value("Date")
-                                                                    .unwrap_or_else(|| String::new());
-                                                                
-                                                                let date = parse_mail_date(&date_str)
-                                                                    .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
-                                                                
-                                                                let text = parsed.get_body().ok();
-                                                                let html = parsed.subparts.iter()
-                                                                    .find(|p| p.ctype.mimetype == "text/html")
-                                                                    .and_then(|p| p.get_body().ok());
-                                                                
-                                                                let uid = msg.uid.unwrap_or(0);
-                                                                let message_id = parsed.headers
-                                                                    .get_first_value("Message-ID")
-                                                                    .unwrap_or_else(|| format!("msg-{}", uid));
-                                                                
-                                                                // Estrai flags
-                                                                let flags_vec: Vec<_> = msg.flags().collect();
-                                                                let flags = flags_vec.iter()
-                                                                    .map(|f| format!("{:?}", f))
-                                                                    .collect();
-                                                                
-                                                                let is_read = flags_vec.iter().any(|f| matches!(f, async_imap::types::Flag::Seen));
-                                                                let is_starred = flags_vec.iter().any(|f| matches!(f, async_imap::types::Flag::Flagged));
-                                                                
-                                                                println!("[IMAP] Messaggio parsato: {} (UID: {})", subject, uid);
-                                                                
-                                                                messages.push(MailMessage {
-                                                                    id: format!("{}-msg-{}", account_id, uid),
-                                                                    account_id: account_id.clone(),
-                                                                    folder_id: folder_id.clone(),
-                                                                    uid,
-                                                                    message_id,
-                                                                    subject,
-                                                                    from_name: None,
-                                                                    from_address: from,
-                                                                    to_addresses: vec![],
-                                                                    cc_addresses: None,
-                                                                    bcc_addresses: None,
-                                                                    date,
-                                                                    text,
-                                                                    html,
-                                                                    flags,
-                                                                    is_read,
-                                                                    is_starred,
-                                                                    is_important: false,
-                                                                    thread_id: None,
-                                                                    in_reply_to: None,
-                                                                    references: None,
-                                                                    synced_at: chrono::Utc::now().timestamp_millis(),
-                                                                });
-                                                            }
-                                                            Err(e) => {
-                                                                println!("[IMAP] Errore nel parsing MIME: {}", e);
-                                                            }
-                                                        }
-                                                    } else {
-                                                        println!("[IMAP] Messaggio senza body");
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    println!("[IMAP] Errore nel fetch del messaggio: {}", e);
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        println!("[IMAP] Errore nel FETCH: {}", e);
-                                    }
-                                }
-                            }
-                            
-                            println!("[IMAP] Recuperati {} messaggi totali", messages.len());
-                            let _ = session.logout().await;
-                            Ok(messages)
-                        }
-                        Err(e) => {
-                            println!("[IMAP] Errore nel SEARCH: {}", e);
-                            let _ = session.logout().await;
-                            Err(format!("Errore nel SEARCH: {}", e))
-                        }
+    // Aggiungi cartelle standard se non presenti
+    let has_inbox = folders.iter().any(|f| f.path.eq_ignore_ascii_case("INBOX"));
+    if !has_inbox {
+        println!("[IMAP] INBOX non trovata, aggiungo manualmente");
+        folders.insert(0, MailFolder::new(account_id, "INBOX", None));
+    }
+    
+    // Se LIST non riporta attributi special-use li richiediamo esplicitamente (SPECIAL-USE o XLIST)
+    if !folders.iter().any(|f| role_from_attributes(&f.attributes).is_some()) {
+        for (path, attributes) in special_use_attributes(conn).await? {
+            if let Some(folder) = folders.iter_mut().find(|f| f.path == path) {
+                for attribute in attributes {
+                    if !folder.attributes.iter().any(|a| a.eq_ignore_ascii_case(&attribute)) {
+                        folder.attributes.push(attribute);
                     }
                 }
-                Err(e) => {
-                    println!("[IMAP] Errore nella selezione cartella: {}", e);
-                    let _ = session.logout().await;
-                    Err(format!("Errore nella selezione cartella: {}", e))
-                }
             }
         }
-        Err(e) => {
-            println!("[IMAP] Errore nella connessione: {}", e);
-            Err(format!("Errore nella connessione: {}", e))
-        }
     }
+    assign_roles(&mut folders);
+    
+    Ok(folders)
 }
 
-fn parse_mail_date(date_str: &str) -> Option<i64> {
-    // Prova a parsare la data con chrono
-    chrono::DateTime::parse_from_rfc2822(date_str)
-        .or_else(|_| chrono::DateTime::parse_from_rfc3339(date_str))
-        .map(|dt| dt.timestamp_millis())
-        .ok()
+/// Percorso della cartella con il ruolo indicato, se presente sul server
+async fn folder_path_for_role(
+    conn: &mut PooledConnection,
+    account_id: &str,
+    role: FolderRole,
+) -> Result<Option<String>, String> {
+    let folders = list_folders_with_roles(conn, account_id).await?;
+    Ok(folders.into_iter().find(|f| f.role == Some(role)).map(|f| f.path))
 }
 
-/// Marca un messaggio come letto/non letto sul server IMAP
-#[tauri::command]
-pub async fn mark_message_read(
-    _account_id: String,
-    folder_path: String,
-    uid: u32,
-    read: bool,
-    email: String,
-    provider: String,
-    access_token: String,
-) -> Result<(), String> {
-    println!(
-        "[IMAP] Mark message {} as {} in folder {}",
-        uid,
-        if read { "read" } else { "unread" },
-        folder_path
-    );
-    
-    match create_imap_session(&provider, &email, &access_token).await {
-        Ok(mut session) => {
-            let select_result = session.select(&folder_path).await;
-            let result = match select_result {
-                Ok(_) => {
-                    let flag = if read { "+FLAGS (\\Seen)" } else { "-FLAGS (\\Seen)" };
-                    let store_result = session.store(format!("{}", uid), flag).await;
-                    match store_result {
-                        Ok(mut stream) => {
-                            // Consuma lo stream completamente
-                            while let Some(_) = stream.next().await {}
-                            Ok(())
-                        }
-                        Err(e) => Err(format!("Errore nel STORE: {}", e))
+/// Esegue LIST "" "*" e converte le risposte in cartelle (ancora non annidate)
+async fn list_folders(conn: &mut PooledConnection, account_id: &str) -> Result<Vec<MailFolder>, String> {
+    // Senza UTF8=ACCEPT i nomi arrivano in UTF-7 modificato (es. "&AMg-" per "È")
+    let utf8 = conn.has_capability("UTF8=ACCEPT");
+    let result = match conn.session()?.list(None, Some("*")).await {
+        Ok(mut folders_stream) => {
+            let mut result = Vec::new();
+            while let Some(folder_result) = folders_stream.next().await {
+                match folder_result {
+                    Ok(folder) => {
+                        let path = if utf8 {
+                            folder.name().to_string()
+                        } else {
+                            decode_mailbox_name(folder.name())
+                        };
+                        let mut mail_folder = MailFolder::new(account_id, &path, folder.delimiter());
+                        mail_folder.attributes = folder.attributes().iter().map(attribute_name).collect();
+                        mail_folder.selectable = !mail_folder.attributes.iter().any(|a| {
+                            a.eq_ignore_ascii_case("\\Noselect") || a.eq_ignore_ascii_case("\\NonExistent")
+                        });
+                        mail_folder.has_children = mail_folder.attributes.iter().any(|a| a.eq_ignore_ascii_case("\\HasChildren"));
+                        println!("[IMAP] Cartella trovata: {} ({:?})", mail_folder.path, mail_folder.attributes);
+                        result.push(mail_folder);
+                    }
+                    Err(ref e) => {
+                        println!("[IMAP] Errore nel parsing cartella: {}", e);
                     }
                 }
-                Err(e) => Err(format!("Errore nella selezione cartella: {}", e))
-            };
-            let _ = session.logout().await;
-            result
+            }
+            Ok(result)
+        }
+        Err(e) => Err(e),
+    };
+    result.map_err(|e| conn.check(e).to_string())
+}
+
+/// LIST-STATUS (RFC 5819): i contatori arrivano come risposte STATUS non taggate del comando.
+/// Se il server rifiuta il comando restituisce una mappa vuota; fallisce solo se la connessione è persa.
+async fn list_status(conn: &mut PooledConnection) -> Result<HashMap<String, FolderStatus>, String> {
+    let command = format!("LIST \"\" \"*\" RETURN (STATUS {})", FOLDER_STATUS_ITEMS);
+    let mut responses = Vec::new();
+    let result = run_command_with(conn.session()?, &command, |response| {
+        if let Response::MailboxData(MailboxDatum::Status { mailbox, status }) = response {
+            responses.push((mailbox.to_string(), status_from_attributes(status)));
+        }
+    })
+    .await;
+    if let Err(e) = result {
+        let e = conn.check(e);
+        if !conn.is_alive() {
+            return Err(format!("Errore nel LIST-STATUS: {}", e));
         }
-        Err(e) => Err(e)
+        println!("[IMAP] LIST-STATUS fallito: {}, uso STATUS per ogni cartella", e);
+        return Ok(HashMap::new());
     }
+    
+    Ok(responses
+        .into_iter()
+        .map(|(mailbox, status)| (conn.decode_mailbox(&mailbox), status))
+        .collect())
 }
 
-/// Sposta un messaggio da una cartella all'altra
-#[tauri::command]
-pub async fn move_message(
-    _account_id: String,
-    folder_path: String,
-    uid: u32,
-    target_folder: String,
-    email: String,
-    provider: String,
-    access_token: String,
-) -> Result<(), String> {
-    println!(
-        "[IMAP] Move message {} from {} to {}",
-        uid, folder_path, target_folder
-    );
+/// STATUS (MESSAGES UNSEEN UIDNEXT UIDVALIDITY) su una singola cartella
+async fn folder_status(conn: &mut PooledConnection, path: &str) -> Result<FolderStatus, String> {
+    let encoded = conn.encode_mailbox(path);
+    let result = conn.session()?.status(&encoded, FOLDER_STATUS_ITEMS).await;
+    match result {
+        Ok(mailbox) => Ok(FolderStatus {
+            messages: Some(mailbox.exists),
+            unseen: mailbox.unseen,
+            uid_next: mailbox.uid_next,
+            uid_validity: mailbox.uid_validity,
+        }),
+        Err(e) => Err(conn.check(e).to_string()),
+    }
+}
+
+fn status_from_attributes(attributes: &[StatusAttribute]) -> FolderStatus {
+    let mut status = FolderStatus::default();
+    for attribute in attributes {
+        match attribute {
+            StatusAttribute::Messages(n) => status.messages = Some(*n),
+            StatusAttribute::Unseen(n) => status.unseen = Some(*n),
+            StatusAttribute::UidNext(n) => status.uid_next = Some(*n),
+            StatusAttribute::UidValidity(n) => status.uid_validity = Some(*n),
+            _ => {}
+        }
+    }
+    status
+}
+
+/// Nome testuale di un attributo LIST (es. \Noselect)
+pub(crate) fn attribute_name(attribute: &NameAttribute) -> String {
+    match attribute {
+        NameAttribute::NoInferiors => "\\Noinferiors".to_string(),
+        NameAttribute::NoSelect => "\\Noselect".to_string(),
+        NameAttribute::Marked => "\\Marked".to_string(),
+        NameAttribute::Unmarked => "\\Unmarked".to_string(),
+        NameAttribute::All => "\\All".to_string(),
+        NameAttribute::Archive => "\\Archive".to_string(),
+        NameAttribute::Drafts => "\\Drafts".to_string(),
+        NameAttribute::Flagged => "\\Flagged".to_string(),
+        NameAttribute::Junk => "\\Junk".to_string(),
+        NameAttribute::Sent => "\\Sent".to_string(),
+        NameAttribute::Trash => "\\Trash".to_string(),
+        NameAttribute::Extension(name) => name.to_string(),
+        other => format!("\\{:?}", other),
+    }
+}
+
+/// Annida le cartelle sotto il rispettivo `parent_path`.
+/// Le cartelle il cui genitore non è stato restituito da LIST diventano radici.
+fn build_folder_tree(folders: Vec<MailFolder>) -> Vec<MailFolder> {
+    let paths: HashSet<String> = folders.iter().map(|f| f.path.clone()).collect();
+    let mut children: HashMap<String, Vec<MailFolder>> = HashMap::new();
+    let mut roots = Vec::new();
     
-    match create_imap_session(&provider, &email, &access_token).await {
-        Ok(mut session) => {
-            let select_result = session.select(&folder_path).await;
-            let result = match select_result {
-                Ok(_) => {
-                    // COPY il messaggio
-                    let copy_result = session.copy(format!("{}", uid), &target_folder).await;
-                    match copy_result {
-                        Ok(_) => {
-                            // Marca come \Deleted nella cartella originale
-                            let store_result = session.store(format!("{}", uid), "+FLAGS (\\Deleted)").await;
-                            match store_result {
-                                Ok(mut stream) => {
-                                    // Consuma lo stream completamente
-                                    while let Some(_) = stream.next().await {}
-                                    Ok(())
-                                }
-                                Err(e) => Err(format!("Errore nel STORE: {}", e))
-                            }
-                        }
-                        Err(e) => Err(format!("Errore nel COPY: {}", e))
-                    }
-                }
-                Err(e) => Err(format!("Errore nella selezione cartella: {}", e))
-            };
-            let _ = session.logout().await;
-            result
+    for folder in folders {
+        match &folder.parent_path {
+            Some(parent) if paths.contains(parent) => {
+                children.entry(parent.clone()).or_default().push(folder);
+            }
+            _ => roots.push(folder),
         }
-        Err(e) => Err(e)
     }
+    
+    for root in roots.iter_mut() {
+        attach_children(root, &mut children);
+    }
+    roots
 }
 
-/// Elimina un messaggio dal server IMAP
-#[tauri::command]
-pub async fn delete_message(
-    _account_id: String,
+fn attach_children(folder: &mut MailFolder, children: &mut HashMap<String, Vec<MailFolder>>) {
+    if let Some(mut folder_children) = children.remove(&folder.path) {
+        for child in folder_children.iter_mut() {
+            attach_children(child, children);
+        }
+        folder.has_children = true;
+        folder.children = folder_children;
+    }
+}
+
+/// Percorsi delle cartelle sottoscritte, con `LIST "" "*" RETURN (SUBSCRIBED)` (RFC 5258)
+/// oppure con LSUB
+async fn subscribed_folders(conn: &mut PooledConnection) -> Result<HashSet<String>, String> {
+    let command = if conn.has_capability("LIST-EXTENDED") {
+        "LIST \"\" \"*\" RETURN (SUBSCRIBED)"
+    } else {
+        "LSUB \"\" \"*\""
+    };
+    
+    // LSUB elenca solo le cartelle sottoscritte, LIST-EXTENDED le marca con \Subscribed
+    let lsub = command.starts_with("LSUB");
+    let mut subscribed = Vec::new();
+    let result = run_command_with(conn.session()?, command, |response| {
+        subscribed.extend(
+            list_entry(response)
+                .filter(|(_, attributes)| lsub || attributes.iter().any(|a| a.eq_ignore_ascii_case("\\Subscribed")))
+                .map(|(path, _)| path),
+        );
+    })
+    .await;
+    result.map_err(|e| conn.check(e).to_string())?;
+    
+    Ok(subscribed.iter().map(|path| conn.decode_mailbox(path)).collect())
+}
+
+/// Delimitatore di gerarchia del server, letto con LIST "" ""
+async fn hierarchy_delimiter(conn: &mut PooledConnection) -> Result<Option<String>, String> {
+    let result = match conn.session()?.list(Some(""), Some("")).await {
+        Ok(mut stream) => {
+            let mut delimiter = None;
+            while let Some(name) = stream.next().await {
+                if let Ok(name) = name {
+                    delimiter = name.delimiter().map(|d| d.to_string());
+                }
+            }
+            Ok(delimiter)
+        }
+        Err(e) => Err(e),
+    };
+    result.map_err(|e| conn.check(e).to_string())
+}
+
+/// Costruisce il percorso completo di una cartella.
+/// `name` può contenere "/" per indicare sottolivelli, convertiti nel delimitatore del server.
+fn folder_path_for(parent_path: Option<&str>, name: &str, delimiter: Option<&str>) -> Result<String, String> {
+    let segments: Vec<&str> = name.split('/').map(|s| s.trim()).collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        return Err(format!("Nome cartella non valido: {}", name));
+    }
+    
+    let delimiter = match delimiter {
+        Some(delimiter) => delimiter,
+        None if segments.len() == 1 && parent_path.is_none() => return Ok(segments[0].to_string()),
+        None => return Err("Il server non supporta cartelle annidate".to_string()),
+    };
+    
+    if delimiter != "/" && segments.iter().any(|s| s.contains(delimiter)) {
+        return Err(format!("Il nome non può contenere il delimitatore \"{}\"", delimiter));
+    }
+    
+    let name = segments.join(delimiter);
+    Ok(match parent_path {
+        Some(parent) if !parent.is_empty() => format!("{}{}{}", parent, delimiter, name),
+        _ => name,
+    })
+}
+
+/// Crea una cartella (es. "Projects/ACME") e la sottoscrive
+#[tauri::command]
+pub async fn create_folder(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    parent_path: Option<String>,
+    name: String,
+) -> Result<MailFolder, String> {
+    println!("[IMAP] Create folder {} in {:?}", name, parent_path);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let delimiter = hierarchy_delimiter(&mut conn).await?;
+    let path = folder_path_for(parent_path.as_deref(), &name, delimiter.as_deref())?;
+    
+    let encoded = conn.encode_mailbox(&path);
+    let create_result = conn.session()?.create(&encoded).await;
+    if let Err(e) = create_result {
+        return Err(format!("Errore nel CREATE: {}", conn.check(e)));
+    }
+    
+    let subscribe_result = conn.session()?.subscribe(&encoded).await;
+    let subscribed = match subscribe_result {
+        Ok(()) => true,
+        Err(e) => {
+            println!("[IMAP] Errore nel SUBSCRIBE di {}: {}", path, conn.check(e));
+            false
+        }
+    };
+    
+    Ok(MailFolder {
+        subscribed,
+        ..MailFolder::new(&account_id, &path, delimiter.as_deref())
+    })
+}
+
+/// Rinomina o sposta una cartella sotto un altro genitore
+#[tauri::command]
+pub async fn rename_folder(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
     folder_path: String,
-    uid: u32,
-    email: String,
-    provider: String,
-    access_token: String,
+    new_parent_path: Option<String>,
+    new_name: String,
+) -> Result<MailFolder, String> {
+    println!("[IMAP] Rename folder {} -> {} in {:?}", folder_path, new_name, new_parent_path);
+    
+    // RENAME INBOX sposta i messaggi invece di rinominare la cartella
+    if folder_path.eq_ignore_ascii_case("INBOX") {
+        return Err("La cartella INBOX non può essere rinominata".to_string());
+    }
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let delimiter = hierarchy_delimiter(&mut conn).await?;
+    let new_path = folder_path_for(new_parent_path.as_deref(), &new_name, delimiter.as_deref())?;
+    
+    conn.release_mailbox(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nel rilascio della cartella: {}", e))?;
+    
+    let encoded_from = conn.encode_mailbox(&folder_path);
+    let encoded_to = conn.encode_mailbox(&new_path);
+    let rename_result = conn.session()?.rename(&encoded_from, &encoded_to).await;
+    if let Err(e) = rename_result {
+        return Err(format!("Errore nel RENAME: {}", conn.check(e)));
+    }
+    
+    // La sottoscrizione resta legata al vecchio nome su molti server
+    let unsubscribe_result = conn.session()?.unsubscribe(&encoded_from).await;
+    if let Err(e) = unsubscribe_result {
+        println!("[IMAP] Errore nell'UNSUBSCRIBE di {}: {}", folder_path, conn.check(e));
+    }
+    let subscribe_result = conn.session()?.subscribe(&encoded_to).await;
+    let subscribed = match subscribe_result {
+        Ok(()) => true,
+        Err(e) => {
+            println!("[IMAP] Errore nel SUBSCRIBE di {}: {}", new_path, conn.check(e));
+            false
+        }
+    };
+    
+    Ok(MailFolder {
+        subscribed,
+        ..MailFolder::new(&account_id, &new_path, delimiter.as_deref())
+    })
+}
+
+/// Elimina una cartella. Rifiuta INBOX e le cartelle speciali (Sent, Trash, ...).
+#[tauri::command]
+pub async fn delete_folder(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+) -> Result<(), String> {
+    println!("[IMAP] Delete folder {}", folder_path);
+    
+    if folder_path.eq_ignore_ascii_case("INBOX") {
+        return Err("La cartella INBOX non può essere eliminata".to_string());
+    }
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    
+    // Verifica il ruolo della cartella con gli stessi criteri di sync_folders
+    let folder = list_folders_with_roles(&mut conn, &account_id)
+        .await?
+        .into_iter()
+        .find(|f| f.path == folder_path)
+        .ok_or_else(|| format!("Cartella non trovata: {}", folder_path))?;
+    if let Some(role) = folder.role {
+        return Err(format!("La cartella speciale {} ({:?}) non può essere eliminata", folder_path, role));
+    }
+    
+    conn.release_mailbox(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nel rilascio della cartella: {}", e))?;
+    
+    let encoded = conn.encode_mailbox(&folder_path);
+    let unsubscribe_result = conn.session()?.unsubscribe(&encoded).await;
+    if let Err(e) = unsubscribe_result {
+        println!("[IMAP] Errore nell'UNSUBSCRIBE di {}: {}", folder_path, conn.check(e));
+    }
+    
+    let delete_result = conn.session()?.delete(&encoded).await;
+    delete_result.map_err(|e| format!("Errore nel DELETE: {}", conn.check(e)))
+}
+
+/// Sottoscrive una cartella (visibile nei client che mostrano solo le cartelle sottoscritte)
+#[tauri::command]
+pub async fn subscribe_folder(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+) -> Result<(), String> {
+    println!("[IMAP] Subscribe folder {}", folder_path);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let encoded = conn.encode_mailbox(&folder_path);
+    let result = conn.session()?.subscribe(&encoded).await;
+    result.map_err(|e| format!("Errore nel SUBSCRIBE: {}", conn.check(e)))
+}
+
+/// Annulla la sottoscrizione di una cartella
+#[tauri::command]
+pub async fn unsubscribe_folder(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+) -> Result<(), String> {
+    println!("[IMAP] Unsubscribe folder {}", folder_path);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let encoded = conn.encode_mailbox(&folder_path);
+    let result = conn.session()?.unsubscribe(&encoded).await;
+    result.map_err(|e| format!("Errore nell'UNSUBSCRIBE: {}", conn.check(e)))
+}
+
+fn get_mock_folders(account_id: &str) -> Result<Vec<MailFolder>, String> {
+    let mock_folder = |id: &str, path: &str, role: FolderRole| MailFolder {
+        id: format!("{}-{}", account_id, id),
+        role: Some(role),
+        ..MailFolder::new(account_id, path, None)
+    };
+    
+    Ok(vec![
+        mock_folder("inbox", "INBOX", FolderRole::Inbox),
+        mock_folder("sent", "Sent", FolderRole::Sent),
+        mock_folder("drafts", "Drafts", FolderRole::Drafts),
+        mock_folder("archive", "Archive", FolderRole::Archive),
+    ])
+}
+
+/// Sincronizza i messaggi di una cartella IMAP.
+///
+/// Senza `state` (o se UIDVALIDITY è cambiato) esegue una sincronizzazione completa,
+/// eventualmente limitata da `since`. Altrimenti restituisce solo le differenze:
+/// nuovi messaggi, flag modificati (CONDSTORE `CHANGEDSINCE`) e UID rimossi
+/// (QRESYNC `VANISHED`, oppure confronto con `known_uids` se il server non lo supporta).
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sync_messages(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_id: String,
+    folder_path: String,
+    since: Option<i64>, // Timestamp opzionale per limitare la prima sincronizzazione
+    state: Option<FolderSyncState>,
+    known_uids: Option<Vec<u32>>,
+) -> Result<SyncDelta, String> {
+    println!(
+        "[IMAP] Sync messages per cartella: {} ({})",
+        folder_id, folder_path
+    );
+    
+    let mut conn = pool
+        .acquire(&accounts, &account_id)
+        .await
+        .map_err(|e| format!("Errore nella connessione: {}", e))?;
+    sync_mailbox(&mut conn, &account_id, &folder_id, &folder_path, since, state, known_uids).await
+}
+
+/// Corpo di `sync_messages` sulla connessione già acquisita dal pool
+async fn sync_mailbox(
+    conn: &mut PooledConnection,
+    account_id: &str,
+    folder_id: &str,
+    folder_path: &str,
+    since: Option<i64>,
+    state: Option<FolderSyncState>,
+    known_uids: Option<Vec<u32>>,
+) -> Result<SyncDelta, String> {
+    // SELECT esplicita per leggere UIDVALIDITY, UIDNEXT e HIGHESTMODSEQ correnti
+    println!("[IMAP] Selezione cartella: {}", folder_path);
+    let mailbox = conn.select_mailbox(folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    
+    let uid_validity = mailbox.uid_validity.unwrap_or(0);
+    let previous = match state {
+        Some(state) if state.uid_validity == uid_validity => Some(state),
+        Some(state) => {
+            println!(
+                "[IMAP] UIDVALIDITY cambiato ({} -> {}), sincronizzazione completa",
+                state.uid_validity, uid_validity
+            );
+            None
+        }
+        None => None,
+    };
+    
+    let previous_uid_next = previous.as_ref().map(|p| p.uid_next).unwrap_or(1);
+    let mut delta = SyncDelta {
+        state: FolderSyncState {
+            uid_validity,
+            uid_next: mailbox.uid_next.unwrap_or(0),
+            highest_modseq: mailbox.highest_modseq,
+        },
+        full_resync: previous.is_none(),
+        new_messages: Vec::new(),
+        changed_flags: Vec::new(),
+        vanished: Vec::new(),
+    };
+    
+    // Se il download dei nuovi messaggi fallisce la sincronizzazione fallisce:
+    // restituire il nuovo UIDNEXT farebbe saltare per sempre gli UID non scaricati
+    match previous {
+        None => {
+            let search_query = search_criteria(since)?;
+            let uids = uid_search(conn, &search_query).await?;
+            delta.new_messages = fetch_messages(conn, account_id, folder_id, &uids).await?;
+        }
+        Some(previous) => {
+            println!(
+                "[IMAP] Sincronizzazione incrementale da UIDNEXT {} (MODSEQ {:?})",
+                previous.uid_next, previous.highest_modseq
+            );
+            
+            // Nuovi messaggi: UID >= UIDNEXT precedente
+            if delta.state.uid_next == 0 || delta.state.uid_next > previous.uid_next {
+                let uids: Vec<u32> = uid_search(conn, &format!("UID {}:*", previous.uid_next))
+                    .await?
+                    .into_iter()
+                    // "n:*" include sempre l'ultimo messaggio, anche se ha UID < n
+                    .filter(|uid| *uid >= previous.uid_next)
+                    .collect();
+                delta.new_messages = fetch_messages(conn, account_id, folder_id, &uids).await?;
+            }
+            
+            // Messaggi già noti: flag modificati e UID rimossi
+            if previous.uid_next > 1 {
+                let known_range = format!("1:{}", previous.uid_next - 1);
+                sync_known_messages(conn, &known_range, &previous, known_uids, &mut delta).await?;
+            }
+        }
+    }
+    
+    // Alcuni server non restituiscono UIDNEXT: lo ricaviamo dall'UID più alto
+    if delta.state.uid_next == 0 {
+        delta.state.uid_next = delta.new_messages
+            .iter()
+            .map(|m| m.uid + 1)
+            .max()
+            .unwrap_or(previous_uid_next);
+    }
+    
+    println!(
+        "[IMAP] Sync completata: {} nuovi, {} flag modificati, {} rimossi",
+        delta.new_messages.len(),
+        delta.changed_flags.len(),
+        delta.vanished.len()
+    );
+    Ok(delta)
+}
+
+/// Aggiorna flag e messaggi rimossi nell'intervallo di UID già noto al frontend
+async fn sync_known_messages(
+    conn: &mut PooledConnection,
+    known_range: &str,
+    previous: &FolderSyncState,
+    known_uids: Option<Vec<u32>>,
+    delta: &mut SyncDelta,
 ) -> Result<(), String> {
-    println!("[IMAP] Delete message {} from folder {}", uid, folder_path);
-    
-    match create_imap_session(&provider, &email, &access_token).await {
-        Ok(mut session) => {
-            let select_result = session.select(&folder_path).await;
-            let result = match select_result {
-                Ok(_) => {
-                    // Marca come \Deleted
-                    let store_result = session.store(format!("{}", uid), "+FLAGS (\\Deleted)").await;
-                    match store_result {
-                        Ok(mut stream) => {
-                            // Consuma lo stream completamente prima di chiamare expunge
-                            while let Some(_) = stream.next().await {}
-                            Ok(())
+    let qresync = conn.has_capability("QRESYNC");
+    let condstore = qresync || conn.has_capability("CONDSTORE");
+    
+    let modseq = match (previous.highest_modseq, condstore) {
+        (Some(modseq), true) => Some(modseq),
+        _ => None,
+    };
+    
+    let query = match modseq {
+        Some(modseq) if qresync => format!("(UID FLAGS) (CHANGEDSINCE {} VANISHED)", modseq),
+        Some(modseq) => format!("(UID FLAGS) (CHANGEDSINCE {})", modseq),
+        None => "(UID FLAGS)".to_string(),
+    };
+    
+    // Scarta risposte non richieste accumulate da comandi precedenti
+    while conn.session()?.unsolicited_responses.try_recv().is_ok() {}
+    
+    let fetch_result = match conn.session()?.uid_fetch(known_range, &query).await {
+        Ok(mut stream) => {
+            while let Some(msg_result) = stream.next().await {
+                match msg_result {
+                    Ok(msg) => {
+                        if let Some(update) = parse_flag_update(&msg) {
+                            delta.changed_flags.push(update);
+                        }
+                    }
+                    Err(e) => println!("[IMAP] Errore nel fetch dei flag: {}", e),
+                }
+            }
+            Ok(())
+        }
+        Err(e) => Err(e),
+    };
+    fetch_result.map_err(|e| format!("Errore nel UID FETCH dei flag: {}", conn.check(e)))?;
+    
+    if modseq.is_some() && qresync {
+        // Con QRESYNC il server riporta gli UID rimossi come VANISHED (EARLIER).
+        // async-imap non ha una variante per VANISHED: arriva come risposta generica.
+        while let Ok(response) = conn.session()?.unsolicited_responses.try_recv() {
+            if let UnsolicitedResponse::Other(data) = response {
+                if let Response::Vanished { uids, .. } = data.parsed() {
+                    delta.vanished.extend(uids.iter().cloned().flatten());
+                }
+            }
+        }
+    } else if let Some(known_uids) = known_uids {
+        // Fallback: confronto tra gli UID noti al frontend e quelli ancora presenti sul server
+        let existing: HashSet<u32> = uid_search(conn, &format!("UID {}", known_range))
+            .await?
+            .into_iter()
+            .collect();
+        delta.vanished = known_uids
+            .into_iter()
+            .filter(|uid| *uid < previous.uid_next && !existing.contains(uid))
+            .collect();
+    }
+    
+    delta.vanished.sort();
+    Ok(())
+}
+
+/// Esegue UID SEARCH e restituisce gli UID ordinati
+async fn uid_search(conn: &mut PooledConnection, query: &str) -> Result<Vec<u32>, String> {
+    println!("[IMAP] Eseguo UID SEARCH con query: {}", query);
+    // UID SEARCH restituisce UID e non numeri di sequenza
+    let result = conn.session()?.uid_search(query).await;
+    match result {
+        Ok(uids) => {
+            let mut uids: Vec<u32> = uids.into_iter().collect();
+            uids.sort();
+            println!("[IMAP] UID SEARCH completato, trovati {} UID", uids.len());
+            Ok(uids)
+        }
+        Err(e) => {
+            let e = conn.check(e);
+            println!("[IMAP] Errore nel UID SEARCH: {}", e);
+            Err(format!("Errore nel UID SEARCH: {}", e))
+        }
+    }
+}
+
+/// Conversazioni calcolate dal server con UID THREAD REFERENCES (RFC 5256)
+async fn uid_thread(conn: &mut PooledConnection, query: &str) -> Result<Vec<ThreadNode>, String> {
+    let command = format!("UID THREAD REFERENCES UTF-8 {}", query);
+    // La connessione riscrive "* THREAD ..." come "* OK THREAD ...", interpretabile da imap-proto
+    let mut tree = Vec::new();
+    let result = run_command_with(conn.session()?, &command, |response| {
+        if let Response::Data { status: Status::Ok, code: None, information: Some(information) } = response {
+            if let Some(threads) = information.strip_prefix("THREAD") {
+                tree.extend(parse_thread_response(threads));
+            }
+        }
+    })
+    .await;
+    
+    match result {
+        Ok(()) => Ok(tree),
+        Err(e) => Err(format!("Errore nel UID THREAD: {}", conn.check(e))),
+    }
+}
+
+/// Scarica i messaggi indicati con UID FETCH in batch da 50.
+/// Fallisce se anche un solo batch non viene scaricato: il chiamante non deve
+/// considerare sincronizzati UID che non ha ricevuto.
+async fn fetch_messages(
+    conn: &mut PooledConnection,
+    account_id: &str,
+    folder_id: &str,
+    uids: &[u32],
+) -> Result<Vec<MailMessage>, String> {
+    let mut messages = Vec::new();
+    if uids.is_empty() {
+        println!("[IMAP] Nessun messaggio da scaricare");
+        return Ok(messages);
+    }
+    
+    println!("[IMAP] Recupero {} messaggi in batch da 50", uids.len());
+    for chunk in uids.chunks(50) {
+        let uid_set: Vec<String> = chunk.iter().map(|u| u.to_string()).collect();
+        
+        let fetch_result = match conn.session()?.uid_fetch(uid_set.join(","), MESSAGE_FETCH_QUERY).await {
+            Ok(mut fetched_stream) => {
+                let mut result = Ok(());
+                while let Some(msg_result) = fetched_stream.next().await {
+                    match msg_result {
+                        Ok(msg) => {
+                            if let Some(message) = parse_fetched_message(account_id, folder_id, &msg) {
+                                messages.push(message);
+                            }
+                        }
+                        Err(e) => {
+                            println!("[IMAP] Errore nel fetch del messaggio: {}", e);
+                            result = Err(e);
                         }
-                        Err(e) => Err(format!("Errore nel STORE: {}", e))
                     }
                 }
-                Err(e) => Err(format!("Errore nella selezione cartella: {}", e))
-            };
-            // Se store è andato a buon fine, esegui expunge
-            let final_result = match result {
-                Ok(_) => {
-                    // EXPUNGE per eliminare definitivamente
-                    match session.expunge().await {
-                        Ok(_) => Ok(()),
-                        Err(e) => Err(format!("Errore nell'EXPUNGE: {}", e))
+                result
+            }
+            Err(e) => Err(e),
+        };
+        
+        if let Err(e) = fetch_result {
+            let e = conn.check(e);
+            println!("[IMAP] Errore nel UID FETCH: {}", e);
+            return Err(format!("Errore nel UID FETCH: {}", e));
+        }
+    }
+    
+    // Gmail assegna un identificativo di conversazione a ogni messaggio
+    if conn.has_capability("X-GM-EXT-1") && !messages.is_empty() {
+        let thread_ids = gmail_thread_ids(conn, uids).await.unwrap_or_default();
+        for message in messages.iter_mut() {
+            if let Some(thread_id) = thread_ids.get(&message.uid) {
+                message.thread_id = Some(thread_id.clone());
+            }
+        }
+    }
+    
+    println!("[IMAP] Recuperati {} messaggi totali", messages.len());
+    Ok(messages)
+}
+
+/// Legge X-GM-THRID con un UID FETCH grezzo, dato che async-imap non lo espone
+async fn gmail_thread_ids(conn: &mut PooledConnection, uids: &[u32]) -> Result<HashMap<u32, String>, String> {
+    let command = format!("UID FETCH {} (UID X-GM-THRID)", compress_uid_set(uids));
+    let mut thread_ids = HashMap::new();
+    // * 12 FETCH (X-GM-THRID 1278455344230334865 UID 4)
+    let result = run_command_with(conn.session()?, &command, |response| {
+        let Response::Fetch(_, attributes) = response else {
+            return;
+        };
+        let uid = attributes.iter().find_map(|attribute| match attribute {
+            AttributeValue::Uid(uid) => Some(*uid),
+            _ => None,
+        });
+        let thread_id = attributes.iter().find_map(|attribute| match attribute {
+            AttributeValue::GmailThrId(id) => Some(id.to_string()),
+            _ => None,
+        });
+        if let (Some(uid), Some(thread_id)) = (uid, thread_id) {
+            thread_ids.insert(uid, thread_id);
+        }
+    })
+    .await;
+    
+    if let Err(e) = result {
+        let e = conn.check(e);
+        println!("[IMAP] Errore nella lettura di X-GM-THRID: {}", e);
+        return Err(format!("Errore nella lettura di X-GM-THRID: {}", e));
+    }
+    Ok(thread_ids)
+}
+
+/// Criteri di UID SEARCH per la prima sincronizzazione, limitati opzionalmente da una data
+fn search_criteria(since: Option<i64>) -> Result<String, String> {
+    match since {
+        Some(since_ts) => {
+            let since_date = chrono::DateTime::from_timestamp(since_ts / 1000, 0)
+                .ok_or_else(|| "Data non valida".to_string())?;
+            Ok(format!("SINCE {}", since_date.format("%d-%b-%Y")))
+        }
+        None => Ok("ALL".to_string()),
+    }
+}
+
+/// Attributi per la lista messaggi: solo metadati, il corpo si scarica con `fetch_message_body`
+pub(crate) const MESSAGE_FETCH_QUERY: &str =
+    "(UID FLAGS INTERNALDATE RFC822.SIZE ENVELOPE BODYSTRUCTURE BODY.PEEK[HEADER.FIELDS (FROM SENDER REPLY-TO TO CC BCC IN-REPLY-TO REFERENCES)])";
+
+/// Converte una risposta UID FETCH (ENVELOPE) in un `MailMessage` senza corpo.
+/// Restituisce `None` se il server non ha incluso l'UID o l'ENVELOPE.
+pub(crate) fn parse_fetched_message(account_id: &str, folder_id: &str, msg: &Fetch) -> Option<MailMessage> {
+    let uid = match msg.uid {
+        Some(uid) => uid,
+        None => {
+            println!("[IMAP] Messaggio {} senza UID, ignorato", msg.message);
+            return None;
+        }
+    };
+    
+    let envelope = match msg.envelope() {
+        Some(envelope) => envelope,
+        None => {
+            println!("[IMAP] Messaggio senza ENVELOPE (UID: {})", uid);
+            return None;
+        }
+    };
+    
+    let subject = envelope.subject
+        .as_deref()
+        .map(decode_header_value)
+        .unwrap_or_else(|| "No Subject".to_string());
+    
+    // Gli header originali sono più affidabili di ENVELOPE, che alcuni server
+    // costruiscono male con nomi non codificati o sintassi irregolare
+    let headers = msg
+        .header()
+        .and_then(|raw| mailparse::parse_headers(raw).ok())
+        .map(|(headers, _)| headers)
+        .unwrap_or_default();
+    let addresses = |name, envelope| message_addresses(&headers, name, envelope);
+    
+    let from = addresses("From", envelope.from.as_ref()).into_iter().next();
+    let from_name = from.as_ref().and_then(|a| a.name.clone());
+    let from_address = from
+        .map(|a| a.address)
+        .unwrap_or_else(|| "unknown@example.com".to_string());
+    let to_addresses = addresses("To", envelope.to.as_ref());
+    let cc_addresses = Some(addresses("Cc", envelope.cc.as_ref())).filter(|a| !a.is_empty());
+    let bcc_addresses = Some(addresses("Bcc", envelope.bcc.as_ref())).filter(|a| !a.is_empty());
+    let reply_to = Some(addresses("Reply-To", envelope.reply_to.as_ref())).filter(|a| !a.is_empty());
+    let sender = addresses("Sender", envelope.sender.as_ref()).into_iter().next();
+    
+    let date_str = envelope.date
+        .as_deref()
+        .map(|d| String::from_utf8_lossy(d).into_owned())
+        .unwrap_or_default();
+    
+    // Se la data dell'ENVELOPE manca o non è valida usa INTERNALDATE del server
+    let date = parse_mail_date(&date_str)
+        .or_else(|| msg.internal_date().map(|d| d.timestamp_millis()))
+        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    
+    let message_id = envelope.message_id
+        .as_deref()
+        .map(|id| String::from_utf8_lossy(id).into_owned())
+        .unwrap_or_else(|| format!("msg-{}", uid));
+    
+    let header_value = |name: &str| headers.get_first_value(name).filter(|v| !v.trim().is_empty());
+    let in_reply_to = header_value("In-Reply-To")
+        .or_else(|| envelope.in_reply_to.as_deref().map(|v| String::from_utf8_lossy(v).into_owned()))
+        .and_then(|value| parse_message_ids(&value).into_iter().next());
+    let references = header_value("References")
+        .map(|value| parse_message_ids(&value))
+        .filter(|ids| !ids.is_empty());
+    // Conversazione stimata dalla radice dei riferimenti; Gmail la sostituisce con X-GM-THRID
+    let thread_id = references
+        .as_ref()
+        .and_then(|ids| ids.first().cloned())
+        .or_else(|| in_reply_to.clone())
+        .unwrap_or_else(|| message_id.clone());
+    
+    let flags = MessageFlags::from_flags(msg.flags());
+    
+    println!("[IMAP] Messaggio parsato: {} (UID: {})", subject, uid);
+    
+    Some(MailMessage {
+        id: format!("{}-msg-{}", account_id, uid),
+        account_id: account_id.to_string(),
+        folder_id: folder_id.to_string(),
+        uid,
+        message_id,
+        subject,
+        from_name,
+        from_address,
+        to_addresses,
+        cc_addresses,
+        bcc_addresses,
+        reply_to,
+        sender,
+        date,
+        size: msg.size,
+        attachments: msg.bodystructure().map(find_attachments).unwrap_or_default(),
+        text: None,
+        html: None,
+        flags: flags.flags,
+        keywords: flags.keywords,
+        is_read: flags.is_read,
+        is_starred: flags.is_starred,
+        is_important: flags.is_important,
+        thread_id: Some(thread_id),
+        in_reply_to,
+        references,
+        synced_at: chrono::Utc::now().timestamp_millis(),
+    })
+}
+
+/// Converte una risposta FETCH (UID FLAGS) in un aggiornamento di flag
+fn parse_flag_update(msg: &Fetch) -> Option<FlagUpdate> {
+    let uid = msg.uid?;
+    let flags = MessageFlags::from_flags(msg.flags());
+    Some(FlagUpdate {
+        uid,
+        flags: flags.flags,
+        keywords: flags.keywords,
+        is_read: flags.is_read,
+        is_starred: flags.is_starred,
+        is_important: flags.is_important,
+    })
+}
+
+fn parse_mail_date(date_str: &str) -> Option<i64> {
+    // Prova a parsare la data con chrono
+    chrono::DateTime::parse_from_rfc2822(date_str)
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(date_str))
+        .map(|dt| dt.timestamp_millis())
+        .ok()
+}
+
+/// Raggruppa i messaggi di una cartella in conversazioni.
+/// Usa X-GM-THRID su Gmail, `UID THREAD REFERENCES` quando il server lo supporta
+/// e altrimenti l'algoritmo JWZ lato client.
+#[tauri::command]
+pub async fn get_threads(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_id: String,
+    folder_path: String,
+    since: Option<i64>,
+) -> Result<Vec<MailThread>, String> {
+    println!("[IMAP] Get threads per cartella: {}", folder_path);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    conn.select(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    let criteria = search_criteria(since)?;
+    
+    if conn.has_capability("X-GM-EXT-1") {
+        let uids = uid_search(&mut conn, &criteria).await?;
+        let messages = fetch_messages(&mut conn, &account_id, &folder_id, &uids).await?;
+        return Ok(threads_by_id(messages));
+    }
+    
+    if conn.has_capability("THREAD=REFERENCES") {
+        match uid_thread(&mut conn, &criteria).await {
+            Ok(tree) => {
+                let mut uids = thread_uids(&tree);
+                uids.sort();
+                let messages = fetch_messages(&mut conn, &account_id, &folder_id, &uids).await?;
+                return Ok(threads_from_server(tree, messages));
+            }
+            Err(e) => println!("[IMAP] {}, uso il threading locale", e),
+        }
+    }
+    
+    let uids = uid_search(&mut conn, &criteria).await?;
+    let messages = fetch_messages(&mut conn, &account_id, &folder_id, &uids).await?;
+    Ok(thread_messages(messages))
+}
+
+/// Scarica il corpo di un singolo messaggio quando l'utente lo apre.
+/// Legge la BODYSTRUCTURE e recupera solo le sezioni text/plain e text/html con BODY.PEEK.
+#[tauri::command]
+pub async fn fetch_message_body(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uid: u32,
+) -> Result<MessageBody, String> {
+    println!("[IMAP] Fetch body messaggio {} in {}", uid, folder_path);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    conn.select(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    
+    fetch_body(&mut conn, uid).await
+}
+
+/// Legge la BODYSTRUCTURE e recupera solo le sezioni text/plain e text/html con BODY.PEEK
+/// (cartella già selezionata)
+pub(crate) async fn fetch_body(conn: &mut PooledConnection, uid: u32) -> Result<MessageBody, String> {
+    let structure = fetch_one(conn, uid, "(UID BODYSTRUCTURE)").await?;
+    let (text_part, html_part) = match structure.bodystructure() {
+        Some(bodystructure) => find_text_parts(bodystructure),
+        None => (None, None),
+    };
+    
+    let sections: Vec<String> = text_part.iter()
+        .chain(html_part.iter())
+        .map(|part| format!("BODY.PEEK[{}]", part.section_spec()))
+        .collect();
+    if sections.is_empty() {
+        println!("[IMAP] Nessuna parte testuale per il messaggio {}", uid);
+        return Ok(MessageBody { uid, text: None, html: None });
+    }
+    
+    let fetched = fetch_one(conn, uid, &format!("(UID {})", sections.join(" "))).await?;
+    let decode_part = |part: &Option<TextPart>| {
+        part.as_ref().and_then(|part| {
+            fetched
+                .section(&SectionPath::Part(part.section.clone(), None))
+                .map(|raw| part.decode(raw))
+        })
+    };
+    
+    Ok(MessageBody {
+        uid,
+        text: decode_part(&text_part),
+        html: decode_part(&html_part),
+    })
+}
+
+/// Esegue UID FETCH su un singolo UID e restituisce la risposta corrispondente
+pub(crate) async fn fetch_one(conn: &mut PooledConnection, uid: u32, query: &str) -> Result<Fetch, String> {
+    let result = match conn.session()?.uid_fetch(uid.to_string(), query).await {
+        Ok(stream) => {
+            let fetched: Vec<_> = stream.collect().await;
+            Ok(fetched)
+        }
+        Err(e) => Err(e),
+    };
+    
+    result
+        .map_err(|e| format!("Errore nel UID FETCH: {}", conn.check(e)))?
+        .into_iter()
+        .filter_map(|msg| msg.ok())
+        .find(|msg| msg.uid == Some(uid))
+        .ok_or_else(|| format!("Messaggio {} non trovato", uid))
+}
+
+/// Esegue UID STORE consumando completamente lo stream delle risposte FETCH
+pub(crate) async fn uid_store(
+    conn: &mut PooledConnection,
+    uid_set: &str,
+    query: &str,
+) -> Result<(), async_imap::error::Error> {
+    let session = conn.session().map_err(|_| async_imap::error::Error::ConnectionLost)?;
+    let result = match session.uid_store(uid_set, query).await {
+        Ok(mut stream) => {
+            let mut result = Ok(());
+            while let Some(item) = stream.next().await {
+                if let Err(e) = item {
+                    result = Err(e);
+                }
+            }
+            result
+        }
+        Err(e) => Err(e),
+    };
+    result.map_err(|e| conn.check(e))
+}
+
+/// Marca come letti/non letti i messaggi indicati sul server IMAP, con un solo UID STORE
+#[tauri::command]
+pub async fn mark_message_read(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uids: Vec<u32>,
+    read: bool,
+) -> Result<Vec<UidResult>, String> {
+    println!(
+        "[IMAP] Mark {} messages as {} in folder {}",
+        uids.len(),
+        if read { "read" } else { "unread" },
+        folder_path
+    );
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    mark_read(&mut conn, &folder_path, &uids, read).await
+}
+
+/// Corpo di `mark_message_read` sulla connessione già acquisita dal pool
+async fn mark_read(
+    conn: &mut PooledConnection,
+    folder_path: &str,
+    uids: &[u32],
+    read: bool,
+) -> Result<Vec<UidResult>, String> {
+    conn.select(folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    
+    let (existing, mut results) = existing_uids(conn, uids).await?;
+    if !existing.is_empty() {
+        let flag = if read { "+FLAGS (\\Seen)" } else { "-FLAGS (\\Seen)" };
+        let outcome = uid_store(conn, &compress_uid_set(&existing), flag)
+            .await
+            .map_err(|e| format!("Errore nel UID STORE: {}", e));
+        results.extend(existing.iter().map(|uid| UidResult::from_outcome(*uid, &outcome)));
+    }
+    
+    results.sort_by_key(|r| r.uid);
+    Ok(results)
+}
+
+/// Aggiunge e rimuove flag e keyword (\Flagged, \Answered, \Draft, $Important,
+/// $Junk/$NotJunk, etichette dell'utente) sui messaggi indicati
+#[tauri::command]
+pub async fn set_flags(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uids: Vec<u32>,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Vec<UidResult>, String> {
+    println!(
+        "[IMAP] Set flags on {} messages in folder {}: +{:?} -{:?}",
+        uids.len(), folder_path, add, remove
+    );
+    
+    let add = add.iter().map(|f| normalize_flag(f)).collect::<Result<Vec<_>, _>>()?;
+    let mut remove = remove.iter().map(|f| normalize_flag(f)).collect::<Result<Vec<_>, _>>()?;
+    exclusive_removals(&add, &mut remove);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    // SELECT esplicito per leggere PERMANENTFLAGS
+    let mailbox = conn.select_mailbox(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    
+    let rejected: Vec<&String> = add
+        .iter()
+        .filter(|f| !is_permanent(f, &mailbox.permanent_flags))
+        .collect();
+    if !rejected.is_empty() {
+        return Err(format!("La cartella {} non consente di salvare i flag {:?}", folder_path, rejected));
+    }
+    
+    let (existing, mut results) = existing_uids(&mut conn, &uids).await?;
+    if !existing.is_empty() {
+        let uid_set = compress_uid_set(&existing);
+        let mut outcome = Ok(());
+        for (operation, flags) in [("+FLAGS.SILENT", &add), ("-FLAGS.SILENT", &remove)] {
+            if flags.is_empty() || outcome.is_err() {
+                continue;
+            }
+            let query = format!("{} ({})", operation, flags.join(" "));
+            outcome = uid_store(&mut conn, &uid_set, &query)
+                .await
+                .map_err(|e| format!("Errore nel UID STORE: {}", e));
+        }
+        results.extend(existing.iter().map(|uid| UidResult::from_outcome(*uid, &outcome)));
+    }
+    
+    results.sort_by_key(|r| r.uid);
+    Ok(results)
+}
+
+/// Sposta i messaggi indicati da una cartella all'altra con un solo comando.
+/// Per ogni messaggio riporta l'UID assegnato nella destinazione quando il server lo comunica
+/// (COPYUID di UIDPLUS) o quando è possibile ritrovarlo tramite Message-ID.
+#[tauri::command]
+pub async fn move_message(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uids: Vec<u32>,
+    target_folder: String,
+) -> Result<MoveResult, String> {
+    println!(
+        "[IMAP] Move {} messages from {} to {}",
+        uids.len(), folder_path, target_folder
+    );
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    conn.select(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    
+    let (existing, mut results) = existing_uids(&mut conn, &uids).await?;
+    let mut result = MoveResult {
+        target_uid_validity: None,
+        results: Vec::new(),
+    };
+    if existing.is_empty() {
+        result.results = results;
+        return Ok(result);
+    }
+    let uid_set = compress_uid_set(&existing);
+    
+    // Senza MOVE+UIDPLUS il COPYUID potrebbe mancare: servono i Message-ID per ritrovare i messaggi
+    let message_ids = if conn.has_capability("MOVE") && conn.has_capability("UIDPLUS") {
+        HashMap::new()
+    } else {
+        message_ids(&mut conn, &uid_set).await?
+    };
+    
+    match move_uid_set(&mut conn, &uid_set, &target_folder).await {
+        Ok(MoveOutcome { copy_uid: Some(copy_uid), removal }) => {
+            result.target_uid_validity = Some(copy_uid.uid_validity);
+            results.extend(existing.iter().map(|uid| UidResult::moved(*uid, copy_uid.target_for(*uid), &removal)));
+        }
+        Ok(MoveOutcome { copy_uid: None, removal }) => {
+            // Cerca i messaggi copiati nella destinazione tramite Message-ID
+            let mailbox = conn.select_mailbox(&target_folder)
+                .await
+                .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+            result.target_uid_validity = mailbox.uid_validity;
+            for uid in &existing {
+                let target_uid = match message_ids.get(uid) {
+                    Some(message_id) => {
+                        let query = format!("HEADER Message-ID {}", quote_imap_string(message_id));
+                        // La copia è già avvenuta: un errore qui lascia solo l'UID sconosciuto
+                        uid_search(&mut conn, &query).await.ok().and_then(|found| found.last().copied())
                     }
+                    None => None,
+                };
+                results.push(UidResult::moved(*uid, target_uid, &removal));
+            }
+        }
+        Err(e) => {
+            let outcome = Err(e);
+            results.extend(existing.iter().map(|uid| UidResult::from_outcome(*uid, &outcome)));
+        }
+    }
+    
+    results.sort_by_key(|r| r.uid);
+    result.results = results;
+    Ok(result)
+}
+
+/// Message-ID (da ENVELOPE) dei messaggi indicati, per UID
+async fn message_ids(conn: &mut PooledConnection, uid_set: &str) -> Result<HashMap<u32, String>, String> {
+    let result = match conn.session()?.uid_fetch(uid_set, "(UID ENVELOPE)").await {
+        Ok(stream) => {
+            let fetched: Vec<_> = stream.collect().await;
+            Ok(fetched)
+        }
+        Err(e) => Err(e),
+    };
+    
+    Ok(result
+        .map_err(|e| format!("Errore nel UID FETCH: {}", conn.check(e)))?
+        .into_iter()
+        .filter_map(|msg| msg.ok())
+        .filter_map(|msg| {
+            let message_id = msg.envelope()?.message_id.as_deref()?;
+            Some((msg.uid?, String::from_utf8_lossy(message_id).into_owned()))
+        })
+        .collect())
+}
+
+/// Separa gli UID richiesti tra quelli presenti nella cartella selezionata
+/// e quelli mancanti, già riportati come falliti
+async fn existing_uids(conn: &mut PooledConnection, uids: &[u32]) -> Result<(Vec<u32>, Vec<UidResult>), String> {
+    let mut uids = uids.to_vec();
+    uids.sort();
+    uids.dedup();
+    if uids.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    
+    let existing = uid_search(conn, &format!("UID {}", compress_uid_set(&uids))).await?;
+    let missing = uids
+        .into_iter()
+        .filter(|uid| !existing.contains(uid))
+        .map(|uid| UidResult::failed(uid, "Messaggio non trovato nella cartella".to_string()))
+        .collect();
+    Ok((existing, missing))
+}
+
+/// Comprime una lista di UID in un sequence-set IMAP (es. "1:50,72,90:120")
+fn compress_uid_set(uids: &[u32]) -> String {
+    let mut uids = uids.to_vec();
+    uids.sort();
+    uids.dedup();
+    
+    let mut ranges: Vec<String> = Vec::new();
+    let mut iter = uids.into_iter().peekable();
+    while let Some(start) = iter.next() {
+        let mut end = start;
+        while end.checked_add(1).is_some_and(|next| iter.peek() == Some(&next)) {
+            end += 1;
+            iter.next();
+        }
+        ranges.push(if start == end {
+            start.to_string()
+        } else {
+            format!("{}:{}", start, end)
+        });
+    }
+    ranges.join(",")
+}
+
+/// Esito di `move_uid_set` quando i messaggi sono stati copiati nella destinazione
+struct MoveOutcome {
+    /// Corrispondenza UID sorgente -> destinazione, se il server l'ha restituita
+    copy_uid: Option<CopyUid>,
+    /// Errore nella rimozione dalla sorgente dopo una copia riuscita (solo fallback COPY)
+    removal: Result<(), String>,
+}
+
+/// Sposta un insieme di UID nella cartella indicata (la cartella sorgente deve essere selezionata).
+///
+/// Con MOVE (RFC 6851) usa `UID MOVE`; altrimenti `UID COPY` + `UID STORE \Deleted` e,
+/// l'eliminazione dei soli UID spostati (vedi `expunge_uid_set`): un EXPUNGE generico
+/// eliminerebbe anche i messaggi marcati \Deleted da altri client.
+/// Restituisce un errore solo se nessun messaggio è stato copiato; se la copia riesce ma
+/// STORE o EXPUNGE falliscono l'errore è in `MoveOutcome::removal`, insieme al COPYUID.
+async fn move_uid_set(
+    conn: &mut PooledConnection,
+    uid_set: &str,
+    target_folder: &str,
+) -> Result<MoveOutcome, String> {
+    let target = quote_imap_string(&conn.encode_mailbox(target_folder));
+    let moved = conn.has_capability("MOVE");
+    let command = if moved {
+        format!("UID MOVE {} {}", uid_set, target)
+    } else {
+        format!("UID COPY {} {}", uid_set, target)
+    };
+    
+    // Con MOVE il COPYUID arriva in una risposta OK non taggata, con COPY in quella taggata
+    let mut copy_uid = None;
+    let result = run_command_with(conn.session()?, &command, |response| {
+        if let Response::Data { code: Some(code), .. } | Response::Done { code: Some(code), .. } = response {
+            copy_uid = copy_uid.take().or_else(|| parse_copyuid(code));
+        }
+    })
+    .await;
+    if let Err(e) = result {
+        let command = if moved { "UID MOVE" } else { "UID COPY" };
+        return Err(format!("Errore nel {}: {}", command, conn.check(e)));
+    }
+    if moved {
+        return Ok(MoveOutcome { copy_uid, removal: Ok(()) });
+    }
+    
+    // Fallback COPY: gli originali vanno marcati \Deleted ed eliminati dalla sorgente
+    let removal = purge_uid_set(conn, uid_set).await;
+    if let Err(e) = &removal {
+        println!("[IMAP] Messaggi {} copiati ma non rimossi dalla sorgente: {}", uid_set, e);
+    }
+    
+    Ok(MoveOutcome { copy_uid, removal })
+}
+
+/// Elimina definitivamente solo gli UID indicati, già marcati \Deleted.
+///
+/// Con UIDPLUS usa `UID EXPUNGE`; altrimenti ricorre a EXPUNGE solo se nessun altro
+/// messaggio della cartella è marcato \Deleted. Restituisce false se non ha eliminato nulla.
+async fn expunge_uid_set(conn: &mut PooledConnection, uid_set: &str) -> Result<bool, String> {
+    let uid_expunge = conn.has_capability("UIDPLUS");
+    if !uid_expunge {
+        let uids = expand_uid_set(uid_set).unwrap_or_default();
+        let deleted = uid_search(conn, "DELETED").await?;
+        if deleted.iter().any(|uid| !uids.contains(uid)) {
+            return Ok(false);
+        }
+    }
+    
+    // I due comandi restituiscono stream di tipo diverso
+    let result = if uid_expunge {
+        match conn.session()?.uid_expunge(uid_set).await {
+            Ok(stream) => {
+                let _: Vec<_> = stream.collect().await;
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    } else {
+        match conn.session()?.expunge().await {
+            Ok(stream) => {
+                let _: Vec<_> = stream.collect().await;
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    };
+    result.map_err(|e| format!("Errore nell'EXPUNGE: {}", conn.check(e)))
+}
+
+/// Codice di risposta COPYUID (RFC 4315): UIDVALIDITY della destinazione e corrispondenza degli UID
+struct CopyUid {
+    uid_validity: u32,
+    source: Vec<u32>,
+    target: Vec<u32>,
+}
+
+impl CopyUid {
+    fn target_for(&self, uid: u32) -> Option<u32> {
+        self.source
+            .iter()
+            .position(|source| *source == uid)
+            .and_then(|index| self.target.get(index).copied())
+    }
+}
+
+/// Corrispondenza degli UID dal codice `[COPYUID <uidvalidity> <uid-set sorgente> <uid-set destinazione>]`.
+/// imap-proto restituisce gli intervalli già ordinati (RFC 4315: 4:2 equivale a 2:4).
+fn parse_copyuid(code: &ResponseCode<'_>) -> Option<CopyUid> {
+    let ResponseCode::CopyUid(uid_validity, source, target) = code else {
+        return None;
+    };
+    let expand = |members: &[UidSetMember]| -> Vec<u32> {
+        members
+            .iter()
+            .flat_map(|member| match member {
+                UidSetMember::Uid(uid) => *uid..=*uid,
+                UidSetMember::UidRange(range) => range.clone(),
+            })
+            .collect()
+    };
+    
+    let (source, target) = (expand(source), expand(target));
+    if source.len() != target.len() {
+        return None;
+    }
+    Some(CopyUid { uid_validity: *uid_validity, source, target })
+}
+
+/// Espande un uid-set come "4:6,9" in [4, 5, 6, 9]
+fn expand_uid_set(set: &str) -> Option<Vec<u32>> {
+    let mut uids = Vec::new();
+    for range in set.split(',') {
+        match range.split_once(':') {
+            Some((from, to)) => {
+                let (from, to): (u32, u32) = (from.parse().ok()?, to.parse().ok()?);
+                // RFC 4315: gli estremi possono essere in ordine inverso
+                let (low, high) = if from <= to { (from, to) } else { (to, from) };
+                uids.extend(low..=high);
+            }
+            None => uids.push(range.parse().ok()?),
+        }
+    }
+    Some(uids)
+}
+
+/// Stringa IMAP quotata (RFC 3501), per i comandi costruiti manualmente
+fn quote_imap_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Elimina i messaggi indicati spostandoli nel Cestino (cartella special-use Trash).
+/// Se i messaggi sono già nel Cestino vengono eliminati definitivamente.
+#[tauri::command]
+pub async fn delete_message(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uids: Vec<u32>,
+) -> Result<Vec<UidResult>, String> {
+    println!("[IMAP] Delete {} messages from folder {}", uids.len(), folder_path);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let trash = folder_path_for_role(&mut conn, &account_id, FolderRole::Trash)
+        .await?
+        .ok_or_else(|| "Cartella Cestino non trovata, usa l'eliminazione definitiva".to_string())?;
+    
+    conn.select(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    
+    let (existing, mut results) = existing_uids(&mut conn, &uids).await?;
+    if !existing.is_empty() {
+        let uid_set = compress_uid_set(&existing);
+        if trash == folder_path {
+            let outcome = purge_uid_set(&mut conn, &uid_set).await;
+            results.extend(existing.iter().map(|uid| UidResult::from_outcome(*uid, &outcome)));
+        } else {
+            match move_uid_set(&mut conn, &uid_set, &trash).await {
+                Ok(MoveOutcome { copy_uid, removal }) => results.extend(existing.iter().map(|uid| {
+                    let target_uid = copy_uid.as_ref().and_then(|c| c.target_for(*uid));
+                    UidResult::moved(*uid, target_uid, &removal)
+                })),
+                Err(e) => {
+                    let outcome = Err(e);
+                    results.extend(existing.iter().map(|uid| UidResult::from_outcome(*uid, &outcome)));
                 }
-                Err(e) => Err(e)
-            };
-            let _ = session.logout().await;
-            final_result
+            }
         }
-        Err(e) => Err(e)
+    }
+    
+    results.sort_by_key(|r| r.uid);
+    Ok(results)
+}
+
+/// Elimina definitivamente i messaggi indicati con UID EXPUNGE, senza toccare gli altri
+/// messaggi marcati \Deleted nella cartella
+#[tauri::command]
+pub async fn purge_message(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+    folder_path: String,
+    uids: Vec<u32>,
+) -> Result<Vec<UidResult>, String> {
+    println!("[IMAP] Purge {} messages from folder {}", uids.len(), folder_path);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    conn.select(&folder_path)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    
+    let (existing, mut results) = existing_uids(&mut conn, &uids).await?;
+    if !existing.is_empty() {
+        let outcome = purge_uid_set(&mut conn, &compress_uid_set(&existing)).await;
+        results.extend(existing.iter().map(|uid| UidResult::from_outcome(*uid, &outcome)));
+    }
+    
+    results.sort_by_key(|r| r.uid);
+    Ok(results)
+}
+
+/// Svuota il Cestino dell'account. Restituisce il numero di messaggi eliminati.
+#[tauri::command]
+pub async fn empty_trash(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
+    account_id: String,
+) -> Result<u32, String> {
+    println!("[IMAP] Empty trash per account: {}", account_id);
+    
+    let mut conn = pool.acquire(&accounts, &account_id).await?;
+    let trash = folder_path_for_role(&mut conn, &account_id, FolderRole::Trash)
+        .await?
+        .ok_or_else(|| "Cartella Cestino non trovata".to_string())?;
+    
+    let mailbox = conn.select_mailbox(&trash)
+        .await
+        .map_err(|e| format!("Errore nella selezione cartella: {}", e))?;
+    if mailbox.exists == 0 {
+        return Ok(0);
+    }
+    
+    uid_store(&mut conn, "1:*", "+FLAGS.SILENT (\\Deleted)")
+        .await
+        .map_err(|e| format!("Errore nel UID STORE: {}", e))?;
+    
+    // Tutta la cartella va eliminata: qui un EXPUNGE generico è corretto
+    let result = match conn.session()?.expunge().await {
+        Ok(stream) => {
+            // Consuma lo stream degli EXPUNGE
+            let _: Vec<_> = stream.collect().await;
+            Ok(mailbox.exists)
+        }
+        Err(e) => Err(e),
+    };
+    result.map_err(|e| format!("Errore nell'EXPUNGE: {}", conn.check(e)))
+}
+
+/// Marca \Deleted ed elimina definitivamente gli UID indicati (cartella già selezionata)
+async fn purge_uid_set(conn: &mut PooledConnection, uid_set: &str) -> Result<(), String> {
+    uid_store(conn, uid_set, "+FLAGS.SILENT (\\Deleted)")
+        .await
+        .map_err(|e| format!("Errore nel UID STORE: {}", e))?;
+    
+    if expunge_uid_set(conn, uid_set).await? {
+        Ok(())
+    } else {
+        Err("Il server non supporta UIDPLUS e la cartella contiene altri messaggi eliminati: i messaggi restano marcati \\Deleted".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::FakeImapServer;
+
+    const ENVELOPE_5: &str = "(\"Mon, 7 Feb 2022 21:52:25 +0100\" \"Primo\" ((\"Anna\" NIL \"anna\" \"example.com\")) NIL NIL ((NIL NIL \"test\" \"example.com\")) NIL NIL NIL \"<primo@example.com>\")";
+    const ENVELOPE_9: &str = "(\"Tue, 8 Feb 2022 10:00:00 +0100\" \"Secondo\" ((\"Bruno\" NIL \"bruno\" \"example.com\")) NIL NIL ((NIL NIL \"test\" \"example.com\")) NIL NIL NIL \"<secondo@example.com>\")";
+
+    #[tokio::test]
+    async fn synced_uids_round_trip_into_mark_message_read() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1".to_string()]),
+            ("SELECT", vec![
+                "* 2 EXISTS".to_string(),
+                "* OK [UIDVALIDITY 7] UID validi".to_string(),
+                "* OK [UIDNEXT 10] prossimo UID".to_string(),
+            ]),
+            ("UID SEARCH", vec!["* SEARCH 5 9".to_string()]),
+            ("UID FETCH", vec![
+                format!("* 1 FETCH (UID 5 FLAGS () RFC822.SIZE 120 ENVELOPE {})", ENVELOPE_5),
+                format!("* 2 FETCH (UID 9 FLAGS () RFC822.SIZE 240 ENVELOPE {})", ENVELOPE_9),
+            ]),
+            ("UID STORE", vec![
+                "* 1 FETCH (UID 5 FLAGS (\\Seen))".to_string(),
+                "* 2 FETCH (UID 9 FLAGS (\\Seen))".to_string(),
+            ]),
+        ])
+        .await;
+
+        let account = server.account();
+        let pool = ImapPool::default();
+        let open = || {
+            let account = account.clone();
+            async move {
+                let session = create_imap_session(&account, "segreto").await?;
+                Ok::<_, SessionError>((account, session))
+            }
+        };
+
+        let mut conn = pool.acquire_with("test", &account, open).await.unwrap();
+        let delta = sync_mailbox(&mut conn, "test", "test-inbox", "INBOX", None, None, None)
+            .await
+            .unwrap();
+        drop(conn);
+
+        assert!(delta.full_resync);
+        assert_eq!(delta.state.uid_validity, 7);
+        assert_eq!(delta.state.uid_next, 10);
+        let uids: Vec<u32> = delta.new_messages.iter().map(|m| m.uid).collect();
+        assert_eq!(uids, vec![5, 9]);
+        assert_eq!(delta.new_messages[0].subject, "Primo");
+
+        // Gli UID restituiti dalla sync vanno passati così come sono ai comandi di modifica
+        let mut conn = pool.acquire_with("test", &account, open).await.unwrap();
+        let results = mark_read(&mut conn, "INBOX", &uids, true).await.unwrap();
+        drop(conn);
+
+        assert!(results.iter().all(|r| r.success));
+        assert_eq!(results.iter().map(|r| r.uid).collect::<Vec<_>>(), vec![5, 9]);
+        let commands = server.commands();
+        assert!(commands.contains(&"UID SEARCH UID 5,9".to_string()), "{:?}", commands);
+        assert!(commands.contains(&"UID STORE 5,9 +FLAGS (\\Seen)".to_string()), "{:?}", commands);
+        // La seconda acquire riusa la sessione del pool: un solo LOGIN
+        assert_eq!(commands.iter().filter(|c| c.starts_with("LOGIN")).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn list_status_reads_every_status_reply_from_the_command() {
+        // Più risposte della capacità del canale delle risposte non richieste (100)
+        let mut lines = Vec::new();
+        for i in 0..150 {
+            lines.push(format!("* LIST () \"/\" \"Cartella{}\"", i));
+            lines.push(format!("* STATUS \"Cartella{}\" (MESSAGES {} UNSEEN 1 UIDNEXT 10 UIDVALIDITY 3)", i, i));
+        }
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 LIST-STATUS".to_string()]),
+            ("LIST", lines),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let statuses = list_status(&mut conn).await.unwrap();
+
+        assert_eq!(statuses.len(), 150);
+        assert_eq!(statuses["Cartella149"].messages, Some(149));
+        assert_eq!(statuses["Cartella0"].uid_validity, Some(3));
+        assert!(conn.is_alive());
+    }
+
+    #[tokio::test]
+    async fn subscribed_folders_reads_list_extended_and_lsub() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 LIST-EXTENDED".to_string()]),
+            ("LIST", vec![
+                "* LIST (\\Subscribed) \"/\" \"INBOX\"".to_string(),
+                "* LIST () \"/\" \"Archivio\"".to_string(),
+                "* LIST (\\Subscribed \\HasNoChildren) \"/\" \"Progetti/ACME\"".to_string(),
+            ]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+        let subscribed = subscribed_folders(&mut conn).await.unwrap();
+        assert_eq!(subscribed, HashSet::from(["INBOX".to_string(), "Progetti/ACME".to_string()]));
+
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1".to_string()]),
+            ("LSUB", vec![
+                "* LSUB () \"/\" \"INBOX\"".to_string(),
+                "* LSUB () \"/\" \"Bozze &AOg-\"".to_string(),
+            ]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+        let subscribed = subscribed_folders(&mut conn).await.unwrap();
+        assert_eq!(subscribed, HashSet::from(["INBOX".to_string(), "Bozze è".to_string()]));
+        assert!(server.commands().contains(&"LSUB \"\" \"*\"".to_string()));
+    }
+
+    #[tokio::test]
+    async fn uid_move_reads_copyuid_from_the_untagged_reply() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 MOVE UIDPLUS".to_string()]),
+            ("UID MOVE", vec![
+                "* OK [COPYUID 7 120:118 30:32] spostati".to_string(),
+                "* 3 EXPUNGE".to_string(),
+                "* 3 EXPUNGE".to_string(),
+                "* 3 EXPUNGE".to_string(),
+            ]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let outcome = move_uid_set(&mut conn, "118:120", "Archivio").await.unwrap();
+
+        assert!(outcome.removal.is_ok());
+        let copy_uid = outcome.copy_uid.unwrap();
+        assert_eq!(copy_uid.uid_validity, 7);
+        assert_eq!(copy_uid.target_for(118), Some(30));
+        assert_eq!(copy_uid.target_for(120), Some(32));
+        assert!(server.commands().contains(&"UID MOVE 118:120 \"Archivio\"".to_string()));
+    }
+
+    #[tokio::test]
+    async fn copy_fallback_fails_when_the_originals_cannot_be_expunged() {
+        // Senza UIDPLUS l'EXPUNGE eliminerebbe anche il messaggio 12, marcato \Deleted da altri
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1".to_string()]),
+            ("UID COPY", vec!["$tag OK [COPYUID 7 5,9 20:21] copiati".to_string()]),
+            ("UID SEARCH", vec!["* SEARCH 5 9 12".to_string()]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let outcome = move_uid_set(&mut conn, "5,9", "Archivio").await.unwrap();
+
+        assert!(outcome.removal.is_err());
+        assert_eq!(outcome.copy_uid.unwrap().target_for(9), Some(21));
+        let commands = server.commands();
+        assert!(commands.contains(&"UID STORE 5,9 +FLAGS.SILENT (\\Deleted)".to_string()), "{:?}", commands);
+        assert!(!commands.iter().any(|c| c.contains("EXPUNGE")), "{:?}", commands);
+    }
+
+    #[tokio::test]
+    async fn qresync_reports_vanished_uids_and_changed_flags() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 ENABLE QRESYNC".to_string()]),
+            ("SELECT", vec![
+                "* 4 EXISTS".to_string(),
+                "* OK [UIDVALIDITY 7] UID validi".to_string(),
+                "* OK [UIDNEXT 10] prossimo UID".to_string(),
+                "* OK [HIGHESTMODSEQ 120] modseq".to_string(),
+            ]),
+            ("UID FETCH", vec![
+                "* VANISHED (EARLIER) 3,7:8".to_string(),
+                "* 1 FETCH (UID 5 FLAGS (\\Seen) MODSEQ (120))".to_string(),
+            ]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let previous = FolderSyncState { uid_validity: 7, uid_next: 10, highest_modseq: Some(100) };
+        let delta = sync_mailbox(&mut conn, "test", "test-inbox", "INBOX", None, Some(previous), None)
+            .await
+            .unwrap();
+
+        assert!(!delta.full_resync);
+        assert_eq!(delta.vanished, vec![3, 7, 8]);
+        assert_eq!(delta.changed_flags.len(), 1);
+        assert!(delta.changed_flags[0].is_read);
+        let commands = server.commands();
+        assert!(
+            commands.contains(&"UID FETCH 1:9 (UID FLAGS) (CHANGEDSINCE 100 VANISHED)".to_string()),
+            "{:?}",
+            commands
+        );
+    }
+
+    #[tokio::test]
+    async fn condstore_without_qresync_detects_expunges_with_unchanged_modseq() {
+        // Senza QRESYNC un expunge non deve per forza aumentare HIGHESTMODSEQ
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 CONDSTORE".to_string()]),
+            ("SELECT", vec![
+                "* 1 EXISTS".to_string(),
+                "* OK [UIDVALIDITY 7] UID validi".to_string(),
+                "* OK [UIDNEXT 10] prossimo UID".to_string(),
+                "* OK [HIGHESTMODSEQ 100] modseq".to_string(),
+            ]),
+            ("UID FETCH", vec![]),
+            ("UID SEARCH", vec!["* SEARCH 5".to_string()]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let previous = FolderSyncState { uid_validity: 7, uid_next: 10, highest_modseq: Some(100) };
+        let delta = sync_mailbox(&mut conn, "test", "test-inbox", "INBOX", None, Some(previous), Some(vec![5, 9]))
+            .await
+            .unwrap();
+
+        assert_eq!(delta.vanished, vec![9]);
+        assert!(server.commands().contains(&"UID SEARCH UID 1:9".to_string()));
+    }
+
+    #[tokio::test]
+    async fn uid_thread_reads_nested_threads_and_keeps_the_session_usable() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 THREAD=REFERENCES".to_string()]),
+            ("UID THREAD", vec!["* THREAD (2)(3 6 (4 23)(44 7 96))".to_string()]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let tree = uid_thread(&mut conn, "ALL").await.unwrap();
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(thread_uids(&tree), vec![2, 3, 6, 4, 23, 44, 7, 96]);
+        assert_eq!(tree[1].children[0].children.len(), 2);
+        assert!(server.commands().contains(&"UID THREAD REFERENCES UTF-8 ALL".to_string()));
+        // La risposta THREAD non deve bloccare lo stream della sessione
+        conn.session().unwrap().noop().await.unwrap();
+        assert!(conn.is_alive());
+    }
+
+    #[tokio::test]
+    async fn reads_gmail_thread_ids_from_fetch() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 X-GM-EXT-1".to_string()]),
+            ("UID FETCH", vec![
+                "* 1 FETCH (X-GM-THRID 1278455344230334865 UID 4)".to_string(),
+                "* 2 FETCH (UID 7 X-GM-THRID 1278455344230334866)".to_string(),
+            ]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let thread_ids = gmail_thread_ids(&mut conn, &[4, 7]).await.unwrap();
+
+        assert_eq!(thread_ids.len(), 2);
+        assert_eq!(thread_ids[&4], "1278455344230334865");
+        assert_eq!(thread_ids[&7], "1278455344230334866");
+        assert!(server.commands().contains(&"UID FETCH 4,7 (UID X-GM-THRID)".to_string()));
+    }
+
+    #[test]
+    fn compresses_unsorted_uids_with_duplicates() {
+        assert_eq!(compress_uid_set(&[9, 3, 4, 3, 5, 1]), "1,3:5,9");
+        assert_eq!(compress_uid_set(&[7]), "7");
+        assert_eq!(compress_uid_set(&[u32::MAX, u32::MAX - 1]), format!("{}:{}", u32::MAX - 1, u32::MAX));
+        assert_eq!(compress_uid_set(&[]), "");
+    }
+
+    #[test]
+    fn expands_uid_sets() {
+        assert_eq!(expand_uid_set("4:6,9"), Some(vec![4, 5, 6, 9]));
+        // RFC 4315: gli estremi possono essere in ordine inverso
+        assert_eq!(expand_uid_set("120:118,3"), Some(vec![118, 119, 120, 3]));
+        assert_eq!(expand_uid_set(&compress_uid_set(&[12, 10, 11, 40])), Some(vec![10, 11, 12, 40]));
+        // "*" dipende dallo stato della cartella e non può essere espanso localmente
+        assert_eq!(expand_uid_set("4:*"), None);
+        assert_eq!(expand_uid_set("*"), None);
+        assert_eq!(expand_uid_set(""), None);
+    }
+
+    fn copyuid(line: &str) -> Option<CopyUid> {
+        let (_, response) = imap_proto::parser::parse_response(line.as_bytes()).expect("risposta valida");
+        match response {
+            Response::Data { code: Some(code), .. } | Response::Done { code: Some(code), .. } => parse_copyuid(&code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn maps_copyuid_ranges_with_different_shapes() {
+        let copy = copyuid("* OK [COPYUID 38505 4,6:8 20:23] spostati\r\n").unwrap();
+
+        assert_eq!(copy.uid_validity, 38505);
+        assert_eq!(copy.target_for(4), Some(20));
+        assert_eq!(copy.target_for(6), Some(21));
+        assert_eq!(copy.target_for(8), Some(23));
+        assert_eq!(copy.target_for(5), None);
+    }
+
+    #[test]
+    fn maps_reversed_copyuid_ranges_in_ascending_order() {
+        let copy = copyuid("A3 OK [COPYUID 7 120:118 3,1:2] fatto\r\n").unwrap();
+
+        assert_eq!(copy.source, vec![118, 119, 120]);
+        assert_eq!(copy.target, vec![3, 1, 2]);
+        assert_eq!(copy.target_for(118), Some(3));
+        assert_eq!(copy.target_for(120), Some(2));
+    }
+
+    #[test]
+    fn rejects_copyuid_with_mismatched_lengths() {
+        assert!(copyuid("* OK [COPYUID 7 1:3 10:11] incompleto\r\n").is_none());
+        assert!(copyuid("* OK [UIDNEXT 4] altro codice\r\n").is_none());
     }
 }
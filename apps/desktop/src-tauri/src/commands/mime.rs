@@ -0,0 +1,714 @@
+use std::borrow::Cow;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use imap_proto::types::{BodyContentSinglePart, BodyParams, BodyStructure, ContentEncoding};
+use serde::{Deserialize, Serialize};
+
+/// Parte testuale individuata nella BODYSTRUCTURE di un messaggio
+#[derive(Debug, Clone)]
+pub struct TextPart {
+    /// Percorso della sezione IMAP (es. [1, 2] per BODY[1.2])
+    pub section: Vec<u32>,
+    /// Sottotipo MIME in minuscolo ("plain", "html")
+    pub subtype: String,
+    pub charset: Option<String>,
+    pub encoding: TransferEncoding,
+    /// text/plain; format=flowed (RFC 3676), con l'eventuale delsp=yes
+    pub flowed: bool,
+    pub delsp: bool,
+}
+
+/// Content-Transfer-Encoding di una parte
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferEncoding {
+    Identity,
+    Base64,
+    QuotedPrintable,
+}
+
+impl TextPart {
+    /// Sezione nel formato usato da BODY.PEEK[...]
+    pub fn section_spec(&self) -> String {
+        self.section
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// Decodifica il contenuto grezzo della sezione in testo
+    pub fn decode(&self, raw: &[u8]) -> String {
+        let bytes = decode_transfer_encoding(raw, self.encoding);
+        let text = decode_charset(&bytes, self.charset.as_deref());
+        if self.flowed {
+            unflow(&text, self.delsp)
+        } else {
+            text
+        }
+    }
+}
+
+/// Disposizione di un allegato: da mostrare nel corpo (es. immagini cid:) o da scaricare
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentDisposition {
+    Inline,
+    Attachment,
+}
+
+/// Allegato individuato nella BODYSTRUCTURE
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub filename: Option<String>,
+    pub mime_type: String,
+    /// Dimensione codificata in byte riportata dal server
+    pub size: u32,
+    pub content_id: Option<String>,
+    pub disposition: AttachmentDisposition,
+    /// Percorso della parte MIME (es. "2.1"), da passare a `fetch_attachment`
+    pub part: String,
+}
+
+/// Parti del corpo scelte in un sottoalbero MIME
+#[derive(Default)]
+struct BodyParts {
+    text: Option<TextPart>,
+    html: Option<TextPart>,
+}
+
+impl BodyParts {
+    fn is_empty(&self) -> bool {
+        self.text.is_none() && self.html.is_none()
+    }
+}
+
+/// Individua le parti text/plain e text/html da visualizzare a partire dalla BODYSTRUCTURE.
+/// Restituisce (text/plain, text/html), ignorando allegati e messaggi inoltrati.
+pub fn find_text_parts(structure: &BodyStructure) -> (Option<TextPart>, Option<TextPart>) {
+    let parts = select_body_parts(structure, &[]);
+    (parts.text, parts.html)
+}
+
+/// Visita ricorsiva dell'albero MIME:
+/// - multipart/alternative: le alternative sono in ordine di fedeltà crescente, vince l'ultima
+/// - multipart/related: il corpo è nella radice (parametro start o prima parte)
+/// - altri multipart (mixed, signed, ...): il primo figlio che contiene un corpo
+fn select_body_parts(structure: &BodyStructure, section: &[u32]) -> BodyParts {
+    match structure {
+        BodyStructure::Multipart { common, bodies, .. } => {
+            let child_section = |index: usize| {
+                let mut child = section.to_vec();
+                child.push(index as u32 + 1);
+                child
+            };
+
+            match common.ty.subtype.to_ascii_lowercase().as_str() {
+                "alternative" => {
+                    let mut result = BodyParts::default();
+                    for (index, body) in bodies.iter().enumerate() {
+                        let parts = select_body_parts(body, &child_section(index));
+                        result.text = parts.text.or(result.text);
+                        result.html = parts.html.or(result.html);
+                    }
+                    result
+                }
+                "related" => {
+                    let start = body_param(&common.ty.params, "start").map(|s| normalize_content_id(&s));
+                    let root = start
+                        .and_then(|start| bodies.iter().position(|b| content_id(b).as_deref() == Some(start.as_str())))
+                        .unwrap_or(0);
+                    bodies
+                        .get(root)
+                        .map(|body| select_body_parts(body, &child_section(root)))
+                        .unwrap_or_default()
+                }
+                _ => bodies
+                    .iter()
+                    .enumerate()
+                    .map(|(index, body)| select_body_parts(body, &child_section(index)))
+                    .find(|parts| !parts.is_empty())
+                    .unwrap_or_default(),
+            }
+        }
+        BodyStructure::Text { common, other, .. } => {
+            let is_attachment = common
+                .disposition
+                .as_ref()
+                .map(|d| d.ty.eq_ignore_ascii_case("attachment"))
+                .unwrap_or(false);
+            if is_attachment {
+                return BodyParts::default();
+            }
+
+            let subtype = common.ty.subtype.to_ascii_lowercase();
+            let param = |name: &str| body_param(&common.ty.params, name).map(|v| v.to_ascii_lowercase());
+            let part = TextPart {
+                // Un messaggio non multipart ha il corpo nella sezione 1
+                section: if section.is_empty() { vec![1] } else { section.to_vec() },
+                charset: body_param(&common.ty.params, "charset"),
+                encoding: match other.transfer_encoding {
+                    ContentEncoding::Base64 => TransferEncoding::Base64,
+                    ContentEncoding::QuotedPrintable => TransferEncoding::QuotedPrintable,
+                    _ => TransferEncoding::Identity,
+                },
+                flowed: subtype == "plain" && param("format").as_deref() == Some("flowed"),
+                delsp: param("delsp").as_deref() == Some("yes"),
+                subtype,
+            };
+
+            match part.subtype.as_str() {
+                "plain" => BodyParts { text: Some(part), html: None },
+                "html" => BodyParts { text: None, html: Some(part) },
+                _ => BodyParts::default(),
+            }
+        }
+        // Allegati e message/rfc822 non contengono il corpo da visualizzare
+        _ => BodyParts::default(),
+    }
+}
+
+/// Content-ID di una parte non multipart, senza parentesi angolari
+fn content_id(structure: &BodyStructure) -> Option<String> {
+    let other = match structure {
+        BodyStructure::Basic { other, .. }
+        | BodyStructure::Text { other, .. }
+        | BodyStructure::Message { other, .. } => other,
+        BodyStructure::Multipart { .. } => return None,
+    };
+    other.id.as_deref().map(normalize_content_id)
+}
+
+fn normalize_content_id(id: &str) -> String {
+    id.trim().trim_start_matches('<').trim_end_matches('>').to_string()
+}
+
+/// Elenca gli allegati: tutte le parti foglia che non sono il corpo visualizzato.
+/// I messaggi inoltrati (message/rfc822) sono un unico allegato.
+pub fn find_attachments(structure: &BodyStructure) -> Vec<Attachment> {
+    let (text, html) = find_text_parts(structure);
+    let body_sections: Vec<Vec<u32>> = text.into_iter().chain(html).map(|p| p.section).collect();
+
+    let mut attachments = Vec::new();
+    collect_attachments(structure, &[], &body_sections, &mut attachments);
+    attachments
+}
+
+fn collect_attachments(
+    structure: &BodyStructure,
+    section: &[u32],
+    body_sections: &[Vec<u32>],
+    out: &mut Vec<Attachment>,
+) {
+    let (common, other) = match structure {
+        BodyStructure::Multipart { bodies, .. } => {
+            for (index, body) in bodies.iter().enumerate() {
+                let mut child = section.to_vec();
+                child.push(index as u32 + 1);
+                collect_attachments(body, &child, body_sections, out);
+            }
+            return;
+        }
+        BodyStructure::Basic { common, other, .. }
+        | BodyStructure::Text { common, other, .. }
+        | BodyStructure::Message { common, other, .. } => (common, other),
+    };
+
+    // Un messaggio non multipart ha il contenuto nella sezione 1
+    let section = if section.is_empty() { vec![1] } else { section.to_vec() };
+    if body_sections.contains(&section) {
+        return;
+    }
+
+    let disposition_params = common.disposition.as_ref().and_then(|d| d.params.as_ref());
+    let filename = disposition_params
+        .and_then(|params| rfc2231_param(params, "filename"))
+        .or_else(|| common.ty.params.as_ref().and_then(|params| rfc2231_param(params, "name")))
+        .map(|name| decode_header_value(name.as_bytes()));
+
+    let is_inline = common
+        .disposition
+        .as_ref()
+        .map(|d| d.ty.eq_ignore_ascii_case("inline"))
+        .unwrap_or(false);
+    let content_id = other.id.as_deref().map(normalize_content_id);
+
+    out.push(Attachment {
+        filename,
+        mime_type: format!("{}/{}", common.ty.ty, common.ty.subtype).to_ascii_lowercase(),
+        size: other.octets,
+        // Senza disposition esplicita le parti con Content-ID sono immagini del corpo HTML
+        disposition: if is_inline || (common.disposition.is_none() && content_id.is_some()) {
+            AttachmentDisposition::Inline
+        } else {
+            AttachmentDisposition::Attachment
+        },
+        content_id,
+        part: section.iter().map(|n| n.to_string()).collect::<Vec<_>>().join("."),
+    });
+}
+
+/// Parte della BODYSTRUCTURE al percorso indicato (es. [2, 1] per BODY[2.1])
+pub fn find_part<'a>(structure: &'a BodyStructure<'a>, path: &[u32]) -> Option<&'a BodyStructure<'a>> {
+    let Some((&first, rest)) = path.split_first() else {
+        return Some(structure);
+    };
+    match structure {
+        BodyStructure::Multipart { bodies, .. } => {
+            find_part(bodies.get(first.checked_sub(1)? as usize)?, rest)
+        }
+        // Dentro un message/rfc822 la numerazione prosegue nel messaggio incapsulato
+        BodyStructure::Message { body, .. } => find_part(body, path),
+        // Un messaggio non multipart ha il contenuto nella sezione 1
+        _ if first == 1 && rest.is_empty() => Some(structure),
+        _ => None,
+    }
+}
+
+/// Content-Transfer-Encoding di una parte non multipart
+pub fn part_encoding(structure: &BodyStructure) -> TransferEncoding {
+    let other: &BodyContentSinglePart = match structure {
+        BodyStructure::Basic { other, .. }
+        | BodyStructure::Text { other, .. }
+        | BodyStructure::Message { other, .. } => other,
+        BodyStructure::Multipart { .. } => return TransferEncoding::Identity,
+    };
+    match other.transfer_encoding {
+        ContentEncoding::Base64 => TransferEncoding::Base64,
+        ContentEncoding::QuotedPrintable => TransferEncoding::QuotedPrintable,
+        _ => TransferEncoding::Identity,
+    }
+}
+
+/// Legge un parametro anche nella forma estesa RFC 2231 (`filename*=utf-8''...`)
+/// e con continuazioni (`filename*0*=...; filename*1*=...`)
+fn rfc2231_param(params: &[(Cow<'_, str>, Cow<'_, str>)], name: &str) -> Option<String> {
+    let find = |key: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.to_string())
+    };
+
+    if let Some(value) = find(name) {
+        return Some(value);
+    }
+
+    // Il charset è indicato solo nel primo segmento esteso: `charset'lingua'testo`
+    let mut charset = String::new();
+    let mut bytes = Vec::new();
+    if let Some(value) = find(&format!("{}*", name)) {
+        bytes = percent_decode(&split_rfc2231_charset(&value, &mut charset));
+    } else {
+        // Continuazioni numerate: solo quelle con "*" finale sono percent-encoded
+        for index in 0.. {
+            if let Some(segment) = find(&format!("{}*{}*", name, index)) {
+                let segment = if index == 0 { split_rfc2231_charset(&segment, &mut charset) } else { segment };
+                bytes.extend(percent_decode(&segment));
+            } else if let Some(segment) = find(&format!("{}*{}", name, index)) {
+                bytes.extend_from_slice(segment.as_bytes());
+            } else {
+                break;
+            }
+        }
+    }
+
+    if bytes.is_empty() {
+        return None;
+    }
+    Some(decode_charset(&bytes, Some(charset.as_str()).filter(|c| !c.is_empty())))
+}
+
+/// Separa `charset'lingua'` dal resto del valore, salvando il charset
+fn split_rfc2231_charset(value: &str, charset: &mut String) -> String {
+    let mut parts = value.splitn(3, '\'');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(found), Some(_language), Some(rest)) => {
+            *charset = found.to_string();
+            rest.to_string()
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let byte = std::str::from_utf8(&bytes[index + 1..index + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if let Some(byte) = byte {
+                result.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        result.push(bytes[index]);
+        index += 1;
+    }
+    result
+}
+
+/// Cerca un parametro (case-insensitive) nella lista di parametri di BODYSTRUCTURE
+pub fn body_param(params: &BodyParams<'_>, name: &str) -> Option<String> {
+    params.as_ref().and_then(|params| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_string())
+    })
+}
+
+/// Decodifica le encoded-word RFC 2047 (=?charset?B?...?=) di un valore di header grezzo
+pub fn decode_header_value(raw: &[u8]) -> String {
+    let mut line = b"X: ".to_vec();
+    line.extend_from_slice(raw);
+    match mailparse::parse_header(&line) {
+        Ok((header, _)) => header.get_value(),
+        Err(_) => String::from_utf8_lossy(raw).into_owned(),
+    }
+}
+
+/// Rimuove base64 o quoted-printable dal contenuto di una parte
+pub fn decode_transfer_encoding(raw: &[u8], encoding: TransferEncoding) -> Vec<u8> {
+    match encoding {
+        TransferEncoding::Identity => raw.to_vec(),
+        TransferEncoding::Base64 => {
+            let cleaned: Vec<u8> = raw
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            BASE64_STANDARD.decode(&cleaned).unwrap_or_else(|e| {
+                println!("[MIME] Errore nella decodifica base64: {}", e);
+                raw.to_vec()
+            })
+        }
+        TransferEncoding::QuotedPrintable => {
+            quoted_printable::decode(raw, quoted_printable::ParseMode::Robust)
+                .unwrap_or_else(|_| raw.to_vec())
+        }
+    }
+}
+
+/// Converte i byte nel charset dichiarato in UTF-8.
+/// Con charset assente o sconosciuto usa UTF-8 se valido, altrimenti windows-1252,
+/// il caso più comune nei messaggi che non dichiarano la codifica.
+pub fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    let label = charset.map(|label| label.trim().trim_matches('"').to_ascii_lowercase());
+    let charset = label.as_deref().and_then(|label| {
+        // Alias diffusi che le tabelle WHATWG non riconoscono
+        let label = match label {
+            "x-gbk" | "cp936" | "gb_2312-80" => "gb18030",
+            "cp1252" | "x-cp1252" => "windows-1252",
+            "cp932" | "x-sjis" => "shift_jis",
+            "ks_c_5601-1987" | "cp949" => "euc-kr",
+            "iso-2022-jp-1" | "iso-2022-jp-2" | "iso-2022-jp-3" => "iso-2022-jp",
+            other => other,
+        };
+        charset::Charset::for_label(label.as_bytes())
+    });
+
+    match charset {
+        Some(charset) => charset.decode_without_bom_handling(bytes).0.into_owned(),
+        None => match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => charset::Charset::for_label(b"windows-1252")
+                .map(|latin| latin.decode_without_bom_handling(bytes).0.into_owned())
+                .unwrap_or_else(|| String::from_utf8_lossy(bytes).into_owned()),
+        },
+    }
+}
+
+/// Ricompone le righe di un testo format=flowed (RFC 3676): una riga che termina con
+/// spazio continua nella successiva con lo stesso livello di citazione
+fn unflow(text: &str, delsp: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    // Paragrafo in costruzione: livello di citazione e contenuto
+    let mut paragraph: Option<(usize, String)> = None;
+
+    // L'a capo finale non apre una nuova riga
+    let body = text.strip_suffix('\n').unwrap_or(text);
+    for line in body.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let depth = line.chars().take_while(|c| *c == '>').count();
+        let content = &line[depth..];
+        // Space-stuffing: lo spazio iniziale aggiunto dal mittente va rimosso
+        let content = content.strip_prefix(' ').unwrap_or(content);
+
+        // Un livello di citazione diverso chiude il paragrafo precedente
+        if let Some((current, _)) = &paragraph {
+            if *current != depth {
+                flush_flowed(&mut result, paragraph.take());
+            }
+        }
+
+        let is_signature = content == "-- ";
+        let (piece, flowed) = match content.strip_suffix(' ') {
+            Some(trimmed) if !is_signature => (if delsp { trimmed } else { content }, true),
+            _ => (content, false),
+        };
+
+        let entry = paragraph.get_or_insert_with(|| (depth, String::new()));
+        entry.1.push_str(piece);
+        if !flowed {
+            flush_flowed(&mut result, paragraph.take());
+        }
+    }
+    flush_flowed(&mut result, paragraph.take());
+
+    // Ogni riga chiusa aggiunge un a capo: l'ultimo va tolto se l'originale non lo aveva
+    if !text.ends_with('\n') {
+        result.pop();
+    }
+    result
+}
+
+fn flush_flowed(result: &mut String, paragraph: Option<(usize, String)>) {
+    if let Some((depth, content)) = paragraph {
+        if depth > 0 {
+            result.push_str(&">".repeat(depth));
+            // Una riga citata vuota resta ">" senza spazio finale
+            if !content.is_empty() {
+                result.push(' ');
+            }
+        }
+        result.push_str(&content);
+        result.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mailparse::body::Body;
+    use std::path::Path;
+
+    /// Messaggio .eml dalla raccolta in tests/fixtures/mime
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mime").join(name);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("fixture {}: {}", path.display(), e))
+    }
+
+    /// BODYSTRUCTURE come la restituirebbe il server per la fixture
+    fn body_structure(raw: &str) -> BodyStructure<'static> {
+        let response = format!("* 1 FETCH (UID 1 BODYSTRUCTURE {})\r\n", raw);
+        match imap_proto::parser::parse_response(response.as_bytes()) {
+            Ok((_, imap_proto::Response::Fetch(_, attributes))) => attributes
+                .into_iter()
+                .find_map(|attribute| match attribute {
+                    imap_proto::AttributeValue::BodyStructure(structure) => Some(structure.into_owned()),
+                    _ => None,
+                })
+                .expect("BODYSTRUCTURE assente"),
+            other => panic!("BODYSTRUCTURE non valida: {:?}", other),
+        }
+    }
+
+    /// Contenuto grezzo di BODY[section], come lo invierebbe il server:
+    /// il corpo della parte ancora nel suo Content-Transfer-Encoding
+    fn section(message: &[u8], path: &[u32]) -> Vec<u8> {
+        let mail = mailparse::parse_mail(message).expect("messaggio valido");
+        let mut part = &mail;
+        for (depth, &index) in path.iter().enumerate() {
+            if part.subparts.is_empty() {
+                // RFC 3501: la sezione 1 di un messaggio non multipart è il corpo stesso
+                assert!(index == 1 && depth == path.len() - 1, "sezione {:?} in una parte non multipart", path);
+            } else {
+                part = &part.subparts[index as usize - 1];
+            }
+        }
+        match part.get_body_encoded() {
+            Body::Base64(body) | Body::QuotedPrintable(body) => body.get_raw().to_vec(),
+            Body::SevenBit(body) | Body::EightBit(body) => body.get_raw().to_vec(),
+            Body::Binary(body) => body.get_raw().to_vec(),
+        }
+    }
+
+    #[test]
+    fn undeclared_charset_falls_back_to_windows_1252() {
+        let message = fixture("windows-1252-undeclared.eml");
+        let structure = body_structure(r#"("TEXT" "PLAIN" NIL NIL NIL "8BIT" 96 4 NIL NIL NIL NIL)"#);
+
+        let (text, html) = find_text_parts(&structure);
+        let text = text.expect("parte text/plain");
+        assert!(html.is_none());
+        assert_eq!(text.section, vec![1]);
+        assert_eq!(text.charset, None);
+        assert_eq!(
+            text.decode(&section(&message, &text.section)),
+            "Buongiorno,\nil caffè costa 2 € al chilo, consegna entro venerdì.\n\nCordiali saluti\nMario\n"
+        );
+        assert!(find_attachments(&structure).is_empty());
+    }
+
+    #[test]
+    fn decode_charset_fallbacks_and_aliases() {
+        // Senza charset: UTF-8 se valido, altrimenti windows-1252
+        assert_eq!(decode_charset("perché".as_bytes(), None), "perché");
+        assert_eq!(decode_charset(b"perch\xe9", None), "perché");
+        // Charset sconosciuto: stesso comportamento del charset assente
+        assert_eq!(decode_charset(b"perch\xe9", Some("unknown-8bit")), "perché");
+        assert_eq!(decode_charset("perché".as_bytes(), Some("x-unknown")), "perché");
+        // Etichette con virgolette, maiuscole e alias non WHATWG
+        assert_eq!(decode_charset(b"perch\xe9", Some("\"ISO-8859-1\"")), "perché");
+        assert_eq!(decode_charset(b"2 \x80", Some("CP1252")), "2 €");
+        assert_eq!(decode_charset(b"\xbe\xc8\xb3\xe7", Some("ks_c_5601-1987")), "안녕");
+        assert_eq!(decode_charset(b"\xc4\xe3\xba\xc3", Some("x-gbk")), "你好");
+        assert_eq!(decode_charset(b"\x82\xb1\x82\xf1", Some("cp932")), "こん");
+    }
+
+    #[test]
+    fn japanese_iso_2022_jp_body_and_headers() {
+        let message = fixture("iso-2022-jp.eml");
+        let structure = body_structure(r#"("TEXT" "PLAIN" ("CHARSET" "ISO-2022-JP") NIL NIL "7BIT" 151 7 NIL NIL NIL NIL)"#);
+
+        let text = find_text_parts(&structure).0.expect("parte text/plain");
+        assert_eq!(text.charset.as_deref(), Some("ISO-2022-JP"));
+        assert_eq!(text.encoding, TransferEncoding::Identity);
+        assert_eq!(
+            text.decode(&section(&message, &text.section)),
+            "田中様\n\nお世話になっております。\n来週の会議は火曜日の10時からに変更となりました。\n\nよろしくお願いいたします。\n山田\n"
+        );
+
+        // Oggetto e mittente codificati RFC 2047 nello stesso charset
+        let (headers, _) = mailparse::parse_headers(&message).expect("header validi");
+        let raw = |name: &str| headers.iter().find(|h| h.get_key() == name).expect("header presente").get_value_raw();
+        assert_eq!(decode_header_value(raw("Subject")), "会議の日程について");
+        assert_eq!(decode_header_value(raw("From")), "山田 太郎 <yamada@example.jp>");
+    }
+
+    #[test]
+    fn outlook_ks_c_5601_alternative() {
+        let message = fixture("ks_c_5601-alternative.eml");
+        let structure = body_structure(
+            r#"(("TEXT" "PLAIN" ("CHARSET" "ks_c_5601-1987") NIL NIL "BASE64" 54 1 NIL NIL NIL NIL)("TEXT" "HTML" ("CHARSET" "ks_c_5601-1987") NIL NIL "BASE64" 102 2 NIL NIL NIL NIL) "ALTERNATIVE" ("BOUNDARY" "_000_SL2P216MB1234ABCD_") NIL NIL NIL)"#,
+        );
+
+        let (text, html) = find_text_parts(&structure);
+        let (text, html) = (text.expect("parte text/plain"), html.expect("parte text/html"));
+        assert_eq!(text.section, vec![1]);
+        assert_eq!(html.section, vec![2]);
+        assert_eq!(text.encoding, TransferEncoding::Base64);
+        assert_eq!(text.decode(&section(&message, &text.section)), "안녕하세요,\n회의는 내일입니다.\n");
+        assert!(html
+            .decode(&section(&message, &html.section))
+            .contains("<p>안녕하세요,</p><p>회의는 내일입니다.</p>"));
+        assert!(find_attachments(&structure).is_empty());
+    }
+
+    #[test]
+    fn apple_mail_format_flowed_with_delsp() {
+        let message = fixture("flowed-delsp.eml");
+        let structure = body_structure(
+            r#"("TEXT" "PLAIN" ("CHARSET" "utf-8" "DELSP" "yes" "FORMAT" "flowed") NIL NIL "8BIT" 300 8 NIL NIL NIL NIL)"#,
+        );
+
+        let text = find_text_parts(&structure).0.expect("parte text/plain");
+        assert!(text.flowed && text.delsp);
+        assert_eq!(
+            text.decode(&section(&message, &text.section)),
+            "Ciao Anna, il treno parte alle 8:15 e arriva a Milano poco prima delle 10. \
+             Ci vediamo in stazione, davanti alla libreria.\n\
+             \n\
+             Luca\n\
+             \n\
+             > Il giorno 14 mar 2024, alle ore 17:02, Anna <anna@example.com> ha scritto:\n\
+             >\n\
+             > A che ora è il treno?\n"
+        );
+    }
+
+    #[test]
+    fn thunderbird_format_flowed_with_quotes_and_signature() {
+        let message = fixture("flowed-quoted.eml");
+        let structure = body_structure(
+            r#"("TEXT" "PLAIN" ("CHARSET" "UTF-8" "FORMAT" "flowed") NIL NIL "8BIT" 260 10 NIL NIL NIL NIL)"#,
+        );
+
+        let text = find_text_parts(&structure).0.expect("parte text/plain");
+        assert!(text.flowed && !text.delsp);
+        assert_eq!(
+            text.decode(&section(&message, &text.section)),
+            "Il verbale va bene, aggiungerei solo la data della prossima riunione.\n\
+             \n\
+             Il 14/03/24 19:20, Anna ha scritto:\n\
+             > Ti giro il verbale di oggi, dimmi se manca qualcosa.\n\
+             >> Riunione spostata a lunedì.\n\
+             \n\
+             -- \n\
+             Giulia Verdi\n"
+        );
+    }
+
+    #[test]
+    fn unflow_keeps_fixed_lines_and_trailing_newline() {
+        // Righe senza spazio finale restano separate, anche se lunghe
+        assert_eq!(unflow("prima riga\nseconda riga", false), "prima riga\nseconda riga");
+        // Space-stuffing rimosso anche senza citazione
+        assert_eq!(unflow(" From nessuno\n", false), "From nessuno\n");
+        // Un cambio di livello di citazione chiude il paragrafo anche se la riga è flowed
+        assert_eq!(unflow("> citata \nnon citata\n", false), "> citata \nnon citata\n");
+        assert_eq!(unflow("parola \r\nspezzata\r\n", true), "parolaspezzata\n");
+    }
+
+    const RELATED_STRUCTURE: &str = r#"((("TEXT" "PLAIN" ("CHARSET" "utf-8") NIL NIL "QUOTED-PRINTABLE" 38 1 NIL NIL NIL NIL)(("IMAGE" "PNG" ("NAME" "logo.png") "<logo.1a2b@example.com>" NIL "BASE64" 98 NIL NIL NIL NIL)("TEXT" "HTML" ("CHARSET" "utf-8") "<body.1a2b@example.com>" NIL "QUOTED-PRINTABLE" 120 2 NIL NIL NIL NIL) "RELATED" ("TYPE" "text/html" "START" "<body.1a2b@example.com>" "BOUNDARY" "rel-44d0") NIL NIL NIL) "ALTERNATIVE" ("BOUNDARY" "alt-91c2") NIL NIL NIL)("APPLICATION" "PDF" ("NAME" "listino.pdf") NIL NIL "BASE64" 106 NIL ("ATTACHMENT" ("FILENAME" "listino.pdf")) NIL NIL) "MIXED" ("BOUNDARY" "mixed-7f3a") NIL NIL NIL)"#;
+
+    #[test]
+    fn multipart_related_uses_start_part_as_root() {
+        let message = fixture("related-start.eml");
+        let structure = body_structure(RELATED_STRUCTURE);
+
+        let (text, html) = find_text_parts(&structure);
+        let (text, html) = (text.expect("parte text/plain"), html.expect("parte text/html"));
+        // La radice indicata da start è la seconda parte del related, non la prima
+        assert_eq!(text.section_spec(), "1.1");
+        assert_eq!(html.section_spec(), "1.2.2");
+        assert_eq!(text.decode(&section(&message, &text.section)).trim_end(), "Le novità di marzo sono online.");
+        let html = html.decode(&section(&message, &html.section));
+        assert!(html.contains(r#"<img src="cid:logo.1a2b@example.com">"#));
+        assert!(html.contains("<p>Le novità di marzo sono online.</p>"));
+    }
+
+    #[test]
+    fn multipart_related_without_start_uses_first_part() {
+        let structure = body_structure(&RELATED_STRUCTURE.replace(r#""START" "<body.1a2b@example.com>" "#, ""));
+
+        // La prima parte del related è l'immagine: resta solo l'alternativa testuale
+        let (text, html) = find_text_parts(&structure);
+        assert_eq!(text.expect("parte text/plain").section_spec(), "1.1");
+        assert!(html.is_none());
+    }
+
+    #[test]
+    fn multipart_related_attachments() {
+        let message = fixture("related-start.eml");
+        let structure = body_structure(RELATED_STRUCTURE);
+
+        let attachments = find_attachments(&structure);
+        assert_eq!(attachments.len(), 2);
+
+        let logo = &attachments[0];
+        assert_eq!(logo.part, "1.2.1");
+        assert_eq!(logo.mime_type, "image/png");
+        assert_eq!(logo.filename.as_deref(), Some("logo.png"));
+        assert_eq!(logo.content_id.as_deref(), Some("logo.1a2b@example.com"));
+        assert_eq!(logo.disposition, AttachmentDisposition::Inline);
+
+        let listino = &attachments[1];
+        assert_eq!(listino.part, "2");
+        assert_eq!(listino.mime_type, "application/pdf");
+        assert_eq!(listino.filename.as_deref(), Some("listino.pdf"));
+        assert_eq!(listino.disposition, AttachmentDisposition::Attachment);
+
+        // Il contenuto decodificato delle parti corrisponde ai file originali
+        for (attachment, magic) in [(logo, &b"\x89PNG\r\n\x1a\n"[..]), (listino, &b"%PDF-1.4\n"[..])] {
+            let path: Vec<u32> = attachment.part.split('.').map(|n| n.parse().unwrap()).collect();
+            let part = find_part(&structure, &path).expect("parte dell'allegato");
+            let content = decode_transfer_encoding(&section(&message, &path), part_encoding(part));
+            assert!(content.starts_with(magic), "{}: {:?}", attachment.part, &content[..8]);
+        }
+    }
+}
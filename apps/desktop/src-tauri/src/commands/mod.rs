@@ -1,4 +1,21 @@
+pub mod account;
+pub mod address;
+pub mod attachments;
+pub mod auth;
+pub mod compose;
+pub mod discovery;
+pub mod flags;
+pub mod idle;
 pub mod imap;
+pub mod mime;
+pub mod oauth;
+pub mod pool;
+pub mod responses;
 pub mod smtp;
+pub mod special_use;
 pub mod system;
-
+#[cfg(test)]
+mod test_support;
+pub mod threading;
+pub mod token_store;
+pub mod utf7;
@@ -0,0 +1,678 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::State;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::account::{verify_login, AccountConfig, AccountManager, AuthMethod, Security, ServerConfig, StoredAccount};
+use super::auth::SessionError;
+use super::system::open_url_in_browser;
+use super::token_store::AccountSecret;
+
+/// Timeout delle richieste verso l'endpoint dei token
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Tempo concesso all'utente per completare il login nel browser
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Timeout per la lettura di una richiesta sul listener di loopback
+const CALLBACK_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Percorso del redirect URI sul listener di loopback
+const CALLBACK_PATH: &str = "/callback";
+
+/// Durata predefinita di un access token se il server non indica `expires_in`
+const DEFAULT_EXPIRES_IN: i64 = 3600;
+
+/// Parametri OAuth2 dell'account, usati per rinnovare l'access token.
+/// Per le app desktop il client secret non è confidenziale (RFC 8252, sezione 8.5).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuthSettings {
+    pub token_url: String,
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+}
+
+/// Parametri per il login OAuth2 interattivo nel browser di sistema
+#[derive(Debug, Clone)]
+struct OAuthLogin {
+    authorization_url: String,
+    scopes: Vec<String>,
+    /// Endpoint dei token e client, salvati con l'account per i refresh successivi
+    settings: OAuthSettings,
+    /// Parametri aggiuntivi dell'URL di autorizzazione (es. `prompt=consent`, `access_type=offline`)
+    extra_params: BTreeMap<String, String>,
+}
+
+/// Provider OAuth2 supportati. Endpoint, client e server sono definiti nel backend:
+/// il frontend indica solo il provider e non può dirottare codice o token altrove.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OAuthProvider {
+    Gmail,
+    Outlook,
+}
+
+impl OAuthProvider {
+    fn as_str(self) -> &'static str {
+        match self {
+            OAuthProvider::Gmail => "gmail",
+            OAuthProvider::Outlook => "outlook",
+        }
+    }
+
+    /// Client registrato per l'app: letto dall'ambiente all'avvio o, in mancanza, incluso in fase di build
+    fn settings(self) -> Result<OAuthSettings, String> {
+        let (token_url, client_id, client_secret) = match self {
+            OAuthProvider::Gmail => (
+                "https://oauth2.googleapis.com/token",
+                client_credential("GOOGLE_CLIENT_ID", option_env!("GOOGLE_CLIENT_ID")),
+                client_credential("GOOGLE_CLIENT_SECRET", option_env!("GOOGLE_CLIENT_SECRET")),
+            ),
+            OAuthProvider::Outlook => (
+                "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+                client_credential("OUTLOOK_CLIENT_ID", option_env!("OUTLOOK_CLIENT_ID")),
+                client_credential("OUTLOOK_CLIENT_SECRET", option_env!("OUTLOOK_CLIENT_SECRET")),
+            ),
+        };
+        let client_id = client_id.ok_or_else(|| {
+            format!(
+                "Client OAuth2 non configurato per {}: impostare {}_CLIENT_ID",
+                self.as_str(),
+                self.env_prefix()
+            )
+        })?;
+        Ok(OAuthSettings {
+            token_url: token_url.to_string(),
+            client_id,
+            client_secret,
+        })
+    }
+
+    fn env_prefix(self) -> &'static str {
+        match self {
+            OAuthProvider::Gmail => "GOOGLE",
+            OAuthProvider::Outlook => "OUTLOOK",
+        }
+    }
+
+    /// Endpoint, scope e parametri del login interattivo.
+    /// Gli scope OpenID servono a ricevere l'id_token con l'indirizzo dell'account.
+    fn login(self) -> Result<OAuthLogin, String> {
+        let (authorization_url, scopes, extra_params): (_, &[&str], &[(&str, &str)]) = match self {
+            OAuthProvider::Gmail => (
+                "https://accounts.google.com/o/oauth2/v2/auth",
+                &["https://mail.google.com/", "openid", "email", "profile"],
+                // Senza access_type=offline e prompt=consent Google non restituisce il refresh token
+                &[("access_type", "offline"), ("prompt", "consent")],
+            ),
+            OAuthProvider::Outlook => (
+                "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
+                &[
+                    "https://outlook.office.com/IMAP.AccessAsUser.All",
+                    "https://outlook.office.com/SMTP.Send",
+                    "offline_access",
+                    "openid",
+                    "email",
+                    "profile",
+                ],
+                &[("prompt", "select_account")],
+            ),
+        };
+        Ok(OAuthLogin {
+            authorization_url: authorization_url.to_string(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            settings: self.settings()?,
+            extra_params: extra_params
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        })
+    }
+
+    /// Account del provider per l'indirizzo indicato, con server IMAP e SMTP e autenticazione XOAUTH2
+    fn account(self, email: &str, settings: OAuthSettings) -> StoredAccount {
+        let server = |host: &str, port, security| ServerConfig {
+            host: host.to_string(),
+            port,
+            security,
+        };
+        let (imap, smtp) = match self {
+            OAuthProvider::Gmail => (
+                server("imap.gmail.com", 993, Security::Tls),
+                server("smtp.gmail.com", 465, Security::Tls),
+            ),
+            OAuthProvider::Outlook => (
+                server("outlook.office365.com", 993, Security::Tls),
+                server("smtp.office365.com", 587, Security::StartTls),
+            ),
+        };
+        StoredAccount {
+            id: format!("{}-{}", self.as_str(), email),
+            config: AccountConfig {
+                email: email.to_string(),
+                username: None,
+                imap,
+                smtp,
+                auth: AuthMethod::Xoauth2,
+            },
+            oauth: Some(settings),
+        }
+    }
+}
+
+fn client_credential(name: &str, built_in: Option<&'static str>) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .or_else(|| built_in.map(str::to_string))
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Account registrato da `oauth_login`, con il nome da mostrare letto dall'id_token
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuthAccount {
+    pub account: StoredAccount,
+    pub display_name: String,
+}
+
+/// Token ottenuti dall'endpoint OAuth2
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Scadenza dell'access token in secondi Unix
+    pub expires_at: i64,
+    /// id_token OpenID Connect, restituito dal login se sono stati richiesti gli scope OpenID
+    pub id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+    #[serde(default)]
+    id_token: Option<String>,
+}
+
+/// Claim dell'id_token usati per creare l'account
+#[derive(Debug, Deserialize)]
+struct IdentityClaims {
+    email: Option<String>,
+    /// Microsoft: nome utente dell'account, presente anche quando manca il claim email
+    preferred_username: Option<String>,
+    name: Option<String>,
+}
+
+impl IdentityClaims {
+    /// Legge i claim dal payload dell'id_token. La firma non viene verificata: il token arriva
+    /// direttamente dall'endpoint dei token su TLS (OpenID Connect Core, sezione 3.1.3.7).
+    fn from_id_token(id_token: &str) -> Result<Self, String> {
+        let payload = id_token
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| "id_token non valido".to_string())?;
+        let json = BASE64_URL
+            .decode(payload.trim_end_matches('='))
+            .map_err(|e| format!("id_token non valido: {}", e))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Claim dell'id_token non validi: {}", e))
+    }
+
+    fn email(&self) -> Option<&str> {
+        self.email
+            .as_deref()
+            .or(self.preferred_username.as_deref())
+            .filter(|email| email.contains('@'))
+    }
+}
+
+/// Errore restituito dall'endpoint dei token (RFC 6749, sezione 5.2)
+#[derive(Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Rinnova l'access token con il refresh token (RFC 6749, sezione 6).
+/// Il server può restituire un nuovo refresh token, che sostituisce il precedente.
+pub async fn refresh_access_token(settings: &OAuthSettings, refresh_token: &str) -> Result<OAuthTokens, String> {
+    let mut params = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", settings.client_id.as_str()),
+    ];
+    if let Some(secret) = &settings.client_secret {
+        params.push(("client_secret", secret.as_str()));
+    }
+    request_tokens(settings, &params).await
+}
+
+/// Scambia il codice di autorizzazione con i token, inviando il code verifier PKCE
+async fn exchange_code(
+    settings: &OAuthSettings,
+    code: &str,
+    code_verifier: &str,
+    redirect_uri: &str,
+) -> Result<OAuthTokens, String> {
+    let mut params = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", settings.client_id.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+    if let Some(secret) = &settings.client_secret {
+        params.push(("client_secret", secret.as_str()));
+    }
+    request_tokens(settings, &params).await
+}
+
+/// POST form-urlencoded verso l'endpoint dei token
+async fn request_tokens(settings: &OAuthSettings, params: &[(&str, &str)]) -> Result<OAuthTokens, String> {
+    let client = reqwest::Client::builder()
+        .timeout(TOKEN_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Errore nella creazione del client HTTP: {}", e))?;
+    let response = client
+        .post(&settings.token_url)
+        .form(params)
+        .send()
+        .await
+        .map_err(|e| format!("Errore nella richiesta del token: {}", e))?;
+
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("Errore nella lettura della risposta del token: {}", e))?;
+
+    if !status.is_success() {
+        return Err(match serde_json::from_str::<TokenErrorResponse>(&body) {
+            // invalid_grant: refresh token revocato o scaduto, serve un nuovo login
+            Ok(error) if error.error == "invalid_grant" => {
+                "Autorizzazione OAuth2 revocata o scaduta: è necessario rifare il login".to_string()
+            }
+            Ok(error) => format!(
+                "Errore OAuth2 {}: {}",
+                error.error,
+                error.error_description.unwrap_or_default()
+            ),
+            Err(_) => format!("Errore nella richiesta del token ({}): {}", status, body),
+        });
+    }
+
+    let tokens: TokenResponse = serde_json::from_str(&body)
+        .map_err(|e| format!("Risposta del token non valida: {}", e))?;
+    Ok(OAuthTokens {
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_at: chrono::Utc::now().timestamp() + tokens.expires_in.unwrap_or(DEFAULT_EXPIRES_IN),
+        id_token: tokens.id_token,
+    })
+}
+
+/// Login OAuth2 con il provider indicato e registrazione dell'account nel backend.
+/// Indirizzo e nome vengono letti dall'id_token; le credenziali sono verificate con il server
+/// IMAP prima del salvataggio.
+#[tauri::command]
+pub async fn oauth_login(
+    accounts: State<'_, AccountManager>,
+    provider: OAuthProvider,
+) -> Result<OAuthAccount, SessionError> {
+    println!("[OAUTH] Login con provider: {}", provider.as_str());
+    let login = provider.login()?;
+    let tokens = authorize(&login, open_url_in_browser).await?;
+
+    let id_token = tokens
+        .id_token
+        .as_deref()
+        .ok_or_else(|| "Il provider non ha restituito l'id_token con l'indirizzo dell'account".to_string())?;
+    let identity = IdentityClaims::from_id_token(id_token)?;
+    let email = identity
+        .email()
+        .ok_or_else(|| "Indirizzo email non presente nell'id_token".to_string())?
+        .to_string();
+
+    let account = provider.account(&email, login.settings);
+    let secret = AccountSecret::Oauth {
+        access_token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        expires_at: tokens.expires_at,
+    };
+    // Token valido ma non accettato dal server IMAP (es. scope mancante): l'account non viene salvato
+    verify_login(&account.config, &secret).await?;
+    accounts.register(&account, Some(&secret))?;
+    println!("[OAUTH] Login completato per account: {}", account.id);
+    Ok(OAuthAccount {
+        display_name: identity.name.unwrap_or_else(|| email.clone()),
+        account,
+    })
+}
+
+/// Registra un account OAuth2 autorizzato prima che il login passasse dal backend.
+/// Server ed endpoint OAuth2 vengono dal provider: dal webview arrivano solo indirizzo e token.
+#[tauri::command]
+pub async fn import_oauth_account(
+    accounts: State<'_, AccountManager>,
+    provider: OAuthProvider,
+    email: String,
+    secret: AccountSecret,
+) -> Result<StoredAccount, SessionError> {
+    println!("[OAUTH] Importazione account {} ({})", email, provider.as_str());
+    let account = provider.account(&email, provider.settings()?);
+    verify_login(&account.config, &secret).await?;
+    accounts.register(&account, Some(&secret))?;
+    Ok(account)
+}
+
+/// Login OAuth2 con PKCE (RFC 7636) e redirect su loopback (RFC 8252, sezione 7.3).
+/// `open` apre l'URL di autorizzazione: il browser di sistema, o un browser finto nei test.
+/// Il codice viene ricevuto su una porta effimera di 127.0.0.1 e scambiato con i token.
+/// Funziona allo stesso modo in sviluppo e in produzione: non dipende dal dev server.
+async fn authorize<F, Fut>(login: &OAuthLogin, open: F) -> Result<OAuthTokens, String>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| format!("Errore nell'apertura del listener di loopback: {}", e))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| format!("Errore nella lettura della porta del listener: {}", e))?
+        .port();
+    let redirect_uri = format!("http://127.0.0.1:{}{}", port, CALLBACK_PATH);
+
+    let code_verifier = random_token(32);
+    let code_challenge = BASE64_URL.encode(Sha256::digest(code_verifier.as_bytes()));
+    let state = random_token(16);
+
+    let scope = login.scopes.join(" ");
+    let mut params = vec![
+        ("response_type", "code"),
+        ("client_id", login.settings.client_id.as_str()),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("scope", scope.as_str()),
+        ("state", state.as_str()),
+        ("code_challenge", code_challenge.as_str()),
+        ("code_challenge_method", "S256"),
+    ];
+    params.extend(login.extra_params.iter().map(|(name, value)| (name.as_str(), value.as_str())));
+    let authorization_url = Url::parse_with_params(&login.authorization_url, &params)
+        .map_err(|e| format!("URL di autorizzazione non valido: {}", e))?;
+
+    open(authorization_url.to_string()).await?;
+
+    let code = tokio::time::timeout(LOGIN_TIMEOUT, wait_for_code(&listener, &state))
+        .await
+        .map_err(|_| "Tempo scaduto per il login OAuth2 nel browser".to_string())??;
+    // Il listener serve solo per questo login: lo chiudiamo prima dello scambio
+    drop(listener);
+
+    println!("[OAUTH] Codice ricevuto, scambio con i token");
+    let tokens = exchange_code(&login.settings, &code, &code_verifier, &redirect_uri).await?;
+    if tokens.refresh_token.is_none() {
+        println!("[OAUTH] Nessun refresh token ricevuto: alla scadenza servirà un nuovo login");
+    }
+    Ok(tokens)
+}
+
+/// Attende il redirect del browser e restituisce il codice di autorizzazione.
+/// Le richieste su altri percorsi (es. /favicon.ico) o con uno state diverso vengono ignorate.
+async fn wait_for_code(listener: &TcpListener, state: &str) -> Result<String, String> {
+    loop {
+        let (mut stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| format!("Errore nell'accettazione della connessione di loopback: {}", e))?;
+
+        let target = match tokio::time::timeout(CALLBACK_READ_TIMEOUT, read_request_target(&mut stream)).await {
+            Ok(Ok(target)) => target,
+            Ok(Err(e)) => {
+                println!("[OAUTH] Richiesta non valida sul listener: {}", e);
+                continue;
+            }
+            Err(_) => continue,
+        };
+
+        let Ok(url) = Url::parse(&format!("http://127.0.0.1{}", target)) else {
+            respond(&mut stream, "400 Bad Request", "Richiesta non valida.").await;
+            continue;
+        };
+        if url.path() != CALLBACK_PATH {
+            respond(&mut stream, "404 Not Found", "Pagina non trovata.").await;
+            continue;
+        }
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        // Lo state protegge da redirect forgiati da altre pagine (CSRF)
+        if param("state").as_deref() != Some(state) {
+            respond(&mut stream, "400 Bad Request", "Parametro state non valido.").await;
+            continue;
+        }
+
+        if let Some(error) = param("error") {
+            respond(&mut stream, "200 OK", "Accesso non riuscito. Puoi chiudere questa finestra e tornare all'app.").await;
+            let description = param("error_description").unwrap_or_default();
+            return Err(format!("Autorizzazione OAuth2 negata: {} {}", error, description).trim_end().to_string());
+        }
+        match param("code") {
+            Some(code) => {
+                respond(&mut stream, "200 OK", "Accesso completato. Puoi chiudere questa finestra e tornare all'app.").await;
+                return Ok(code);
+            }
+            None => respond(&mut stream, "400 Bad Request", "Codice di autorizzazione mancante.").await,
+        }
+    }
+}
+
+/// Legge la request line e le intestazioni, restituendo il target (percorso e query)
+async fn read_request_target(stream: &mut TcpStream) -> Result<String, String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Le intestazioni non servono, ma vanno consumate prima di rispondere
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).await.map_err(|e| e.to_string())?;
+        if read == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", target, _] => Ok(target.to_string()),
+        _ => Err(format!("request line inattesa: {}", request_line.trim_end())),
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let body = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Mail Client</title></head><body><p>{}</p></body></html>",
+        message
+    );
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        println!("[OAUTH] Errore nella risposta al browser: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Valore casuale codificato in base64url, per code verifier (43 caratteri con 32 byte) e state
+fn random_token(bytes: usize) -> String {
+    let mut data = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut data);
+    BASE64_URL.encode(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::FakeHttpServer;
+    use std::sync::{Arc, Mutex};
+
+    fn query_param(url: &Url, name: &str) -> Option<String> {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    /// id_token non firmato con i claim indicati, come quello restituito dall'endpoint dei token
+    fn id_token(claims: &str) -> String {
+        format!("{}.{}.firma", BASE64_URL.encode(r#"{"alg":"RS256"}"#), BASE64_URL.encode(claims))
+    }
+
+    #[tokio::test]
+    async fn authorizes_with_pkce_against_a_fake_authorization_server() {
+        let token_response = format!(
+            r#"{{"access_token":"accesso","refresh_token":"rinnovo","expires_in":3600,"id_token":"{}"}}"#,
+            id_token(r#"{"email":"anna@example.test","name":"Anna Rossi"}"#)
+        );
+        let server = FakeHttpServer::start(vec![("/token".to_string(), token_response)]).await;
+        let login = OAuthLogin {
+            authorization_url: server.url("/authorize"),
+            scopes: vec!["mail".to_string(), "openid".to_string()],
+            settings: OAuthSettings {
+                token_url: server.url("/token"),
+                client_id: "client-id".to_string(),
+                client_secret: None,
+            },
+            extra_params: BTreeMap::from([("prompt".to_string(), "consent".to_string())]),
+        };
+
+        // Browser finto: il server di autorizzazione approva subito e reindirizza al loopback,
+        // preceduto da un redirect forgiato con uno state diverso che va ignorato
+        let opened = Arc::new(Mutex::new(None));
+        let browser = {
+            let opened = opened.clone();
+            move |url: String| async move {
+                let url = Url::parse(&url).map_err(|e| e.to_string())?;
+                let redirect_uri = query_param(&url, "redirect_uri").ok_or("redirect_uri mancante")?;
+                let state = query_param(&url, "state").ok_or("state mancante")?;
+                *opened.lock().unwrap() = Some(url);
+                tokio::spawn(async move {
+                    let forged = reqwest::get(format!("{}?code=rubato&state=altro", redirect_uri)).await.unwrap();
+                    assert_eq!(forged.status(), 400);
+                    let callback = reqwest::get(format!("{}?code=codice&state={}", redirect_uri, state)).await.unwrap();
+                    assert_eq!(callback.status(), 200);
+                });
+                Ok(())
+            }
+        };
+
+        let tokens = authorize(&login, browser).await.unwrap();
+        assert_eq!(tokens.access_token, "accesso");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("rinnovo"));
+        let identity = IdentityClaims::from_id_token(tokens.id_token.as_deref().unwrap()).unwrap();
+        assert_eq!(identity.email(), Some("anna@example.test"));
+        assert_eq!(identity.name.as_deref(), Some("Anna Rossi"));
+
+        let url = opened.lock().unwrap().take().unwrap();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(query_param(&url, "response_type").as_deref(), Some("code"));
+        assert_eq!(query_param(&url, "client_id").as_deref(), Some("client-id"));
+        assert_eq!(query_param(&url, "scope").as_deref(), Some("mail openid"));
+        assert_eq!(query_param(&url, "prompt").as_deref(), Some("consent"));
+        assert_eq!(query_param(&url, "code_challenge_method").as_deref(), Some("S256"));
+        let redirect_uri = query_param(&url, "redirect_uri").unwrap();
+        assert!(redirect_uri.starts_with("http://127.0.0.1:") && redirect_uri.ends_with(CALLBACK_PATH));
+
+        // Lo scambio usa il codice del redirect valido e il verifier che corrisponde alla challenge
+        let bodies = server.bodies();
+        assert_eq!(bodies.len(), 1);
+        let form: BTreeMap<String, String> = Url::parse(&format!("http://127.0.0.1/?{}", bodies[0]))
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["code"], "codice");
+        assert_eq!(form["client_id"], "client-id");
+        assert_eq!(form["redirect_uri"], redirect_uri);
+        assert!(!form.contains_key("client_secret"));
+        let challenge = BASE64_URL.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+        assert_eq!(query_param(&url, "code_challenge"), Some(challenge));
+    }
+
+    #[tokio::test]
+    async fn reports_a_denied_authorization_without_exchanging_the_code() {
+        let server = FakeHttpServer::start(Vec::new()).await;
+        let login = OAuthLogin {
+            authorization_url: server.url("/authorize"),
+            scopes: vec!["mail".to_string()],
+            settings: OAuthSettings {
+                token_url: server.url("/token"),
+                client_id: "client-id".to_string(),
+                client_secret: Some("segreto".to_string()),
+            },
+            extra_params: BTreeMap::new(),
+        };
+        let browser = |url: String| async move {
+            let url = Url::parse(&url).map_err(|e| e.to_string())?;
+            let redirect = format!(
+                "{}?error=access_denied&state={}",
+                query_param(&url, "redirect_uri").unwrap(),
+                query_param(&url, "state").unwrap()
+            );
+            tokio::spawn(reqwest::get(redirect));
+            Ok(())
+        };
+
+        let error = authorize(&login, browser).await.err().unwrap();
+        assert!(error.contains("access_denied"), "{}", error);
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn reads_the_account_address_from_the_id_token() {
+        // Microsoft: senza claim email l'indirizzo è nel preferred_username
+        let claims = IdentityClaims::from_id_token(&id_token(r#"{"preferred_username":"anna@outlook.test"}"#)).unwrap();
+        assert_eq!(claims.email(), Some("anna@outlook.test"));
+
+        let claims = IdentityClaims::from_id_token(&id_token(r#"{"preferred_username":"anna"}"#)).unwrap();
+        assert_eq!(claims.email(), None);
+
+        assert!(IdentityClaims::from_id_token("non-un-jwt").is_err());
+    }
+
+    #[test]
+    fn provider_accounts_use_xoauth2_over_tls() {
+        let settings = OAuthSettings {
+            token_url: "https://oauth2.googleapis.com/token".to_string(),
+            client_id: "client-id".to_string(),
+            client_secret: None,
+        };
+        let account = OAuthProvider::Gmail.account("anna@gmail.com", settings.clone());
+        assert_eq!(account.id, "gmail-anna@gmail.com");
+        assert_eq!(account.config.auth, AuthMethod::Xoauth2);
+        assert_eq!(account.config.imap.host, "imap.gmail.com");
+        assert_eq!(account.oauth, Some(settings.clone()));
+
+        let account = OAuthProvider::Outlook.account("anna@outlook.com", settings);
+        assert_eq!(account.config.smtp.security, Security::StartTls);
+        assert_eq!(account.config.imap.security, Security::Tls);
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use async_imap::types::{Capability, Mailbox};
+use async_imap::Session;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use super::account::{AccountConfig, AccountManager};
+use super::auth::SessionError;
+use super::imap::CombinedStream;
+use super::utf7::{decode_mailbox_name, encode_mailbox_name};
+
+/// Dopo questo periodo di inattività la sessione viene verificata con NOOP prima dell'uso
+const HEALTH_CHECK_AFTER: Duration = Duration::from_secs(60);
+
+/// Sessione IMAP autenticata mantenuta nel pool
+struct PooledSession {
+    session: Session<CombinedStream>,
+    capabilities: HashSet<String>,
+    selected: Option<String>,
+    /// Configurazione con cui è stata aperta la sessione
+    account: AccountConfig,
+    last_used: Instant,
+}
+
+/// Pool di sessioni IMAP, una per account, registrato nello stato Tauri con `app.manage`
+#[derive(Default)]
+pub struct ImapPool {
+    sessions: Mutex<HashMap<String, Arc<Mutex<Option<PooledSession>>>>>,
+}
+
+impl ImapPool {
+    /// Restituisce la sessione dell'account, creandola o ricreandola se necessario.
+    /// La connessione resta bloccata per l'account finché il `PooledConnection` non viene rilasciato.
+    pub async fn acquire(&self, accounts: &AccountManager, account_id: &str) -> Result<PooledConnection, SessionError> {
+        let account = accounts.account(account_id)?.config;
+        self.acquire_with(account_id, &account, || accounts.open_session(account_id))
+            .await
+    }
+
+    /// Come `acquire`, con `open` che apre una nuova sessione autenticata quando quella del pool
+    /// manca o non è più valida. Permette di collegare il pool a un server di test.
+    pub(crate) async fn acquire_with<F, Fut>(
+        &self,
+        account_id: &str,
+        account: &AccountConfig,
+        open: F,
+    ) -> Result<PooledConnection, SessionError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(AccountConfig, Session<CombinedStream>), SessionError>>,
+    {
+        let slot = {
+            let mut sessions = self.sessions.lock().await;
+            sessions
+                .entry(account_id.to_string())
+                .or_insert_with(|| Arc::new(Mutex::new(None)))
+                .clone()
+        };
+
+        let mut guard = slot.lock_owned().await;
+
+        // Server o metodo di autenticazione modificati: la sessione esistente non è più valida
+        if guard.as_ref().is_some_and(|pooled| &pooled.account != account) {
+            println!("[IMAP Pool] Configurazione modificata per account: {}, riconnessione", account_id);
+            *guard = None;
+        }
+
+        // Health check con NOOP se la sessione è rimasta inattiva
+        if let Some(pooled) = guard.as_mut() {
+            if pooled.last_used.elapsed() >= HEALTH_CHECK_AFTER {
+                if let Err(e) = pooled.session.noop().await {
+                    println!("[IMAP Pool] NOOP fallito per {}: {}, riconnessione", account_id, e);
+                    *guard = None;
+                }
+            }
+        }
+
+        if guard.is_none() {
+            println!("[IMAP Pool] Nuova sessione per account: {}", account_id);
+            let (account, mut session) = open().await?;
+            let capabilities = enable_extensions(&mut session).await?;
+            *guard = Some(PooledSession {
+                session,
+                capabilities,
+                selected: None,
+                account,
+                last_used: Instant::now(),
+            });
+        }
+
+        Ok(PooledConnection { guard })
+    }
+
+    /// Chiude con LOGOUT e rimuove la sessione dell'account, dopo l'eventuale operazione in corso
+    pub async fn remove(&self, account_id: &str) {
+        let slot = self.sessions.lock().await.remove(account_id);
+        let Some(slot) = slot else {
+            return;
+        };
+        let pooled = slot.lock().await.take();
+        if let Some(mut pooled) = pooled {
+            println!("[IMAP Pool] Chiusura sessione per account: {}", account_id);
+            let _ = pooled.session.logout().await;
+        }
+    }
+}
+
+/// Accesso esclusivo alla sessione di un account ottenuto da `ImapPool::acquire`
+pub struct PooledConnection {
+    guard: OwnedMutexGuard<Option<PooledSession>>,
+}
+
+/// Messaggio restituito quando la sessione è stata invalidata da un errore di connessione
+const CONNECTION_LOST: &str = "Connessione IMAP persa, riprovare";
+
+impl PooledConnection {
+    /// Dopo un errore di connessione la sessione viene rimossa dal pool:
+    /// ogni uso successivo fallisce con `ConnectionLost` invece di andare in panic
+    fn pooled(&mut self) -> Result<&mut PooledSession, async_imap::error::Error> {
+        self.guard.as_mut().ok_or(async_imap::error::Error::ConnectionLost)
+    }
+
+    /// Sessione IMAP sottostante, o errore se la connessione è stata persa durante questo utilizzo
+    pub(crate) fn session(&mut self) -> Result<&mut Session<CombinedStream>, String> {
+        self.guard
+            .as_mut()
+            .map(|pooled| &mut pooled.session)
+            .ok_or_else(|| CONNECTION_LOST.to_string())
+    }
+
+    /// `false` se un errore di connessione ha invalidato la sessione: i comandi successivi fallirebbero
+    pub fn is_alive(&self) -> bool {
+        self.guard.is_some()
+    }
+
+    /// Verifica se il server ha annunciato una capability (es. "QRESYNC", "MOVE")
+    /// Restituisce `false` se la sessione è stata invalidata.
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.guard
+            .as_ref()
+            .is_some_and(|pooled| pooled.capabilities.contains(&capability.to_ascii_uppercase()))
+    }
+
+    /// Nome della cartella nel formato atteso dal server: UTF-8 se UTF8=ACCEPT è abilitato,
+    /// altrimenti UTF-7 modificato
+    pub fn encode_mailbox(&self, folder_path: &str) -> String {
+        if self.has_capability("UTF8=ACCEPT") {
+            folder_path.to_string()
+        } else {
+            encode_mailbox_name(folder_path)
+        }
+    }
+
+    /// Nome della cartella restituito dal server convertito in testo leggibile
+    pub fn decode_mailbox(&self, folder_path: &str) -> String {
+        if self.has_capability("UTF8=ACCEPT") {
+            folder_path.to_string()
+        } else {
+            decode_mailbox_name(folder_path)
+        }
+    }
+
+    /// Seleziona sempre la cartella, per ottenere UIDVALIDITY, UIDNEXT e HIGHESTMODSEQ aggiornati
+    pub async fn select_mailbox(&mut self, folder_path: &str) -> Result<Mailbox, async_imap::error::Error> {
+        let encoded = self.encode_mailbox(folder_path);
+        let pooled = self.pooled()?;
+        pooled.selected = None;
+        let result = pooled.session.select(&encoded).await;
+        match result {
+            Ok(mailbox) => {
+                self.pooled()?.selected = Some(folder_path.to_string());
+                Ok(mailbox)
+            }
+            Err(e) => Err(self.check(e)),
+        }
+    }
+
+    /// Seleziona la cartella solo se non è già quella selezionata
+    pub async fn select(&mut self, folder_path: &str) -> Result<(), async_imap::error::Error> {
+        let pooled = self.pooled()?;
+        if pooled.selected.as_deref() == Some(folder_path) {
+            return Ok(());
+        }
+
+        pooled.selected = None;
+        let encoded = self.encode_mailbox(folder_path);
+        let result = self.pooled()?.session.select(&encoded).await;
+        match result {
+            Ok(_) => {
+                self.pooled()?.selected = Some(folder_path.to_string());
+                Ok(())
+            }
+            Err(e) => Err(self.check(e)),
+        }
+    }
+
+    /// Esce dalla cartella se è quella selezionata, prima di rinominarla o eliminarla.
+    /// Usa UNSELECT quando disponibile perché CLOSE eliminerebbe i messaggi \Deleted.
+    pub async fn release_mailbox(&mut self, folder_path: &str) -> Result<(), async_imap::error::Error> {
+        if self.pooled()?.selected.as_deref() != Some(folder_path) {
+            return Ok(());
+        }
+
+        let unselect = self.has_capability("UNSELECT");
+        let pooled = self.pooled()?;
+        pooled.selected = None;
+        let result = if unselect {
+            pooled.session.run_command_and_check_ok("UNSELECT").await
+        } else {
+            pooled.session.select("INBOX").await.map(|_| ())
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => Err(self.check(e)),
+        }
+    }
+
+    /// Invalida la sessione se l'errore indica che la connessione è caduta,
+    /// così la prossima `acquire` si riconnette in modo trasparente.
+    /// Dopo l'invalidazione `session()` restituisce un errore: il chiamante deve interrompere l'operazione.
+    pub fn check(&mut self, error: async_imap::error::Error) -> async_imap::error::Error {
+        if is_connection_error(&error) {
+            println!("[IMAP Pool] Connessione persa: {}, la sessione verrà ricreata", error);
+            *self.guard = None;
+        }
+        error
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(pooled) = self.guard.as_mut() {
+            pooled.last_used = Instant::now();
+        }
+    }
+}
+
+/// Legge le capability del server e abilita CONDSTORE/QRESYNC e UTF8=ACCEPT se disponibili.
+/// Le estensioni che non è stato possibile abilitare vengono rimosse dall'insieme restituito.
+/// ENABLE è valido solo prima di SELECT, quindi va eseguito subito dopo il login.
+async fn enable_extensions(session: &mut Session<CombinedStream>) -> Result<HashSet<String>, String> {
+    let mut capabilities: HashSet<String> = session
+        .capabilities()
+        .await
+        .map_err(|e| format!("Errore nel CAPABILITY: {}", e))?
+        .iter()
+        .map(|capability| match capability {
+            Capability::Imap4rev1 => "IMAP4REV1".to_string(),
+            Capability::Auth(mechanism) => format!("AUTH={}", mechanism.to_ascii_uppercase()),
+            Capability::Atom(atom) => atom.to_ascii_uppercase(),
+        })
+        .collect();
+
+    if capabilities.contains("ENABLE") {
+        let mut extensions = Vec::new();
+        if capabilities.contains("QRESYNC") {
+            extensions.push("QRESYNC");
+        } else if capabilities.contains("CONDSTORE") {
+            extensions.push("CONDSTORE");
+        }
+        // RFC 6855: con UTF8=ACCEPT i nomi delle cartelle viaggiano in UTF-8 invece che in UTF-7 modificato
+        if capabilities.contains("UTF8=ACCEPT") {
+            extensions.push("UTF8=ACCEPT");
+        }
+
+        for extension in extensions {
+            match session.run_command_and_check_ok(format!("ENABLE {}", extension)).await {
+                Ok(()) => println!("[IMAP Pool] Estensione {} abilitata", extension),
+                Err(e) => {
+                    // Senza ENABLE i comandi che dipendono dall'estensione verrebbero rifiutati
+                    println!("[IMAP Pool] ENABLE {} fallito: {}", extension, e);
+                    if extension == "UTF8=ACCEPT" {
+                        capabilities.remove("UTF8=ACCEPT");
+                    } else {
+                        capabilities.remove("QRESYNC");
+                        capabilities.remove("CONDSTORE");
+                    }
+                }
+            }
+        }
+    } else {
+        capabilities.remove("UTF8=ACCEPT");
+    }
+
+    Ok(capabilities)
+}
+
+fn is_connection_error(error: &async_imap::error::Error) -> bool {
+    matches!(
+        error,
+        async_imap::error::Error::Io(_) | async_imap::error::Error::ConnectionLost
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::imap::create_imap_session;
+    use crate::commands::test_support::FakeImapServer;
+
+    fn logins(server: &FakeImapServer) -> usize {
+        server.commands().iter().filter(|c| c.starts_with("LOGIN")).count()
+    }
+
+    #[tokio::test]
+    async fn drops_extensions_the_server_refuses_to_enable() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 ENABLE QRESYNC CONDSTORE UTF8=ACCEPT".to_string()]),
+            ("ENABLE QRESYNC", vec!["$tag NO non supportato".to_string()]),
+            ("ENABLE UTF8=ACCEPT", vec!["* ENABLED UTF8=ACCEPT".to_string()]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+
+        let conn = server.connect(&pool).await;
+
+        assert!(!conn.has_capability("QRESYNC"));
+        assert!(!conn.has_capability("CONDSTORE"));
+        assert!(conn.has_capability("utf8=accept"));
+        assert_eq!(conn.encode_mailbox("Attività"), "Attività");
+    }
+
+    #[tokio::test]
+    async fn reuses_the_session_until_the_connection_is_lost() {
+        let server = FakeImapServer::start(vec![("CAPABILITY", vec!["* CAPABILITY IMAP4rev1".to_string()])]).await;
+        let pool = ImapPool::default();
+
+        let mut conn = server.connect(&pool).await;
+        drop(conn);
+        conn = server.connect(&pool).await;
+        assert_eq!(logins(&server), 1);
+
+        conn.check(async_imap::error::Error::ConnectionLost);
+        assert!(!conn.is_alive());
+        assert!(conn.session().is_err());
+        drop(conn);
+
+        let conn = server.connect(&pool).await;
+        assert!(conn.is_alive());
+        assert_eq!(logins(&server), 2);
+    }
+
+    #[tokio::test]
+    async fn removed_account_logs_out_after_the_operation_in_progress() {
+        let server = FakeImapServer::start(vec![("CAPABILITY", vec!["* CAPABILITY IMAP4rev1".to_string()])]).await;
+        let pool = ImapPool::default();
+
+        let conn = server.connect(&pool).await;
+        let remove = pool.remove("test");
+        tokio::pin!(remove);
+        // La sessione è in uso: la rimozione attende il rilascio
+        assert!(tokio::time::timeout(Duration::from_millis(100), remove.as_mut()).await.is_err());
+        drop(conn);
+        remove.await;
+        assert_eq!(server.commands().last().map(String::as_str), Some("LOGOUT"));
+
+        // Rimuovere un account senza sessione non fa nulla
+        pool.remove("test").await;
+        server.connect(&pool).await;
+        assert_eq!(logins(&server), 2);
+    }
+
+    #[tokio::test]
+    async fn reconnects_when_the_account_configuration_changes() {
+        let server = FakeImapServer::start(vec![("CAPABILITY", vec!["* CAPABILITY IMAP4rev1".to_string()])]).await;
+        let pool = ImapPool::default();
+        drop(server.connect(&pool).await);
+
+        let mut account = server.account();
+        account.username = Some("altro@example.com".to_string());
+        let open = || {
+            let account = account.clone();
+            async move {
+                let session = create_imap_session(&account, "segreto").await?;
+                Ok::<_, SessionError>((account, session))
+            }
+        };
+        let conn = pool.acquire_with("test", &account, open).await.unwrap();
+
+        assert!(conn.is_alive());
+        assert_eq!(logins(&server), 2);
+        assert!(server.commands().iter().any(|c| c.starts_with("LOGIN \"altro@example.com\"")), "{:?}", server.commands());
+    }
+}
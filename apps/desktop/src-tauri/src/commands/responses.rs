@@ -0,0 +1,230 @@
+use async_imap::error::{Error, Result};
+use async_imap::Session;
+use imap_proto::types::{Response, Status};
+
+use super::imap::CombinedStream;
+
+/// Risposte non taggate che imap-proto non sa interpretare, riscritte in una forma equivalente.
+/// Una risposta non interpretabile blocca la sessione: async-imap non consuma il buffer
+/// e ogni lettura successiva fallisce con lo stesso errore.
+const REWRITES: &[(&[u8], &[u8])] = &[
+    // XLIST (estensione storica di Gmail) ha la stessa sintassi di LIST
+    (b"* XLIST ", b"* LIST "),
+    // THREAD (RFC 5256) diventa una risposta OK con i dati nel testo
+    (b"* THREAD", b"* OK THREAD"),
+];
+
+/// Attributi XLIST che imap-proto interpreta male: riconosce `\All` in `\AllMail`
+/// e fallisce sul resto del nome
+const XLIST_ATTRIBUTES: &[(&[u8], &[u8])] = &[(b"\\AllMail", b"\\All")];
+
+/// Riscrive le risposte elencate in `REWRITES` nei dati ricevuti dal server, prima che
+/// arrivino ad async-imap. Confronta solo l'inizio delle righe e inoltra senza modifiche
+/// il contenuto dei literal (`{n}`), che può contenere qualsiasi testo.
+#[derive(Default)]
+pub(crate) struct ResponseRewriter {
+    /// Byte del literal in corso ancora da inoltrare
+    literal: usize,
+    /// Inizio della riga corrente, trattenuto finché può ancora corrispondere a una riscrittura
+    head: Vec<u8>,
+    /// L'inizio della riga corrente è già stato confrontato
+    mid_line: bool,
+    /// Ultimi byte della riga corrente, per riconoscere l'annuncio di un literal
+    tail: Vec<u8>,
+    /// Lista degli attributi di una risposta XLIST, trattenuta fino alla parentesi di chiusura
+    attributes: Option<Vec<u8>>,
+}
+
+impl ResponseRewriter {
+    /// Elabora i byte ricevuti aggiungendo a `output` quelli pronti per async-imap
+    pub fn feed(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        for &byte in input {
+            if self.literal > 0 {
+                self.literal -= 1;
+                output.push(byte);
+            } else if let Some(attributes) = &mut self.attributes {
+                attributes.push(byte);
+                if byte == b')' || byte == b'\n' {
+                    for byte in rewrite_attributes(attributes) {
+                        self.push(byte, output);
+                    }
+                    self.attributes = None;
+                }
+            } else if self.mid_line {
+                self.push(byte, output);
+            } else {
+                self.head.push(byte);
+                if let Some((pattern, replacement)) = REWRITES.iter().find(|(pattern, _)| *pattern == self.head.as_slice()) {
+                    output.extend_from_slice(replacement);
+                    if *pattern == b"* XLIST " {
+                        self.attributes = Some(Vec::new());
+                    }
+                    self.head.clear();
+                    self.mid_line = true;
+                } else if !REWRITES.iter().any(|(pattern, _)| pattern.starts_with(&self.head)) {
+                    // Solo l'ultimo byte trattenuto può essere un fine riga
+                    self.mid_line = true;
+                    for byte in std::mem::take(&mut self.head) {
+                        self.push(byte, output);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Restituisce i byte ancora trattenuti quando il server chiude la connessione
+    pub fn finish(&mut self, output: &mut Vec<u8>) {
+        output.append(&mut self.head);
+        output.extend(self.attributes.take().unwrap_or_default());
+    }
+
+    fn push(&mut self, byte: u8, output: &mut Vec<u8>) {
+        output.push(byte);
+        if byte == b'\n' {
+            // Dopo un literal la riga prosegue: il suo inizio è già stato confrontato
+            self.literal = literal_length(&self.tail);
+            self.mid_line = self.literal > 0;
+            self.tail.clear();
+        } else {
+            self.tail.push(byte);
+            if self.tail.len() > 64 {
+                self.tail.drain(..32);
+            }
+        }
+    }
+}
+
+/// Sostituisce gli attributi elencati in `XLIST_ATTRIBUTES` in una lista come `(\\HasNoChildren \\AllMail)`
+fn rewrite_attributes(list: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(list.len());
+    for (i, token) in list.split(|b| *b == b' ').enumerate() {
+        if i > 0 {
+            output.push(b' ');
+        }
+        let end = token.iter().position(|b| matches!(b, b')' | b'\r' | b'\n')).unwrap_or(token.len());
+        let (name, rest) = token.split_at(end);
+        match XLIST_ATTRIBUTES.iter().find(|(pattern, _)| pattern.eq_ignore_ascii_case(name)) {
+            Some((_, replacement)) => output.extend_from_slice(replacement),
+            None => output.extend_from_slice(name),
+        }
+        output.extend_from_slice(rest);
+    }
+    output
+}
+
+/// Lunghezza del literal annunciato a fine riga (`{123}`), 0 se non c'è
+fn literal_length(line: &[u8]) -> usize {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let Some(line) = line.strip_suffix(b"}") else {
+        return 0;
+    };
+    let Some(start) = line.iter().rposition(|b| *b == b'{') else {
+        return 0;
+    };
+    std::str::from_utf8(&line[start + 1..])
+        .ok()
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Invia un comando che async-imap non implementa (LIST-STATUS, UID MOVE, X-GM-THRID...)
+/// e passa a `handle` ogni risposta ricevuta, compresa quella taggata che chiude il comando.
+/// Le risposte vengono lette dallo stream della sessione e non dal canale delle risposte
+/// non richieste: quello ha una capacità limitata e scarta le risposte in eccesso.
+pub(crate) async fn run_command_with<F>(session: &mut Session<CombinedStream>, command: &str, mut handle: F) -> Result<()>
+where
+    F: FnMut(&Response<'_>),
+{
+    let id = session.run_command(command).await?;
+
+    while let Some(response) = session.read_response().await {
+        let response = response?;
+        let parsed = response.parsed();
+        handle(parsed);
+        if let Response::Done { tag, status, code, information } = parsed {
+            if *tag == id {
+                return match status {
+                    Status::Ok => Ok(()),
+                    Status::No => Err(Error::No(format!("code: {:?}, info: {:?}", code, information))),
+                    _ => Err(Error::Bad(format!("code: {:?}, info: {:?}", code, information))),
+                };
+            }
+        }
+    }
+    Err(Error::ConnectionLost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut rewriter = ResponseRewriter::default();
+        let mut output = Vec::new();
+        for chunk in chunks {
+            rewriter.feed(chunk, &mut output);
+        }
+        rewriter.finish(&mut output);
+        output
+    }
+
+    #[test]
+    fn rewrites_xlist_at_the_start_of_a_line() {
+        let input = b"* XLIST (\\HasNoChildren \\Inbox) \"/\" \"Inbox\"\r\n* LIST () \"/\" \"XLIST \"\r\nA1 OK fatto\r\n";
+        let expected = b"* LIST (\\HasNoChildren \\Inbox) \"/\" \"Inbox\"\r\n* LIST () \"/\" \"XLIST \"\r\nA1 OK fatto\r\n";
+        assert_eq!(rewrite(&[input]), expected.to_vec());
+
+        // Il risultato non dipende da come il server spezza i dati
+        let bytes: Vec<&[u8]> = input.chunks(1).collect();
+        assert_eq!(rewrite(&bytes), expected.to_vec());
+        let pieces: Vec<&[u8]> = input.chunks(5).collect();
+        assert_eq!(rewrite(&pieces), expected.to_vec());
+    }
+
+    #[test]
+    fn rewrites_gmail_attributes_in_xlist() {
+        let input = b"* XLIST (\\HasNoChildren \\AllMail) \"/\" \"\\AllMail\"\r\n* LIST (\\AllMail) \"/\" \"X\"\r\n";
+        let expected = b"* LIST (\\HasNoChildren \\All) \"/\" \"\\AllMail\"\r\n* LIST (\\AllMail) \"/\" \"X\"\r\n";
+        assert_eq!(rewrite(&[input]), expected.to_vec());
+        let bytes: Vec<&[u8]> = input.chunks(1).collect();
+        assert_eq!(rewrite(&bytes), expected.to_vec());
+        assert_eq!(rewrite(&[b"* XLIST (\\AllMail"]), b"* LIST (\\AllMail".to_vec());
+    }
+
+    #[test]
+    fn forwards_literals_unchanged() {
+        let body = b"Ciao\r\n* XLIST finto nel corpo\r\n";
+        let mut input = format!("* 1 FETCH (UID 4 BODY[] {{{}}}\r\n", body.len()).into_bytes();
+        input.extend_from_slice(body);
+        input.extend_from_slice(b")\r\n* XLIST () \"/\" \"Dopo\"\r\n");
+
+        let mut expected = format!("* 1 FETCH (UID 4 BODY[] {{{}}}\r\n", body.len()).into_bytes();
+        expected.extend_from_slice(body);
+        expected.extend_from_slice(b")\r\n* LIST () \"/\" \"Dopo\"\r\n");
+
+        assert_eq!(rewrite(&[&input]), expected);
+        let bytes: Vec<&[u8]> = input.chunks(1).collect();
+        assert_eq!(rewrite(&bytes), expected);
+    }
+
+    #[test]
+    fn rewrites_thread_as_an_untagged_ok() {
+        let output = rewrite(&[b"* THREAD (2)(3 6 (4 23))\r\n* THREAD\r\nA4 OK fatto\r\n"]);
+        assert_eq!(output, b"* OK THREAD (2)(3 6 (4 23))\r\n* OK THREAD\r\nA4 OK fatto\r\n".to_vec());
+    }
+
+    #[test]
+    fn returns_held_bytes_when_the_stream_ends() {
+        assert_eq!(rewrite(&[b"* XL"]), b"* XL".to_vec());
+        assert_eq!(rewrite(&[b"+ \r\n"]), b"+ \r\n".to_vec());
+    }
+
+    #[test]
+    fn reads_literal_announcements() {
+        assert_eq!(literal_length(b"* 1 FETCH (BODY[] {120}\r"), 120);
+        assert_eq!(literal_length(b"* LIST () \"/\" {5}"), 5);
+        assert_eq!(literal_length(b"* OK testo {x}\r"), 0);
+        assert_eq!(literal_length(b"* LIST () \"/\" \"a{3}\"\r"), 0);
+    }
+}
@@ -1,11 +1,24 @@
 use serde::{Deserialize, Serialize};
+use tauri::State;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
 use lettre::{
-    message::{header::ContentType, Mailbox, Message, MultiPart, SinglePart},
-    transport::smtp::authentication::Credentials,
-    SmtpTransport, Transport,
+    message::{header::ContentType, Attachment as MimeAttachment, Mailbox, Message, MultiPart, SinglePart},
+    transport::smtp::authentication::{Credentials, Mechanism},
+    transport::smtp::client::{AsyncSmtpConnection, TlsParameters},
+    transport::smtp::extension::ClientId,
+    transport::smtp::{AsyncSmtpTransportBuilder, Error as SmtpError},
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+use super::account::{AccountConfig, AccountManager, AuthMethod, Security, ServerConfig};
+use super::auth::sasl_initial_response;
+use super::compose::{load_original, mark_original, prepare_message, OriginalMessage};
+use super::pool::ImapPool;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attachment {
     pub filename: String,
     pub content_type: String,
@@ -21,33 +34,75 @@ pub struct ComposeMessage {
     pub body_html: Option<String>,
     pub body_text: Option<String>,
     pub attachments: Option<Vec<Attachment>>,
+    /// Message-ID a cui si risponde (header In-Reply-To)
+    pub in_reply_to: Option<String>,
+    /// Catena di Message-ID della conversazione (header References)
+    pub references: Option<Vec<String>>,
+    /// Messaggio a cui si risponde o che si inoltra: oggetto, destinatari, citazione
+    /// e intestazioni vengono completati lato server
+    pub original: Option<OriginalMessage>,
 }
 
-/// Invia un'email tramite SMTP
+/// Invia un'email tramite SMTP.
+/// Per risposte e inoltri legge il messaggio originale via IMAP e, dopo l'invio,
+/// lo marca con \Answered o $Forwarded.
 #[tauri::command]
 pub async fn send_email(
+    pool: State<'_, ImapPool>,
+    accounts: State<'_, AccountManager>,
     account_id: String,
-    email: String,
-    provider: String,
-    access_token: String,
-    message: ComposeMessage,
+    mut message: ComposeMessage,
 ) -> Result<(), String> {
+    let (account, secret) = accounts.credentials(&account_id).await?;
     println!(
         "[SMTP] Send email da account: {} ({})",
-        account_id, email
+        account_id, account.email
     );
     println!("[SMTP] To: {:?}", message.to);
     println!("[SMTP] Subject: {}", message.subject);
     
-    // Configurazione SMTP in base al provider
-    let (host, port, use_tls) = match provider.as_str() {
-        "gmail" => ("smtp.gmail.com", 465, true),
-        "outlook" => ("smtp.office365.com", 587, false),
-        _ => return Err(format!("Provider non supportato: {}", provider)),
-    };
+    if let Some(original) = &message.original {
+        let kind = original.kind;
+        let loaded = load_original(&pool, &accounts, &account_id, original).await?;
+        prepare_message(&mut message, &loaded, &account.email, kind);
+    }
+    
+    let email_message = build_message(&account.email, &message)?;
     
-    // Costruisci il messaggio
-    let from_mailbox: Mailbox = email
+    // Invia l'email: con OAuth2 un access token rifiutato viene rinnovato e l'invio ripetuto una volta
+    account.smtp.check_security()?;
+    let mut result = deliver(&account, &secret, &email_message).await;
+    if let Err(e) = &result {
+        if account.auth.is_oauth() && is_auth_rejected(e) {
+            println!("[SMTP] Token rifiutato dal server: {}, riprovo dopo il refresh", e);
+            let (account, secret) = accounts.refresh_rejected(&account_id, &secret).await?;
+            result = deliver(&account, &secret, &email_message).await;
+        }
+    }
+
+    match result {
+        Ok(_) => {
+            println!("[SMTP] Email inviata con successo!");
+            // Il flag sull'originale è accessorio: un errore non annulla l'invio riuscito
+            if let Some(original) = &message.original {
+                if let Err(e) = mark_original(&pool, &accounts, &account_id, original).await {
+                    println!("[SMTP] Errore nell'aggiornamento dei flag dell'originale: {}", e);
+                }
+            }
+            Ok(())
+        }
+        Err(e) => {
+            let error_msg = format!("Errore nell'invio dell'email: {:?}", e);
+            println!("[SMTP] {}", error_msg);
+            Err(error_msg)
+        }
+    }
+}
+
+/// Costruisce il messaggio da inviare: multipart/alternative se c'è un corpo HTML,
+/// dentro un multipart/mixed con un SinglePart per ogni allegato
+fn build_message(from: &str, message: &ComposeMessage) -> Result<Message, String> {
+    let from_mailbox: Mailbox = from
         .parse()
         .map_err(|e| format!("Email mittente non valida: {}", e))?;
     
@@ -78,6 +133,14 @@ pub async fn send_email(
         }
     }
     
+    // Intestazioni di threading per le risposte
+    if let Some(in_reply_to) = &message.in_reply_to {
+        builder = builder.in_reply_to(in_reply_to.clone());
+    }
+    if let Some(references) = message.references.as_ref().filter(|r| !r.is_empty()) {
+        builder = builder.references(references.join(" "));
+    }
+    
     // Aggiungi BCC se presente
     if let Some(bcc) = &message.bcc {
         for bcc_addr in bcc {
@@ -88,61 +151,410 @@ pub async fn send_email(
     }
     
     // Costruisci il corpo del messaggio
-    let email_message = if let Some(html) = &message.body_html {
-        // Messaggio HTML con fallback text
-        let text_part = message.body_text.as_deref().unwrap_or("");
-        builder
-            .multipart(
-                MultiPart::alternative()
-                    .singlepart(
-                        SinglePart::builder()
-                            .header(ContentType::TEXT_PLAIN)
-                            .body(text_part.to_string()),
-                    )
-                    .singlepart(
-                        SinglePart::builder()
-                            .header(ContentType::TEXT_HTML)
-                            .body(html.clone()),
-                    ),
-            )
-            .map_err(|e| format!("Errore nella costruzione del messaggio: {}", e))?
+    let text = message.body_text.as_deref().unwrap_or("").to_string();
+    let attachments = message.attachments.as_deref().unwrap_or_default();
+    if attachments.is_empty() {
+        if let Some(html) = &message.body_html {
+            // Messaggio HTML con fallback text
+            builder.multipart(alternative_body(text, html.clone()))
+        } else {
+            // Solo testo
+            builder.body(text)
+        }
     } else {
-        // Solo testo
-        builder
-            .body(
-                message
-                    .body_text
-                    .as_deref()
-                    .unwrap_or("")
-                    .to_string(),
-            )
-            .map_err(|e| format!("Errore nella costruzione del messaggio: {}", e))?
-    };
+        // multipart/mixed: prima il corpo, poi un SinglePart per ogni allegato
+        let mut mixed = match &message.body_html {
+            Some(html) => MultiPart::mixed().multipart(alternative_body(text, html.clone())),
+            None => MultiPart::mixed().singlepart(SinglePart::plain(text)),
+        };
+        for attachment in attachments {
+            println!("[SMTP] Allegato: {} ({} byte)", attachment.filename, attachment.content.len());
+            mixed = mixed.singlepart(attachment_part(attachment));
+        }
+        builder.multipart(mixed)
+    }
+    .map_err(|e| format!("Errore nella costruzione del messaggio: {}", e))
+}
+
+/// Invia il messaggio autenticandosi con il metodo dell'account.
+/// La cifratura configurata va verificata prima con `check_security`.
+async fn deliver(account: &AccountConfig, secret: &str, message: &Message) -> Result<(), SmtpError> {
+    match account.auth {
+        AuthMethod::OauthBearer => send_oauthbearer(account, secret, message).await,
+        _ => mailer(account, secret)?.send(message.clone()).await.map(|_| ()),
+    }
+}
+
+/// Trasporto autenticato verso il server SMTP dell'account
+fn mailer(account: &AccountConfig, secret: &str) -> Result<AsyncSmtpTransport<Tokio1Executor>, SmtpError> {
+    // Trasporto asincrono: l'invio non blocca i worker del runtime
+    let mailer_builder = relay_builder(&account.smtp)?;
     
-    // Crea il trasporto SMTP
-    let mailer_builder = if use_tls {
-        SmtpTransport::relay(host)
-            .map_err(|e| format!("Errore nella creazione del trasporto SMTP: {}", e))?
-    } else {
-        SmtpTransport::builder_dangerous(host).port(port)
+    // Con una password lettre sceglie il primo meccanismo della lista annunciato da EHLO
+    let mechanisms = match account.auth {
+        AuthMethod::Xoauth2 => vec![Mechanism::Xoauth2],
+        // OAUTHBEARER non è tra i meccanismi di lettre: lo gestisce send_oauthbearer
+        AuthMethod::OauthBearer => Vec::new(),
+        AuthMethod::Plain => vec![Mechanism::Plain, Mechanism::Login],
+        AuthMethod::Login => vec![Mechanism::Login, Mechanism::Plain],
     };
-    
-    // Autenticazione OAuth2 (per ora usa access_token come password)
-    // Nota: Per OAuth2 completo, serve implementare XOAUTH2
-    let creds = Credentials::new(email.clone(), access_token);
-    let mailer = mailer_builder.credentials(creds).build();
-    
-    // Invia l'email
-    match mailer.send(&email_message) {
-        Ok(_) => {
-            println!("[SMTP] Email inviata con successo!");
-            Ok(())
-        }
-        Err(e) => {
-            let error_msg = format!("Errore nell'invio dell'email: {:?}", e);
-            println!("[SMTP] {}", error_msg);
-            Err(error_msg)
-        }
+    let creds = Credentials::new(account.username().to_string(), secret.to_string());
+    Ok(mailer_builder
+        .credentials(creds)
+        .authentication(mechanisms)
+        .build())
+}
+
+/// Timeout dei comandi SMTP, come quello predefinito del trasporto di lettre
+const SMTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Invio con SASL OAUTHBEARER (RFC 7628), che lettre non implementa: la connessione
+/// è quella del client SMTP di lettre, il comando AUTH viene composto qui
+async fn send_oauthbearer(account: &AccountConfig, token: &str, message: &Message) -> Result<(), SmtpError> {
+    let server = &account.smtp;
+    let hello = ClientId::default();
+    let tls = match server.security {
+        Security::Tls => Some(TlsParameters::new(server.host.clone())?),
+        Security::StartTls | Security::Plain => None,
+    };
+    let mut connection =
+        AsyncSmtpConnection::connect_tokio1((server.host.as_str(), server.port), Some(SMTP_TIMEOUT), &hello, tls, None)
+            .await?;
+    if server.security == Security::StartTls {
+        // STARTTLS obbligatorio: la connessione fallisce se il server non lo offre
+        connection.starttls(TlsParameters::new(server.host.clone())?, &hello).await?;
+    }
+
+    let (mechanism, initial) = sasl_initial_response(AuthMethod::OauthBearer, account.username(), token, server);
+    let response = connection
+        .command(format!("AUTH {} {}\r\n", mechanism, BASE64_STANDARD.encode(initial)))
+        .await?;
+    if response.has_code(334) {
+        // RFC 7628 3.2.3: il token è stato rifiutato e la richiesta contiene i dettagli in JSON.
+        // Il client risponde con un solo ^A e il server chiude lo scambio con l'errore (535)
+        println!("[SMTP] OAUTHBEARER rifiutato: {}", response.message().collect::<Vec<_>>().join(" "));
+        connection.command("AQ==\r\n").await?;
+    }
+
+    connection.send(message.envelope(), &message.formatted()).await?;
+    let _ = connection.quit().await;
+    Ok(())
+}
+
+/// Errore restituito da lettre quando il server risponde a XOAUTH2 con una richiesta 334
+/// contenente i dettagli dell'errore invece di accettare il token
+const XOAUTH2_ERROR_CHALLENGE: &str = "This mechanism does not expect a challenge";
+
+/// Credenziali rifiutate: 535 (RFC 4954), oppure il rifiuto del token XOAUTH2 segnalato
+/// con una richiesta 334. Gli altri errori del client (es. nessun meccanismo compatibile)
+/// non sono un rifiuto e non devono causare un refresh del token.
+fn is_auth_rejected(error: &SmtpError) -> bool {
+    error.status().is_some_and(|code| code.to_string() == "535")
+        || (error.is_client() && error.to_string().contains(XOAUTH2_ERROR_CHALLENGE))
+}
+
+/// Verifica che il server SMTP risponda con la cifratura configurata, senza autenticarsi
+pub(crate) async fn probe_smtp(server: &ServerConfig) -> Result<(), String> {
+    let mailer: AsyncSmtpTransport<Tokio1Executor> = transport_builder(server)?.build();
+    match mailer.test_connection().await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("Il server SMTP {} non risponde", server.host)),
+        Err(e) => Err(format!("Errore nella connessione SMTP a {}:{}: {}", server.host, server.port, e)),
     }
 }
 
+/// Trasporto verso il server SMTP con la cifratura configurata
+fn transport_builder(server: &ServerConfig) -> Result<AsyncSmtpTransportBuilder, String> {
+    server.check_security()?;
+    relay_builder(server).map_err(|e| format!("Errore nella creazione del trasporto SMTP: {}", e))
+}
+
+/// Come `transport_builder`, senza verificare che la cifratura sia consentita
+fn relay_builder(server: &ServerConfig) -> Result<AsyncSmtpTransportBuilder, SmtpError> {
+    let builder = match server.security {
+        Security::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&server.host)?,
+        // STARTTLS obbligatorio: la connessione fallisce se il server non lo offre
+        Security::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&server.host)?,
+        Security::Plain => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&server.host),
+    };
+    Ok(builder.port(server.port))
+}
+
+/// Corpo multipart/alternative con la versione testo e quella HTML
+fn alternative_body(text: String, html: String) -> MultiPart {
+    MultiPart::alternative()
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_PLAIN)
+                .body(text),
+        )
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_HTML)
+                .body(html),
+        )
+}
+
+/// Parte di un allegato: lettre sceglie la codifica (base64 per i dati binari)
+/// e scrive il filename in Content-Disposition secondo RFC 2231 se non è ASCII
+fn attachment_part(attachment: &Attachment) -> SinglePart {
+    let content_type = ContentType::parse(&attachment.content_type).unwrap_or_else(|_| {
+        println!("[SMTP] Content-Type non valido per {}: {}", attachment.filename, attachment.content_type);
+        ContentType::parse("application/octet-stream").expect("content type statico valido")
+    });
+    MimeAttachment::new(attachment.filename.clone()).body(attachment.content.clone(), content_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::FakeSmtpServer;
+    use mailparse::MailHeaderMap;
+
+    fn compose(body_html: Option<&str>, attachments: Vec<Attachment>) -> ComposeMessage {
+        ComposeMessage {
+            to: vec!["destinatario@example.com".to_string()],
+            cc: Some(vec!["copia@example.com".to_string()]),
+            bcc: None,
+            subject: "Preventivo".to_string(),
+            body_html: body_html.map(str::to_string),
+            body_text: Some("Corpo del messaggio".to_string()),
+            attachments: Some(attachments),
+            in_reply_to: None,
+            references: None,
+            original: None,
+        }
+    }
+
+    /// Messaggio costruito come in send_email, già serializzato
+    fn message_with(attachment: &Attachment) -> Vec<u8> {
+        build_message("mittente@example.com", &compose(None, vec![attachment.clone()]))
+            .expect("messaggio valido")
+            .formatted()
+    }
+
+    fn body_text(part: &mailparse::ParsedMail<'_>) -> String {
+        part.get_body().expect("corpo decodificato").trim_end().to_string()
+    }
+
+    #[test]
+    fn html_message_with_attachment_nests_alternative_in_mixed() {
+        let attachment = Attachment {
+            filename: "listino.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            content: b"%PDF-1.4\n\x00\xff\x80binario".to_vec(),
+        };
+        let mut message = compose(Some("<p>Corpo del <b>messaggio</b></p>"), vec![attachment.clone()]);
+        message.in_reply_to = Some("<originale@example.com>".to_string());
+        message.references = Some(vec!["<primo@example.com>".to_string(), "<originale@example.com>".to_string()]);
+
+        let raw = build_message("mittente@example.com", &message).unwrap().formatted();
+        let parsed = mailparse::parse_mail(&raw).expect("messaggio rileggibile");
+
+        assert_eq!(parsed.ctype.mimetype, "multipart/mixed");
+        let headers = &parsed.headers;
+        assert_eq!(headers.get_first_value("From").as_deref(), Some("mittente@example.com"));
+        assert_eq!(headers.get_first_value("To").as_deref(), Some("destinatario@example.com"));
+        assert_eq!(headers.get_first_value("Cc").as_deref(), Some("copia@example.com"));
+        assert_eq!(headers.get_first_value("Subject").as_deref(), Some("Preventivo"));
+        assert_eq!(headers.get_first_value("In-Reply-To").as_deref(), Some("<originale@example.com>"));
+        assert_eq!(
+            headers.get_first_value("References").as_deref(),
+            Some("<primo@example.com> <originale@example.com>")
+        );
+
+        assert_eq!(parsed.subparts.len(), 2);
+        let alternative = &parsed.subparts[0];
+        assert_eq!(alternative.ctype.mimetype, "multipart/alternative");
+        assert_eq!(alternative.subparts.len(), 2);
+        assert_eq!(alternative.subparts[0].ctype.mimetype, "text/plain");
+        assert_eq!(body_text(&alternative.subparts[0]), "Corpo del messaggio");
+        assert_eq!(alternative.subparts[1].ctype.mimetype, "text/html");
+        assert_eq!(body_text(&alternative.subparts[1]), "<p>Corpo del <b>messaggio</b></p>");
+
+        let part = &parsed.subparts[1];
+        assert_eq!(part.ctype.mimetype, "application/pdf");
+        assert_eq!(part.get_body_raw().unwrap(), attachment.content);
+    }
+
+    #[test]
+    fn message_without_attachments_is_not_mixed() {
+        let raw = build_message("mittente@example.com", &compose(Some("<p>Ciao</p>"), Vec::new())).unwrap().formatted();
+        let parsed = mailparse::parse_mail(&raw).unwrap();
+        assert_eq!(parsed.ctype.mimetype, "multipart/alternative");
+        assert_eq!(parsed.subparts.len(), 2);
+
+        let raw = build_message("mittente@example.com", &compose(None, Vec::new())).unwrap().formatted();
+        let parsed = mailparse::parse_mail(&raw).unwrap();
+        assert_eq!(parsed.ctype.mimetype, "text/plain");
+        assert!(parsed.subparts.is_empty());
+        assert_eq!(body_text(&parsed), "Corpo del messaggio");
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        let mut message = compose(None, Vec::new());
+        message.to = vec!["non un indirizzo".to_string()];
+        let error = build_message("mittente@example.com", &message).unwrap_err();
+        assert!(error.starts_with("Email destinatario non valida"), "{}", error);
+        assert!(build_message("mittente", &compose(None, Vec::new())).is_err());
+    }
+
+    #[test]
+    fn attachment_round_trips_binary_content_and_non_ascii_filename() {
+        // Tutti i valori dei byte, righe lunghe comprese, e un nome abbastanza lungo
+        // da essere spezzato in più continuazioni RFC 2231
+        let content: Vec<u8> = (0..=255u8).cycle().take(5000).collect();
+        let attachment = Attachment {
+            filename: "Fattura n°12 – città di Forlì, 日本語.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            content: content.clone(),
+        };
+
+        let raw = message_with(&attachment);
+        let parsed = mailparse::parse_mail(&raw).expect("messaggio rileggibile");
+        assert_eq!(parsed.ctype.mimetype, "multipart/mixed");
+        assert_eq!(parsed.subparts.len(), 2);
+        assert_eq!(parsed.subparts[0].ctype.mimetype, "text/plain");
+
+        let part = &parsed.subparts[1];
+        assert_eq!(part.ctype.mimetype, "application/pdf");
+        let disposition = part.get_content_disposition();
+        assert!(matches!(disposition.disposition, mailparse::DispositionType::Attachment));
+        assert_eq!(disposition.params.get("filename"), Some(&attachment.filename));
+        assert_eq!(part.get_body_raw().expect("corpo decodificato"), content);
+    }
+
+    #[test]
+    fn invalid_content_type_falls_back_to_octet_stream() {
+        let attachment = Attachment {
+            filename: "dati.bin".to_string(),
+            content_type: "non un content type".to_string(),
+            content: vec![0, 159, 146, 150, 255],
+        };
+
+        let raw = message_with(&attachment);
+        let parsed = mailparse::parse_mail(&raw).expect("messaggio rileggibile");
+        let part = &parsed.subparts[1];
+        assert_eq!(part.ctype.mimetype, "application/octet-stream");
+        assert_eq!(part.get_content_disposition().params.get("filename").map(String::as_str), Some("dati.bin"));
+        assert_eq!(part.get_body_raw().expect("corpo decodificato"), attachment.content);
+    }
+
+    fn test_message() -> Message {
+        Message::builder()
+            .from("test@example.com".parse().unwrap())
+            .to("destinatario@example.com".parse().unwrap())
+            .subject("Prova di invio")
+            .body("Corpo del messaggio".to_string())
+            .expect("messaggio valido")
+    }
+
+    fn with_auth(server: &FakeSmtpServer, auth: AuthMethod) -> AccountConfig {
+        AccountConfig { auth, ..server.account() }
+    }
+
+    #[tokio::test]
+    async fn sends_with_xoauth2() {
+        let server = FakeSmtpServer::start(&["AUTH PLAIN LOGIN XOAUTH2"], vec![("AUTH XOAUTH2", "235 2.7.0 Accepted")]).await;
+
+        let account = with_auth(&server, AuthMethod::Xoauth2);
+        mailer(&account, "token-valido").unwrap().send(test_message()).await.expect("invio riuscito");
+
+        let commands = server.commands();
+        let auth = commands.iter().find(|c| c.starts_with("AUTH XOAUTH2 ")).expect("comando AUTH XOAUTH2");
+        let response = BASE64_STANDARD.decode(auth.trim_start_matches("AUTH XOAUTH2 ")).unwrap();
+        assert_eq!(response, b"user=test@example.com\x01auth=Bearer token-valido\x01\x01");
+        assert!(commands.iter().any(|c| c == "RCPT TO:<destinatario@example.com>"));
+        let messages = server.messages();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Subject: Prova di invio"));
+    }
+
+    #[tokio::test]
+    async fn rejected_xoauth2_token_is_an_auth_rejection() {
+        // Gmail risponde a un token scaduto con una richiesta 334 contenente l'errore in JSON
+        let server = FakeSmtpServer::start(
+            &["AUTH XOAUTH2"],
+            vec![("AUTH XOAUTH2", "334 eyJzdGF0dXMiOiI0MDEiLCJzY2hlbWVzIjoiYmVhcmVyIn0=")],
+        )
+        .await;
+
+        let account = with_auth(&server, AuthMethod::Xoauth2);
+        let error = mailer(&account, "token-scaduto").unwrap().send(test_message()).await.expect_err("token rifiutato");
+        assert!(is_auth_rejected(&error), "{}", error);
+        assert!(server.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_password_is_an_auth_rejection() {
+        let server = FakeSmtpServer::start(
+            &["AUTH PLAIN LOGIN"],
+            vec![("AUTH PLAIN", "535 5.7.8 Authentication credentials invalid")],
+        )
+        .await;
+
+        let account = with_auth(&server, AuthMethod::Plain);
+        let error = mailer(&account, "password-errata").unwrap().send(test_message()).await.expect_err("password rifiutata");
+        assert!(is_auth_rejected(&error), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_not_auth_rejections() {
+        // Nessun meccanismo compatibile annunciato: errore del client ma non un rifiuto del token
+        let server = FakeSmtpServer::start(&["AUTH PLAIN LOGIN"], vec![]).await;
+        let account = with_auth(&server, AuthMethod::Xoauth2);
+        let error = mailer(&account, "token").unwrap().send(test_message()).await.expect_err("nessun meccanismo");
+        assert!(error.is_client());
+        assert!(!is_auth_rejected(&error), "{}", error);
+
+        // Destinatario rifiutato dopo un'autenticazione riuscita
+        let server = FakeSmtpServer::start(
+            &["AUTH XOAUTH2"],
+            vec![("AUTH XOAUTH2", "235 2.7.0 Accepted"), ("RCPT TO", "550 5.1.1 User unknown")],
+        )
+        .await;
+        let account = with_auth(&server, AuthMethod::Xoauth2);
+        let error = mailer(&account, "token").unwrap().send(test_message()).await.expect_err("destinatario rifiutato");
+        assert!(!is_auth_rejected(&error), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn sends_with_oauthbearer() {
+        let server = FakeSmtpServer::start(&["AUTH PLAIN XOAUTH2 OAUTHBEARER"], vec![("AUTH OAUTHBEARER", "235 2.7.0 Accepted")]).await;
+
+        let account = with_auth(&server, AuthMethod::OauthBearer);
+        deliver(&account, "token-valido", &test_message()).await.expect("invio riuscito");
+
+        let commands = server.commands();
+        let auth = commands.iter().find(|c| c.starts_with("AUTH OAUTHBEARER ")).expect("comando AUTH OAUTHBEARER");
+        let response = BASE64_STANDARD.decode(auth.trim_start_matches("AUTH OAUTHBEARER ")).unwrap();
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            format!("n,a=test@example.com,\x01host=127.0.0.1\x01port={}\x01auth=Bearer token-valido\x01\x01", server.port)
+        );
+        assert!(commands.iter().any(|c| c == "RCPT TO:<destinatario@example.com>"));
+        let messages = server.messages();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Subject: Prova di invio"));
+    }
+
+    #[tokio::test]
+    async fn rejected_oauthbearer_token_is_an_auth_rejection() {
+        // RFC 7628: errore in JSON nella richiesta 334, poi 535 dopo la risposta ^A del client
+        let server = FakeSmtpServer::start(
+            &["AUTH OAUTHBEARER"],
+            vec![
+                ("AUTH OAUTHBEARER", "334 eyJzdGF0dXMiOiJpbnZhbGlkX3Rva2VuIn0="),
+                ("AQ==", "535 5.7.8 Authentication credentials invalid"),
+            ],
+        )
+        .await;
+
+        let account = with_auth(&server, AuthMethod::OauthBearer);
+        let error = deliver(&account, "token-scaduto", &test_message()).await.expect_err("token rifiutato");
+        assert!(is_auth_rejected(&error), "{}", error);
+        assert!(server.commands().iter().any(|c| c == "AQ=="));
+        assert!(server.messages().is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use imap_proto::types::{MailboxDatum, Response};
+use serde::{Deserialize, Serialize};
+
+use super::imap::{attribute_name, MailFolder};
+use super::pool::PooledConnection;
+use super::responses::run_command_with;
+
+/// Ruolo di una cartella speciale (RFC 6154)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FolderRole {
+    Inbox,
+    Sent,
+    Drafts,
+    Trash,
+    Junk,
+    Archive,
+    All,
+    Flagged,
+}
+
+/// Nomi localizzati usati come ultima risorsa quando il server non annuncia SPECIAL-USE
+const LOCALIZED_NAMES: &[(FolderRole, &[&str])] = &[
+    (FolderRole::Sent, &[
+        "sent", "sent items", "sent mail", "sent messages", "posta inviata", "inviata", "inviati",
+        "elementi inviati", "gesendet", "gesendete elemente", "gesendete objekte", "envoyés",
+        "éléments envoyés", "messages envoyés", "enviados", "elementos enviados", "verzonden",
+        "verzonden items", "enviadas", "itens enviados", "送信済みメール", "送信済みアイテム",
+    ]),
+    (FolderRole::Drafts, &[
+        "drafts", "draft", "bozze", "entwürfe", "brouillons", "borradores", "concepten",
+        "rascunhos", "下書き",
+    ]),
+    (FolderRole::Trash, &[
+        "trash", "deleted items", "deleted messages", "bin", "cestino", "posta eliminata",
+        "elementi eliminati", "papierkorb", "gelöschte elemente", "gelöschte objekte", "corbeille",
+        "éléments supprimés", "papelera", "elementos eliminados", "prullenbak",
+        "verwijderde items", "lixeira", "itens excluídos", "ゴミ箱", "削除済みアイテム",
+    ]),
+    (FolderRole::Junk, &[
+        "junk", "junk e-mail", "junk email", "junk mail", "spam", "bulk mail",
+        "posta indesiderata", "spamverdacht", "junk-e-mail", "courrier indésirable", "indésirables",
+        "correo no deseado", "ongewenste e-mail", "lixo eletrônico", "迷惑メール",
+    ]),
+    (FolderRole::Archive, &[
+        "archive", "archives", "archivio", "archiv", "archivé", "archivo", "archief", "arquivo",
+        "アーカイブ",
+    ]),
+];
+
+/// Ruolo derivato dagli attributi LIST (RFC 6154) o dai nomi usati da XLIST
+pub fn role_from_attributes(attributes: &[String]) -> Option<FolderRole> {
+    attributes.iter().find_map(|attribute| {
+        match attribute.to_ascii_lowercase().as_str() {
+            "\\sent" => Some(FolderRole::Sent),
+            "\\drafts" => Some(FolderRole::Drafts),
+            "\\trash" => Some(FolderRole::Trash),
+            "\\junk" | "\\spam" => Some(FolderRole::Junk),
+            "\\archive" => Some(FolderRole::Archive),
+            "\\all" | "\\allmail" => Some(FolderRole::All),
+            "\\flagged" | "\\starred" => Some(FolderRole::Flagged),
+            "\\inbox" => Some(FolderRole::Inbox),
+            _ => None,
+        }
+    })
+}
+
+/// Ruolo dedotto dal nome visualizzato della cartella
+fn role_from_name(name: &str) -> Option<FolderRole> {
+    let name = name.trim().to_lowercase();
+    LOCALIZED_NAMES
+        .iter()
+        .find(|(_, names)| names.contains(&name.as_str()))
+        .map(|(role, _)| *role)
+}
+
+/// Assegna `role` a ogni cartella: INBOX, poi attributi special-use,
+/// infine i nomi localizzati per i ruoli non ancora assegnati.
+pub fn assign_roles(folders: &mut [MailFolder]) {
+    for folder in folders.iter_mut() {
+        folder.role = if folder.path.eq_ignore_ascii_case("INBOX") {
+            Some(FolderRole::Inbox)
+        } else {
+            role_from_attributes(&folder.attributes)
+        };
+    }
+
+    // Un ruolo dedotto dal nome non deve duplicare un ruolo annunciato dal server
+    let mut assigned: Vec<FolderRole> = folders.iter().filter_map(|f| f.role).collect();
+    for folder in folders.iter_mut().filter(|f| f.role.is_none() && f.selectable) {
+        if let Some(role) = role_from_name(&folder.name) {
+            if !assigned.contains(&role) {
+                folder.role = Some(role);
+                assigned.push(role);
+            }
+        }
+    }
+}
+
+/// Legge gli attributi special-use con `LIST "" "*" RETURN (SPECIAL-USE)` (RFC 6154)
+/// oppure con XLIST (estensione storica di Gmail) quando LIST non li riporta.
+/// Restituisce coppie (percorso, attributi), vuote se il server rifiuta il comando;
+/// fallisce solo se la connessione è stata persa.
+pub async fn special_use_attributes(conn: &mut PooledConnection) -> Result<Vec<(String, Vec<String>)>, String> {
+    let command = if conn.has_capability("SPECIAL-USE") {
+        "LIST \"\" \"*\" RETURN (SPECIAL-USE)"
+    } else if conn.has_capability("XLIST") {
+        "XLIST \"\" \"*\""
+    } else {
+        return Ok(Vec::new());
+    };
+
+    let mut entries = Vec::new();
+    let result = run_command_with(conn.session()?, command, |response| entries.extend(list_entry(response))).await;
+    match result {
+        Ok(()) => Ok(entries
+            .into_iter()
+            .map(|(path, attributes)| (conn.decode_mailbox(&path), attributes))
+            .collect()),
+        Err(e) => {
+            let e = conn.check(e);
+            if !conn.is_alive() {
+                return Err(format!("Errore nel {}: {}", command, e));
+            }
+            println!("[IMAP] {} fallito: {}", command, e);
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// Percorso e attributi di una risposta LIST (o XLIST, riscritta come LIST dalla connessione)
+pub(crate) fn list_entry(response: &Response<'_>) -> Option<(String, Vec<String>)> {
+    match response {
+        Response::MailboxData(MailboxDatum::List { name_attributes, name, .. }) => {
+            Some((name.to_string(), name_attributes.iter().map(attribute_name).collect()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::pool::ImapPool;
+    use crate::commands::test_support::FakeImapServer;
+
+    #[tokio::test]
+    async fn reads_special_use_attributes_from_list() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 SPECIAL-USE".to_string()]),
+            ("LIST", vec![
+                "* LIST (\\HasNoChildren) \"/\" \"INBOX\"".to_string(),
+                "* LIST (\\HasNoChildren \\Sent) \"/\" \"Posta inviata\"".to_string(),
+            ]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let attributes = special_use_attributes(&mut conn).await.unwrap();
+
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(attributes[1].0, "Posta inviata");
+        assert_eq!(role_from_attributes(&attributes[1].1), Some(FolderRole::Sent));
+        assert!(server.commands().contains(&"LIST \"\" \"*\" RETURN (SPECIAL-USE)".to_string()));
+    }
+
+    #[tokio::test]
+    async fn reads_xlist_responses_and_keeps_the_session_usable() {
+        let server = FakeImapServer::start(vec![
+            ("CAPABILITY", vec!["* CAPABILITY IMAP4rev1 XLIST".to_string()]),
+            ("XLIST", vec![
+                "* XLIST (\\HasNoChildren \\Inbox) \"/\" \"Inbox\"".to_string(),
+                "* XLIST (\\HasNoChildren \\AllMail) \"/\" \"[Gmail]/All Mail\"".to_string(),
+                "* XLIST (\\HasNoChildren \\Spam) \"/\" \"[Gmail]/Spam\"".to_string(),
+            ]),
+        ])
+        .await;
+        let pool = ImapPool::default();
+        let mut conn = server.connect(&pool).await;
+
+        let attributes = special_use_attributes(&mut conn).await.unwrap();
+
+        let roles: Vec<(String, Option<FolderRole>)> = attributes
+            .iter()
+            .map(|(path, attributes)| (path.clone(), role_from_attributes(attributes)))
+            .collect();
+        assert_eq!(
+            roles,
+            vec![
+                ("INBOX".to_string(), Some(FolderRole::Inbox)),
+                ("[Gmail]/All Mail".to_string(), Some(FolderRole::All)),
+                ("[Gmail]/Spam".to_string(), Some(FolderRole::Junk)),
+            ]
+        );
+        // Le risposte XLIST non devono bloccare lo stream della sessione
+        conn.session().unwrap().noop().await.unwrap();
+        assert!(conn.is_alive());
+    }
+}
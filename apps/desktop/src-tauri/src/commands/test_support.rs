@@ -0,0 +1,356 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hickory_resolver::proto::op::{Message, MessageType, ResponseCode};
+use hickory_resolver::proto::rr::Record;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UdpSocket};
+
+use super::account::{AccountConfig, AuthMethod, Security, ServerConfig};
+use super::auth::SessionError;
+use super::imap::create_imap_session;
+use super::pool::{ImapPool, PooledConnection};
+
+/// Server IMAP finto per i test: a ogni comando risponde con le righe non taggate
+/// associate al primo prefisso corrispondente, seguite da `<tag> OK`.
+/// Una riga che inizia con `$tag ` sostituisce la risposta taggata (es. `$tag NO rifiutato`);
+/// durante AUTHENTICATE, dopo una richiesta di continuazione (`+ ...`) il server attende la riga del client.
+/// Registra i comandi ricevuti (senza tag) e le righe di continuazione per le verifiche.
+pub(crate) struct FakeImapServer {
+    pub port: u16,
+    commands: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeImapServer {
+    /// Avvia il server su una porta libera di 127.0.0.1.
+    /// `script` associa un prefisso di comando (es. "UID SEARCH") alle risposte non taggate.
+    pub async fn start(script: Vec<(&'static str, Vec<String>)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind del server finto");
+        let port = listener.local_addr().expect("porta del server finto").port();
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let script = Arc::new(script);
+
+        let recorded = commands.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (read_half, mut write_half) = stream.into_split();
+                let mut reader = BufReader::new(read_half);
+                if write_half.write_all(b"* OK server di test pronto\r\n").await.is_err() {
+                    continue;
+                }
+
+                let mut line = String::new();
+                while matches!(reader.read_line(&mut line).await, Ok(n) if n > 0) {
+                    let request = line.trim_end().to_string();
+                    line.clear();
+                    let Some((tag, command)) = request.split_once(' ') else {
+                        continue;
+                    };
+                    recorded.lock().unwrap().push(command.to_string());
+
+                    let mut reply = String::new();
+                    let mut tagged = false;
+                    let upper = command.to_ascii_uppercase();
+                    if let Some((_, responses)) = script.iter().find(|(prefix, _)| upper.starts_with(prefix)) {
+                        for response in responses {
+                            match response.strip_prefix("$tag ") {
+                                Some(status) => {
+                                    reply.push_str(&format!("{} {}", tag, status));
+                                    tagged = true;
+                                }
+                                None => reply.push_str(response),
+                            }
+                            reply.push_str("\r\n");
+
+                            // Scambio SASL: inviamo quanto preparato e leggiamo la risposta del client.
+                            // Con IDLE invece il client resta in attesa fino a DONE.
+                            if response.starts_with('+') && upper.starts_with("AUTHENTICATE") {
+                                if write_half.write_all(reply.as_bytes()).await.is_err() {
+                                    break;
+                                }
+                                reply.clear();
+                                if !matches!(reader.read_line(&mut line).await, Ok(n) if n > 0) {
+                                    break;
+                                }
+                                recorded.lock().unwrap().push(line.trim_end().to_string());
+                                line.clear();
+                            }
+                        }
+                    }
+                    if !tagged {
+                        reply.push_str(&format!("{} OK completato\r\n", tag));
+                    }
+                    if write_half.write_all(reply.as_bytes()).await.is_err() || upper.starts_with("LOGOUT") {
+                        break;
+                    }
+                }
+            }
+        });
+
+        FakeImapServer { port, commands }
+    }
+
+    /// Comandi ricevuti finora, senza tag
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Account con autenticazione LOGIN in chiaro verso il server finto
+    pub fn account(&self) -> AccountConfig {
+        local_account(self.port)
+    }
+
+    /// Sessione del pool verso il server finto, per l'account "test"
+    pub async fn connect(&self, pool: &ImapPool) -> PooledConnection {
+        let account = self.account();
+        let open = || async {
+            let session = create_imap_session(&account, "segreto").await?;
+            Ok::<_, SessionError>((account.clone(), session))
+        };
+        pool.acquire_with("test", &self.account(), open).await.expect("connessione al server finto")
+    }
+}
+
+/// Server SMTP finto per i test: risponde a EHLO annunciando `extensions`, ai comandi
+/// con la risposta del primo prefisso corrispondente in `script` e agli altri con 250.
+/// Registra i comandi ricevuti e il contenuto dei messaggi inviati con DATA.
+pub(crate) struct FakeSmtpServer {
+    pub port: u16,
+    commands: Arc<Mutex<Vec<String>>>,
+    messages: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeSmtpServer {
+    /// Avvia il server su una porta libera di 127.0.0.1.
+    /// `script` associa un prefisso di comando (es. "AUTH PLAIN") a una risposta completa (es. "535 5.7.8 ...").
+    pub async fn start(extensions: &[&str], script: Vec<(&'static str, &'static str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind del server finto");
+        let port = listener.local_addr().expect("porta del server finto").port();
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let mut ehlo = vec!["250-localhost".to_string()];
+        ehlo.extend(extensions.iter().map(|extension| format!("250-{}", extension)));
+        ehlo.push("250 8BITMIME".to_string());
+        let ehlo = ehlo.join("\r\n");
+        let script = Arc::new(script);
+
+        let (recorded, delivered) = (commands.clone(), messages.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (read_half, mut write_half) = stream.into_split();
+                let mut reader = BufReader::new(read_half);
+                if write_half.write_all(b"220 localhost ESMTP server di test\r\n").await.is_err() {
+                    continue;
+                }
+
+                let mut line = String::new();
+                while matches!(reader.read_line(&mut line).await, Ok(n) if n > 0) {
+                    let command = line.trim_end().to_string();
+                    line.clear();
+                    recorded.lock().unwrap().push(command.clone());
+
+                    let upper = command.to_ascii_uppercase();
+                    let reply = match script.iter().find(|(prefix, _)| upper.starts_with(prefix)) {
+                        Some((_, reply)) => reply.to_string(),
+                        None if upper.starts_with("EHLO") => ehlo.clone(),
+                        None if upper.starts_with("DATA") => {
+                            if write_half.write_all(b"354 fine con <CRLF>.<CRLF>\r\n").await.is_err() {
+                                break;
+                            }
+                            // Il messaggio termina con una riga contenente solo "."
+                            let mut message = String::new();
+                            while matches!(reader.read_line(&mut line).await, Ok(n) if n > 0) {
+                                if line.trim_end() == "." {
+                                    break;
+                                }
+                                message.push_str(&line);
+                                line.clear();
+                            }
+                            line.clear();
+                            delivered.lock().unwrap().push(message);
+                            "250 2.0.0 messaggio accettato".to_string()
+                        }
+                        None if upper.starts_with("QUIT") => "221 2.0.0 arrivederci".to_string(),
+                        None => "250 2.0.0 OK".to_string(),
+                    };
+                    if write_half.write_all(format!("{}\r\n", reply).as_bytes()).await.is_err()
+                        || upper.starts_with("QUIT")
+                    {
+                        break;
+                    }
+                }
+            }
+        });
+
+        FakeSmtpServer { port, commands, messages }
+    }
+
+    /// Comandi ricevuti finora
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Messaggi ricevuti con DATA, senza la riga finale "."
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// Account con autenticazione LOGIN in chiaro verso il server finto
+    pub fn account(&self) -> AccountConfig {
+        local_account(self.port)
+    }
+}
+
+/// Server HTTP finto per i test: risponde con il corpo associato al percorso
+/// (query esclusa) e con 404 agli altri. Registra le richieste ricevute, query compresa,
+/// e il corpo delle POST.
+pub(crate) struct FakeHttpServer {
+    pub port: u16,
+    requests: Arc<Mutex<Vec<String>>>,
+    bodies: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeHttpServer {
+    /// Avvia il server su una porta libera di 127.0.0.1; `routes` associa un percorso al corpo della risposta
+    pub async fn start(routes: Vec<(String, String)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind del server finto");
+        let port = listener.local_addr().expect("porta del server finto").port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let routes = Arc::new(routes);
+
+        let (recorded, recorded_bodies) = (requests.clone(), bodies.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (routes, recorded, recorded_bodies) = (routes.clone(), recorded.clone(), recorded_bodies.clone());
+                // Le fonti della discovery vengono interrogate in parallelo
+                tokio::spawn(async move {
+                    let (read_half, mut write_half) = stream.into_split();
+                    let mut reader = BufReader::new(read_half);
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).await.is_err() {
+                        return;
+                    }
+                    // Delle intestazioni serve solo Content-Length, per leggere il corpo delle POST
+                    let mut header = String::new();
+                    let mut content_length = 0;
+                    while matches!(reader.read_line(&mut header).await, Ok(n) if n > 0) && !header.trim_end().is_empty() {
+                        if let Some((name, value)) = header.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap_or(0);
+                            }
+                        }
+                        header.clear();
+                    }
+                    if content_length > 0 {
+                        let mut body = vec![0u8; content_length];
+                        if reader.read_exact(&mut body).await.is_err() {
+                            return;
+                        }
+                        recorded_bodies.lock().unwrap().push(String::from_utf8_lossy(&body).into_owned());
+                    }
+
+                    let target = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let path = target.split('?').next().unwrap_or("/").to_string();
+                    recorded.lock().unwrap().push(target);
+                    let (status, body) = match routes.iter().find(|(route, _)| *route == path) {
+                        Some((_, body)) => ("200 OK", body.clone()),
+                        None => ("404 Not Found", "non trovato".to_string()),
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = write_half.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        FakeHttpServer { port, requests, bodies }
+    }
+
+    /// URL completo per un percorso del server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// Richieste ricevute finora (percorso e query)
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Corpi delle richieste POST ricevute finora
+    pub fn bodies(&self) -> Vec<String> {
+        self.bodies.lock().unwrap().clone()
+    }
+}
+
+/// Server DNS finto per i test, su UDP: risponde con i record che corrispondono
+/// a nome e tipo richiesti e con NXDOMAIN se non ce ne sono
+pub(crate) struct FakeDnsServer {
+    pub address: SocketAddr,
+}
+
+impl FakeDnsServer {
+    pub async fn start(records: Vec<Record>) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.expect("bind del DNS finto");
+        let address = socket.local_addr().expect("indirizzo del DNS finto");
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buffer).await {
+                let Ok(request) = Message::from_vec(&buffer[..len]) else {
+                    continue;
+                };
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_op_code(request.op_code())
+                    .set_recursion_desired(request.recursion_desired())
+                    .set_recursion_available(true);
+                for query in request.queries() {
+                    response.add_query(query.clone());
+                    for record in records
+                        .iter()
+                        .filter(|r| r.name() == query.name() && r.record_type() == query.query_type())
+                    {
+                        response.add_answer(record.clone());
+                    }
+                }
+                if response.answers().is_empty() {
+                    response.set_response_code(ResponseCode::NXDomain);
+                }
+                if let Ok(bytes) = response.to_vec() {
+                    let _ = socket.send_to(&bytes, peer).await;
+                }
+            }
+        });
+
+        FakeDnsServer { address }
+    }
+}
+
+/// Porta di 127.0.0.1 su cui non è in ascolto nessuno
+pub(crate) async fn closed_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind della porta di prova");
+    listener.local_addr().expect("porta di prova").port()
+}
+
+/// Account di test con IMAP e SMTP in chiaro su 127.0.0.1
+pub(crate) fn local_account(port: u16) -> AccountConfig {
+    let server = ServerConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        security: Security::Plain,
+    };
+    AccountConfig {
+        email: "test@example.com".to_string(),
+        username: None,
+        imap: server.clone(),
+        smtp: server,
+        auth: AuthMethod::Login,
+    }
+}
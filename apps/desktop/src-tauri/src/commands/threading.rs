@@ -0,0 +1,595 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::imap::MailMessage;
+
+/// Prefissi di risposta e inoltro rimossi dall'oggetto per raggruppare le conversazioni
+const REPLY_PREFIXES: &[&str] = &["re", "r", "rif", "aw", "sv", "vs", "antw", "odp"];
+const FORWARD_PREFIXES: &[&str] = &["fw", "fwd", "i", "inoltro", "wg", "tr", "rv", "enc"];
+
+/// Nodo dell'albero di una conversazione; `uid` è None per i messaggi citati ma non presenti
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadNode {
+    pub uid: Option<u32>,
+    pub children: Vec<ThreadNode>,
+}
+
+/// Conversazione con i messaggi ordinati per data e l'albero delle risposte
+#[derive(Debug, Serialize)]
+pub struct MailThread {
+    pub id: String,
+    pub subject: String,
+    pub last_date: i64,
+    pub unread_count: usize,
+    pub messages: Vec<MailMessage>,
+    pub tree: Vec<ThreadNode>,
+}
+
+/// Estrae i Message-ID (`<...>`) da un header References o In-Reply-To
+pub fn parse_message_ids(value: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else { break };
+        let id: String = rest[start..start + end + 1]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if id.len() > 2 && !ids.contains(&id) {
+            ids.push(id);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    ids
+}
+
+/// Raggruppa i messaggi in conversazioni con l'algoritmo di Jamie Zawinski:
+/// References/In-Reply-To e, per i messaggi senza riferimenti, l'oggetto normalizzato.
+pub fn thread_messages(messages: Vec<MailMessage>) -> Vec<MailThread> {
+    let roots = group_by_subject(build_containers(&messages), &messages);
+    let mut messages: Vec<Option<MailMessage>> = messages.into_iter().map(Some).collect();
+
+    let mut threads: Vec<MailThread> = roots
+        .into_iter()
+        .map(|root| {
+            let mut thread_messages = Vec::new();
+            let tree = vec![to_thread_node(root, &mut messages, &mut thread_messages)];
+            build_thread(None, tree, thread_messages)
+        })
+        .collect();
+    sort_threads(&mut threads);
+    threads
+}
+
+/// Raggruppa i messaggi per `thread_id` assegnato dal server (X-GM-THRID di Gmail);
+/// l'albero di ogni conversazione viene ricostruito dai riferimenti
+pub fn threads_by_id(messages: Vec<MailMessage>) -> Vec<MailThread> {
+    let mut groups: HashMap<String, Vec<MailMessage>> = HashMap::new();
+    for message in messages {
+        let id = message.thread_id.clone().unwrap_or_else(|| message.message_id.clone());
+        groups.entry(id).or_default().push(message);
+    }
+
+    let mut threads: Vec<MailThread> = groups
+        .into_iter()
+        .map(|(id, group)| {
+            let roots = build_containers(&group);
+            let mut messages: Vec<Option<MailMessage>> = group.into_iter().map(Some).collect();
+
+            let mut thread_messages = Vec::new();
+            let tree = roots
+                .into_iter()
+                .map(|root| to_thread_node(root, &mut messages, &mut thread_messages))
+                .collect();
+            build_thread(Some(id), tree, thread_messages)
+        })
+        .collect();
+    sort_threads(&mut threads);
+    threads
+}
+
+/// Conversazioni calcolate dal server con `UID THREAD REFERENCES` (RFC 5256)
+pub fn threads_from_server(tree: Vec<ThreadNode>, messages: Vec<MailMessage>) -> Vec<MailThread> {
+    let mut by_uid: HashMap<u32, MailMessage> = messages.into_iter().map(|m| (m.uid, m)).collect();
+
+    let mut threads: Vec<MailThread> = tree
+        .into_iter()
+        .map(|root| {
+            let mut uids = Vec::new();
+            collect_uids(&root, &mut uids);
+            let thread_messages = uids.iter().filter_map(|uid| by_uid.remove(uid)).collect();
+            build_thread(None, vec![root], thread_messages)
+        })
+        .filter(|thread| !thread.messages.is_empty())
+        .collect();
+    sort_threads(&mut threads);
+    threads
+}
+
+/// Interpreta i dati di una risposta THREAD, come `(2)(3 6 (4 23)(44 7 96))`, in alberi di UID
+pub fn parse_thread_response(threads: &str) -> Vec<ThreadNode> {
+    let mut roots = Vec::new();
+    let bytes = threads.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'(' {
+            roots.extend(parse_thread_group(bytes, &mut pos));
+        } else {
+            pos += 1;
+        }
+    }
+    roots
+}
+
+/// Un gruppo "(n1 n2 ... (sotto)(gruppi))" è una catena n1 -> n2 -> ... con i sottogruppi
+/// come figli dell'ultimo elemento; senza UID il gruppo è un nodo fittizio
+fn parse_thread_group(bytes: &[u8], pos: &mut usize) -> Option<ThreadNode> {
+    *pos += 1;
+    let mut uids = Vec::new();
+    let mut children = Vec::new();
+
+    while *pos < bytes.len() {
+        match bytes[*pos] {
+            b')' => {
+                *pos += 1;
+                break;
+            }
+            b'(' => children.extend(parse_thread_group(bytes, pos)),
+            b'0'..=b'9' => {
+                let start = *pos;
+                while *pos < bytes.len() && bytes[*pos].is_ascii_digit() {
+                    *pos += 1;
+                }
+                if let Ok(uid) = std::str::from_utf8(&bytes[start..*pos]).unwrap_or("").parse() {
+                    uids.push(uid);
+                }
+            }
+            _ => *pos += 1,
+        }
+    }
+
+    let mut chain = uids.into_iter().rev();
+    let mut node = match chain.next() {
+        Some(uid) => ThreadNode { uid: Some(uid), children },
+        None if children.is_empty() => return None,
+        None => ThreadNode { uid: None, children },
+    };
+    for uid in chain {
+        node = ThreadNode { uid: Some(uid), children: vec![node] };
+    }
+    Some(node)
+}
+
+/// Container dell'algoritmo JWZ: un Message-ID, visto come messaggio o solo come riferimento
+struct Container {
+    id: String,
+    message: Option<usize>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// Nodo dell'albero dopo la potatura dei container vuoti
+struct Node {
+    id: String,
+    message: Option<usize>,
+    children: Vec<Node>,
+}
+
+/// Passi 1-4 di JWZ: collega i messaggi tramite i riferimenti e restituisce le radici potate
+fn build_containers(messages: &[MailMessage]) -> Vec<Node> {
+    let mut containers: Vec<Container> = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+
+    for (index, message) in messages.iter().enumerate() {
+        let message_id = message.message_id.trim().to_string();
+        let mut current = container_for(&message_id, &mut ids, &mut containers);
+        if containers[current].message.is_some() {
+            // Message-ID duplicato: il secondo messaggio ha un container separato
+            current = containers.len();
+            containers.push(Container { id: message_id, message: None, parent: None, children: Vec::new() });
+        }
+        containers[current].message = Some(index);
+
+        let mut references = message.references.clone().unwrap_or_default();
+        if let Some(in_reply_to) = &message.in_reply_to {
+            if !references.contains(in_reply_to) {
+                references.push(in_reply_to.clone());
+            }
+        }
+
+        // Collega ogni riferimento al successivo senza sovrascrivere legami già noti
+        let mut previous: Option<usize> = None;
+        for reference in &references {
+            let container = container_for(reference, &mut ids, &mut containers);
+            if let Some(parent) = previous {
+                if containers[container].parent.is_none()
+                    && parent != container
+                    && !is_descendant(&containers, parent, container)
+                {
+                    link(&mut containers, parent, container);
+                }
+            }
+            previous = Some(container);
+        }
+
+        // Il genitore del messaggio è l'ultimo riferimento, salvo cicli
+        match previous {
+            Some(parent) if parent == current || is_descendant(&containers, parent, current) => {}
+            Some(parent) => {
+                unlink(&mut containers, current);
+                link(&mut containers, parent, current);
+            }
+            None => unlink(&mut containers, current),
+        }
+    }
+
+    let roots: Vec<usize> = (0..containers.len())
+        .filter(|index| containers[*index].parent.is_none())
+        .collect();
+    roots
+        .into_iter()
+        .flat_map(|root| prune(&containers, root, true))
+        .collect()
+}
+
+fn container_for(id: &str, ids: &mut HashMap<String, usize>, containers: &mut Vec<Container>) -> usize {
+    *ids.entry(id.to_string()).or_insert_with(|| {
+        containers.push(Container { id: id.to_string(), message: None, parent: None, children: Vec::new() });
+        containers.len() - 1
+    })
+}
+
+/// true se `node` discende da `ancestor` (o coincide)
+fn is_descendant(containers: &[Container], node: usize, ancestor: usize) -> bool {
+    let mut current = Some(node);
+    while let Some(index) = current {
+        if index == ancestor {
+            return true;
+        }
+        current = containers[index].parent;
+    }
+    false
+}
+
+fn link(containers: &mut [Container], parent: usize, child: usize) {
+    containers[child].parent = Some(parent);
+    containers[parent].children.push(child);
+}
+
+fn unlink(containers: &mut [Container], child: usize) {
+    if let Some(parent) = containers[child].parent.take() {
+        containers[parent].children.retain(|c| *c != child);
+    }
+}
+
+/// Rimuove i container vuoti promuovendo i figli; alla radice un container vuoto
+/// resta solo se tiene insieme più figli
+fn prune(containers: &[Container], index: usize, is_root: bool) -> Vec<Node> {
+    let children: Vec<Node> = containers[index]
+        .children
+        .iter()
+        .flat_map(|child| prune(containers, *child, false))
+        .collect();
+
+    match containers[index].message {
+        Some(message) => vec![Node { id: containers[index].id.clone(), message: Some(message), children }],
+        None if is_root && children.len() > 1 => {
+            vec![Node { id: containers[index].id.clone(), message: None, children }]
+        }
+        None => children,
+    }
+}
+
+/// Passo 5 di JWZ: unisce le radici con lo stesso oggetto normalizzato
+fn group_by_subject(roots: Vec<Node>, messages: &[MailMessage]) -> Vec<Node> {
+    let mut result: Vec<Node> = Vec::new();
+    let mut table: HashMap<String, usize> = HashMap::new();
+
+    for node in roots {
+        let (subject, is_reply) = match node_subject(&node, messages) {
+            Some((subject, is_reply)) if !subject.is_empty() => (subject, is_reply),
+            _ => {
+                result.push(node);
+                continue;
+            }
+        };
+
+        let Some(&index) = table.get(&subject) else {
+            table.insert(subject, result.len());
+            result.push(node);
+            continue;
+        };
+
+        let existing = &mut result[index];
+        let existing_is_reply = node_subject(existing, messages).map(|(_, r)| r).unwrap_or(false);
+        if existing.message.is_none() && node.message.is_none() {
+            existing.children.extend(node.children);
+        } else if existing.message.is_none() {
+            existing.children.push(node);
+        } else if node.message.is_none() {
+            let previous = std::mem::replace(existing, node);
+            existing.children.push(previous);
+        } else if !existing_is_reply && is_reply {
+            existing.children.push(node);
+        } else {
+            // Nessuno dei due è risposta dell'altro: diventano fratelli sotto un nodo fittizio
+            let id = existing.id.clone();
+            let previous = std::mem::replace(existing, Node { id, message: None, children: Vec::new() });
+            existing.children = vec![previous, node];
+        }
+    }
+    result
+}
+
+/// Oggetto normalizzato del nodo (o del primo figlio se fittizio) e se era una risposta
+fn node_subject(node: &Node, messages: &[MailMessage]) -> Option<(String, bool)> {
+    match node.message {
+        Some(index) => Some(normalize_subject(&messages[index].subject)),
+        None => node.children.first().and_then(|child| node_subject(child, messages)),
+    }
+}
+
+/// Oggetto normalizzato per il confronto (spazi e maiuscole) e se era una risposta
+fn normalize_subject(subject: &str) -> (String, bool) {
+    let (rest, is_reply) = strip_subject_prefixes(subject);
+    let normalized = rest.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (normalized, is_reply)
+}
+
+/// Rimuove i prefissi "Re:", "Fwd:", "R:", "I:", "AW:", "Re[2]:" ... dall'oggetto.
+/// Restituisce l'oggetto rimanente e se almeno un prefisso era presente.
+pub fn strip_subject_prefixes(subject: &str) -> (&str, bool) {
+    let mut rest = subject.trim();
+    let mut stripped = false;
+
+    while let Some(colon) = rest.find(':') {
+        let prefix = rest[..colon].trim();
+        // Contatori come "Re[2]" o "Re(2)"
+        let word = prefix
+            .split(['[', '('])
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        if REPLY_PREFIXES.contains(&word.as_str()) || FORWARD_PREFIXES.contains(&word.as_str()) {
+            stripped = true;
+            rest = rest[colon + 1..].trim_start();
+        } else {
+            break;
+        }
+    }
+    (rest, stripped)
+}
+
+/// Converte un nodo JWZ nell'albero di UID, spostando i messaggi nella conversazione
+fn to_thread_node(node: Node, messages: &mut [Option<MailMessage>], out: &mut Vec<MailMessage>) -> ThreadNode {
+    let uid = node.message.and_then(|index| messages[index].take()).map(|message| {
+        let uid = message.uid;
+        out.push(message);
+        uid
+    });
+    ThreadNode {
+        uid,
+        children: node
+            .children
+            .into_iter()
+            .map(|child| to_thread_node(child, messages, out))
+            .collect(),
+    }
+}
+
+/// UID presenti negli alberi, in ordine di visita
+pub fn thread_uids(tree: &[ThreadNode]) -> Vec<u32> {
+    let mut uids = Vec::new();
+    for node in tree {
+        collect_uids(node, &mut uids);
+    }
+    uids
+}
+
+fn collect_uids(node: &ThreadNode, out: &mut Vec<u32>) {
+    out.extend(node.uid);
+    for child in &node.children {
+        collect_uids(child, out);
+    }
+}
+
+/// Compone la conversazione: se manca l'id del server usa il Message-ID del primo messaggio dell'albero
+fn build_thread(id: Option<String>, mut tree: Vec<ThreadNode>, mut messages: Vec<MailMessage>) -> MailThread {
+    let uids = thread_uids(&tree);
+    let first = uids
+        .first()
+        .and_then(|uid| messages.iter().find(|m| m.uid == *uid))
+        .or_else(|| messages.first());
+    let id = id
+        .or_else(|| first.map(|m| m.message_id.clone()))
+        .unwrap_or_default();
+    let subject = first.map(|m| m.subject.clone()).unwrap_or_default();
+
+    messages.sort_by_key(|m| m.date);
+    for message in messages.iter_mut() {
+        message.thread_id = Some(id.clone());
+    }
+
+    // Risposte in ordine cronologico a ogni livello
+    let dates: HashMap<u32, i64> = messages.iter().map(|m| (m.uid, m.date)).collect();
+    sort_nodes(&mut tree, &dates);
+
+    MailThread {
+        id,
+        subject,
+        last_date: messages.last().map(|m| m.date).unwrap_or_default(),
+        unread_count: messages.iter().filter(|m| !m.is_read).count(),
+        messages,
+        tree,
+    }
+}
+
+/// Data di un nodo: quella del messaggio o, se fittizio, la più vecchia tra i discendenti
+fn node_date(node: &ThreadNode, dates: &HashMap<u32, i64>) -> i64 {
+    node.uid
+        .and_then(|uid| dates.get(&uid).copied())
+        .or_else(|| node.children.iter().map(|child| node_date(child, dates)).min())
+        .unwrap_or_default()
+}
+
+fn sort_nodes(nodes: &mut [ThreadNode], dates: &HashMap<u32, i64>) {
+    nodes.sort_by_key(|node| node_date(node, dates));
+    for node in nodes.iter_mut() {
+        sort_nodes(&mut node.children, dates);
+    }
+}
+
+/// Conversazioni più recenti per prime
+fn sort_threads(threads: &mut [MailThread]) {
+    threads.sort_by_key(|thread| std::cmp::Reverse(thread.last_date));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(uid: u32, message_id: &str, subject: &str, references: &[&str]) -> MailMessage {
+        MailMessage {
+            id: format!("test-{}", uid),
+            account_id: "test".to_string(),
+            folder_id: "test-inbox".to_string(),
+            uid,
+            message_id: message_id.to_string(),
+            subject: subject.to_string(),
+            from_name: None,
+            from_address: "anna@example.com".to_string(),
+            to_addresses: Vec::new(),
+            cc_addresses: None,
+            bcc_addresses: None,
+            reply_to: None,
+            sender: None,
+            date: uid as i64 * 60,
+            size: None,
+            attachments: Vec::new(),
+            text: None,
+            html: None,
+            flags: Vec::new(),
+            keywords: Vec::new(),
+            is_read: true,
+            is_starred: false,
+            is_important: false,
+            thread_id: None,
+            in_reply_to: references.last().map(|id| id.to_string()),
+            references: Some(references.iter().map(|id| id.to_string()).collect()),
+            synced_at: 0,
+        }
+    }
+
+    /// Albero come stringa compatta: `1(2(3))`, con `_` per i nodi fittizi
+    fn shape(nodes: &[ThreadNode]) -> String {
+        nodes
+            .iter()
+            .map(|node| {
+                let uid = node.uid.map_or("_".to_string(), |uid| uid.to_string());
+                if node.children.is_empty() {
+                    uid
+                } else {
+                    format!("{}({})", uid, shape(&node.children))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn follows_a_references_chain() {
+        let threads = thread_messages(vec![
+            message(3, "<c@x>", "Re: Re: Cena", &["<a@x>", "<b@x>"]),
+            message(1, "<a@x>", "Cena", &[]),
+            message(2, "<b@x>", "Re: Cena", &["<a@x>"]),
+        ]);
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(shape(&threads[0].tree), "1(2(3))");
+        assert_eq!(threads[0].id, "<a@x>");
+        assert_eq!(threads[0].subject, "Cena");
+        assert!(threads[0].messages.iter().all(|m| m.thread_id.as_deref() == Some("<a@x>")));
+    }
+
+    #[test]
+    fn ignores_references_that_would_create_a_cycle() {
+        let threads = thread_messages(vec![
+            message(1, "<a@x>", "Giro", &["<b@x>"]),
+            message(2, "<b@x>", "Re: Giro", &["<a@x>"]),
+        ]);
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].messages.len(), 2);
+        assert_eq!(thread_uids(&threads[0].tree).len(), 2);
+    }
+
+    #[test]
+    fn keeps_a_dummy_container_for_a_missing_parent() {
+        let threads = thread_messages(vec![
+            message(1, "<b@x>", "Re: Assente", &["<mancante@x>"]),
+            message(2, "<c@x>", "Re: Assente", &["<mancante@x>"]),
+        ]);
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(shape(&threads[0].tree), "_(1 2)");
+        // Il thread prende l'id dal primo messaggio presente
+        assert_eq!(threads[0].id, "<b@x>");
+    }
+
+    #[test]
+    fn promotes_the_only_child_of_a_missing_parent() {
+        let threads = thread_messages(vec![message(1, "<b@x>", "Re: Assente", &["<mancante@x>"])]);
+
+        assert_eq!(shape(&threads[0].tree), "1");
+    }
+
+    #[test]
+    fn groups_messages_without_references_by_subject() {
+        let threads = thread_messages(vec![
+            message(1, "<a@x>", "Riunione di lunedì", &[]),
+            message(2, "<b@x>", "RE: riunione  di lunedì", &[]),
+            message(3, "<c@x>", "Altro argomento", &[]),
+        ]);
+
+        assert_eq!(threads.len(), 2);
+        let riunione = threads.iter().find(|t| t.id == "<a@x>").unwrap();
+        assert_eq!(shape(&riunione.tree), "1(2)");
+    }
+
+    #[test]
+    fn parses_nested_thread_responses() {
+        assert_eq!(shape(&parse_thread_response(" (1 (2)(3 4))")), "1(2 3(4))");
+        assert_eq!(shape(&parse_thread_response("(2)(3 6 (4 23)(44 7 96))")), "2 3(6(4(23) 44(7(96))))");
+        // Un gruppo senza UID iniziale è un nodo fittizio
+        assert_eq!(shape(&parse_thread_response("((3)(5))")), "_(3 5)");
+        assert!(parse_thread_response("").is_empty());
+    }
+
+    #[test]
+    fn strips_reply_and_forward_prefixes() {
+        assert_eq!(strip_subject_prefixes("Re: Fwd: Ciao"), ("Ciao", true));
+        assert_eq!(strip_subject_prefixes("R: I: Preventivo"), ("Preventivo", true));
+        assert_eq!(strip_subject_prefixes("AW: Re[2]: Termin"), ("Termin", true));
+        assert_eq!(strip_subject_prefixes("  Ordine: 42 "), ("Ordine: 42", false));
+        assert_eq!(strip_subject_prefixes("Ciao"), ("Ciao", false));
+    }
+
+    #[test]
+    fn threads_messages_from_the_server_tree() {
+        let tree = parse_thread_response("(1 (2)(3))(4)");
+        let threads = threads_from_server(
+            tree,
+            vec![
+                message(1, "<a@x>", "Uno", &[]),
+                message(2, "<b@x>", "Re: Uno", &[]),
+                message(3, "<c@x>", "Re: Uno", &[]),
+            ],
+        );
+
+        // Il thread di 4 non ha messaggi scaricati e viene scartato
+        assert_eq!(threads.len(), 1);
+        assert_eq!(shape(&threads[0].tree), "1(2 3)");
+        assert_eq!(threads[0].last_date, 180);
+    }
+}
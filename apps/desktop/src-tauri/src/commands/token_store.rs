@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+/// Servizio con cui i segreti sono registrati nel portachiavi del sistema
+const KEYRING_SERVICE: &str = "mail-client";
+
+/// File dei segreti cifrati nella cartella dati dell'app, usato quando il portachiavi
+/// non è disponibile. Oggetto JSON account → valore cifrato in base64, lo stesso formato
+/// degli store di tauri-plugin-store.
+const SECRETS_FILE: &str = "secrets.json";
+
+/// Chiave del file cifrato, nella cartella dati dell'app
+const SECRETS_KEY_FILE: &str = "secrets.key";
+
+/// Lunghezza del nonce di ChaCha20-Poly1305, salvato in testa a ogni valore cifrato
+const NONCE_LEN: usize = 12;
+
+/// Segreto di un account: password (anche per le app) o token OAuth2.
+/// Non implementa Debug per non finire nei log.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountSecret {
+    Password {
+        password: String,
+    },
+    Oauth {
+        access_token: String,
+        #[serde(default)]
+        refresh_token: Option<String>,
+        /// Scadenza dell'access token in secondi Unix
+        expires_at: i64,
+    },
+}
+
+impl AccountSecret {
+    /// Password o access token da usare per l'autenticazione
+    pub fn into_value(self) -> String {
+        match self {
+            AccountSecret::Password { password } => password,
+            AccountSecret::Oauth { access_token, .. } => access_token,
+        }
+    }
+}
+
+/// Dove vengono salvati i segreti
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    /// Portachiavi del sistema (Keychain, Credential Manager, Secret Service)
+    Keyring,
+    /// Store cifrato con ChaCha20-Poly1305 e chiave locale con permessi 0600
+    EncryptedFile,
+}
+
+/// Archivio dei segreti degli account, posseduto dal backend
+pub struct TokenStore {
+    /// Cartella dati dell'app, con il file cifrato e la sua chiave
+    dir: Result<PathBuf, String>,
+    backend: OnceLock<Backend>,
+    /// Segreti già letti, per non interrogare il portachiavi a ogni comando
+    cache: Mutex<HashMap<String, AccountSecret>>,
+    /// Serializza le modifiche al file cifrato (lettura, modifica e riscrittura)
+    file: Mutex<()>,
+}
+
+impl TokenStore {
+    pub fn new(app: &AppHandle) -> Self {
+        TokenStore {
+            dir: app
+                .path()
+                .app_data_dir()
+                .map_err(|e| format!("Cartella dati dell'app non disponibile: {}", e)),
+            backend: OnceLock::new(),
+            cache: Mutex::new(HashMap::new()),
+            file: Mutex::new(()),
+        }
+    }
+
+    pub fn load(&self, account_id: &str) -> Result<Option<AccountSecret>, String> {
+        if let Some(secret) = self.cache().get(account_id) {
+            return Ok(Some(secret.clone()));
+        }
+
+        let raw = match self.backend() {
+            Backend::Keyring => match keyring_entry(account_id)?.get_password() {
+                Ok(raw) => Some(raw),
+                Err(keyring::Error::NoEntry) => None,
+                Err(e) => return Err(format!("Errore nella lettura dal portachiavi: {}", e)),
+            },
+            Backend::EncryptedFile => self.file_get(account_id)?,
+        };
+        let Some(raw) = raw else {
+            return Ok(None);
+        };
+
+        let secret: AccountSecret =
+            serde_json::from_str(&raw).map_err(|e| format!("Credenziali salvate non valide: {}", e))?;
+        self.cache().insert(account_id.to_string(), secret.clone());
+        Ok(Some(secret))
+    }
+
+    pub fn save(&self, account_id: &str, secret: &AccountSecret) -> Result<(), String> {
+        let raw = serde_json::to_string(secret).map_err(|e| format!("Errore nella serializzazione delle credenziali: {}", e))?;
+        match self.backend() {
+            Backend::Keyring => keyring_entry(account_id)?
+                .set_password(&raw)
+                .map_err(|e| format!("Errore nella scrittura nel portachiavi: {}", e))?,
+            Backend::EncryptedFile => self.file_set(account_id, &raw)?,
+        }
+        self.cache().insert(account_id.to_string(), secret.clone());
+        Ok(())
+    }
+
+    pub fn delete(&self, account_id: &str) -> Result<(), String> {
+        self.cache().remove(account_id);
+        match self.backend() {
+            Backend::Keyring => match keyring_entry(account_id)?.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(format!("Errore nella rimozione dal portachiavi: {}", e)),
+            },
+            Backend::EncryptedFile => self.file_update(|secrets| {
+                secrets.remove(account_id);
+            }),
+        }
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, HashMap<String, AccountSecret>> {
+        // La cache contiene solo copie: anche dopo un panic il contenuto resta utilizzabile
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Il portachiavi viene verificato una sola volta, al primo accesso a un segreto
+    fn backend(&self) -> Backend {
+        *self.backend.get_or_init(|| {
+            detect_backend(keyring::Entry::new(KEYRING_SERVICE, "probe").and_then(|entry| entry.get_password()))
+        })
+    }
+
+    fn dir(&self) -> Result<&Path, String> {
+        self.dir.as_deref().map_err(|e| e.clone())
+    }
+
+    fn file_lock(&self) -> std::sync::MutexGuard<'_, ()> {
+        self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Contenuto del file cifrato; vuoto se il file non esiste ancora
+    fn read_secrets(&self) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+        match fs::read(self.dir()?.join(SECRETS_FILE)) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| format!("File delle credenziali non valido: {}", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(serde_json::Map::new()),
+            Err(e) => Err(format!("Errore nella lettura delle credenziali: {}", e)),
+        }
+    }
+
+    /// Applica `change` al file cifrato e lo riscrive sostituendo quello precedente
+    fn file_update(&self, change: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>)) -> Result<(), String> {
+        let _lock = self.file_lock();
+        let mut secrets = self.read_secrets()?;
+        change(&mut secrets);
+
+        let dir = self.dir()?;
+        fs::create_dir_all(dir).map_err(|e| format!("Errore nella creazione della cartella dati: {}", e))?;
+        let data = serde_json::to_vec_pretty(&secrets)
+            .map_err(|e| format!("Errore nella serializzazione delle credenziali: {}", e))?;
+        let temp = dir.join(format!("{}.tmp", SECRETS_FILE));
+        fs::write(&temp, data)
+            .and_then(|()| fs::rename(&temp, dir.join(SECRETS_FILE)))
+            .map_err(|e| format!("Errore nel salvataggio delle credenziali: {}", e))
+    }
+
+    fn file_get(&self, account_id: &str) -> Result<Option<String>, String> {
+        let value = {
+            let _lock = self.file_lock();
+            self.read_secrets()?.remove(account_id)
+        };
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let data = value
+            .as_str()
+            .and_then(|encoded| BASE64_STANDARD.decode(encoded).ok())
+            .filter(|data| data.len() > NONCE_LEN)
+            .ok_or_else(|| format!("Credenziali cifrate non valide per l'account {}", account_id))?;
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| format!("Impossibile decifrare le credenziali dell'account {}", account_id))?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|e| format!("Credenziali decifrate non valide: {}", e))
+    }
+
+    fn file_set(&self, account_id: &str, raw: &str) -> Result<(), String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()?
+            .encrypt(&nonce, raw.as_bytes())
+            .map_err(|_| "Errore nella cifratura delle credenziali".to_string())?;
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+
+        self.file_update(|secrets| {
+            secrets.insert(account_id.to_string(), BASE64_STANDARD.encode(data).into());
+        })
+    }
+
+    /// Chiave del file cifrato: generata al primo uso e leggibile solo dall'utente.
+    /// Protegge i segreti copiati insieme allo store (backup, sincronizzazioni),
+    /// non da altri processi dello stesso utente.
+    fn cipher(&self) -> Result<ChaCha20Poly1305, String> {
+        let dir = self.dir()?;
+        let path = dir.join(SECRETS_KEY_FILE);
+
+        let key = match fs::read(&path) {
+            Ok(key) if key.len() == 32 => key,
+            Ok(_) => return Err("Chiave delle credenziali non valida".to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                fs::create_dir_all(dir).map_err(|e| format!("Errore nella creazione della cartella dati: {}", e))?;
+                let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+                write_private(&path, &key).map_err(|e| format!("Errore nel salvataggio della chiave: {}", e))?;
+                key.to_vec()
+            }
+            Err(e) => return Err(format!("Errore nella lettura della chiave: {}", e)),
+        };
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+/// Il portachiavi è utilizzabile se la lettura di prova riesce o non trova la voce;
+/// su Linux senza Secret Service (sessioni senza GNOME Keyring o KWallet) si passa al file cifrato
+fn detect_backend(probe: keyring::Result<String>) -> Backend {
+    match probe {
+        Ok(_) | Err(keyring::Error::NoEntry) => Backend::Keyring,
+        Err(e) => {
+            println!("[TOKENS] Portachiavi non disponibile ({}), uso il file cifrato", e);
+            Backend::EncryptedFile
+        }
+    }
+}
+
+fn keyring_entry(account_id: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, account_id).map_err(|e| format!("Errore nell'accesso al portachiavi: {}", e))
+}
+
+/// Crea il file con permessi 0600 sui sistemi Unix
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Archivio con il backend indicato in una cartella temporanea vuota
+    fn store_in(name: &str, backend: Backend) -> (TokenStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("mail-client-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        (reopen(&dir, backend), dir)
+    }
+
+    /// Nuova istanza sulla stessa cartella, senza i segreti in cache
+    fn reopen(dir: &Path, backend: Backend) -> TokenStore {
+        TokenStore {
+            dir: Ok(dir.to_path_buf()),
+            backend: OnceLock::from(backend),
+            cache: Mutex::new(HashMap::new()),
+            file: Mutex::new(()),
+        }
+    }
+
+    fn oauth() -> AccountSecret {
+        AccountSecret::Oauth {
+            access_token: "ya29.access".to_string(),
+            refresh_token: Some("1//refresh".to_string()),
+            expires_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn unavailable_keyring_falls_back_to_the_encrypted_file() {
+        assert_eq!(detect_backend(Ok("segreto".to_string())), Backend::Keyring);
+        assert_eq!(detect_backend(Err(keyring::Error::NoEntry)), Backend::Keyring);
+        let unavailable = keyring::Error::PlatformFailure("Secret Service non disponibile".into());
+        assert_eq!(detect_backend(Err(unavailable)), Backend::EncryptedFile);
+    }
+
+    #[test]
+    fn encrypted_file_round_trip() {
+        let (store, dir) = store_in("round-trip", Backend::EncryptedFile);
+        assert!(store.load("gmail").unwrap().is_none());
+
+        store.save("gmail", &oauth()).unwrap();
+        store
+            .save("lavoro", &AccountSecret::Password { password: "segreto".to_string() })
+            .unwrap();
+
+        // Il file contiene solo valori cifrati, la chiave è leggibile solo dall'utente
+        let file = fs::read_to_string(dir.join(SECRETS_FILE)).unwrap();
+        assert!(file.contains("\"gmail\"") && file.contains("\"lavoro\""), "{}", file);
+        assert!(!file.contains("ya29.access") && !file.contains("segreto"), "{}", file);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.join(SECRETS_KEY_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let reopened = reopen(&dir, Backend::EncryptedFile);
+        match reopened.load("gmail").unwrap() {
+            Some(AccountSecret::Oauth { access_token, refresh_token, expires_at }) => {
+                assert_eq!(access_token, "ya29.access");
+                assert_eq!(refresh_token.as_deref(), Some("1//refresh"));
+                assert_eq!(expires_at, 1_700_000_000);
+            }
+            _ => panic!("token OAuth2 non letto"),
+        }
+        assert_eq!(reopened.load("lavoro").unwrap().map(AccountSecret::into_value).as_deref(), Some("segreto"));
+
+        reopened.delete("gmail").unwrap();
+        // La rimozione di un segreto assente non è un errore
+        reopened.delete("gmail").unwrap();
+        let reopened = reopen(&dir, Backend::EncryptedFile);
+        assert!(reopened.load("gmail").unwrap().is_none());
+        assert!(reopened.load("lavoro").unwrap().is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn secrets_cannot_be_read_with_another_key() {
+        let (store, dir) = store_in("other-key", Backend::EncryptedFile);
+        store.save("gmail", &oauth()).unwrap();
+
+        fs::write(dir.join(SECRETS_KEY_FILE), [7u8; 32]).unwrap();
+        let error = reopen(&dir, Backend::EncryptedFile).load("gmail").err().unwrap();
+        assert!(error.contains("decifrare"), "{}", error);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use base64::engine::general_purpose::STANDARD_NO_PAD as BASE64_NO_PAD;
+use base64::Engine;
+
+/// Decodifica un nome di cartella in UTF-7 modificato (RFC 3501, sezione 5.1.3).
+/// Se la codifica non è valida restituisce il nome originale.
+pub fn decode_mailbox_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut rest = name;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = match after.find('-') {
+            Some(end) => end,
+            None => return name.to_string(),
+        };
+
+        let encoded = &after[..end];
+        if encoded.is_empty() {
+            // "&-" rappresenta il carattere "&"
+            result.push('&');
+        } else {
+            match decode_utf16_run(encoded) {
+                Some(decoded) => result.push_str(&decoded),
+                None => return name.to_string(),
+            }
+        }
+        rest = &after[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Codifica un nome di cartella in UTF-7 modificato per i comandi inviati al server
+pub fn encode_mailbox_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut pending: Vec<u16> = Vec::new();
+
+    for c in name.chars() {
+        if (' '..='~').contains(&c) {
+            flush_utf16_run(&mut pending, &mut result);
+            if c == '&' {
+                result.push_str("&-");
+            } else {
+                result.push(c);
+            }
+        } else {
+            let mut buffer = [0u16; 2];
+            pending.extend_from_slice(c.encode_utf16(&mut buffer));
+        }
+    }
+
+    flush_utf16_run(&mut pending, &mut result);
+    result
+}
+
+/// Base64 modificato (',' al posto di '/', senza padding) di una sequenza UTF-16BE
+fn decode_utf16_run(encoded: &str) -> Option<String> {
+    let bytes = BASE64_NO_PAD.decode(encoded.replace(',', "/")).ok()?;
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units).ok()
+}
+
+fn flush_utf16_run(pending: &mut Vec<u16>, result: &mut String) {
+    if pending.is_empty() {
+        return;
+    }
+
+    let bytes: Vec<u8> = pending.iter().flat_map(|unit| unit.to_be_bytes()).collect();
+    result.push('&');
+    result.push_str(&BASE64_NO_PAD.encode(bytes).replace('/', ","));
+    result.push('-');
+    pending.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_non_ascii_names() {
+        assert_eq!(encode_mailbox_name("Posta inviata/Répertoire"), "Posta inviata/R&AOk-pertoire");
+        assert_eq!(encode_mailbox_name("日本語"), "&ZeVnLIqe-");
+        assert_eq!(encode_mailbox_name("~peter/mail/台北/日本語"), "~peter/mail/&U,BTFw-/&ZeVnLIqe-");
+    }
+
+    #[test]
+    fn escapes_ampersand() {
+        assert_eq!(encode_mailbox_name("Tom & Jerry"), "Tom &- Jerry");
+        assert_eq!(decode_mailbox_name("Tom &- Jerry"), "Tom & Jerry");
+        assert_eq!(encode_mailbox_name("&Répertoire&"), "&-R&AOk-pertoire&-");
+    }
+
+    #[test]
+    fn round_trips_mailbox_names() {
+        for name in [
+            "INBOX",
+            "Posta inviata/Répertoire",
+            "日本語",
+            "Fatture & ricevute/2024",
+            "&",
+            "Entwürfe & Vorlagen/日本語 & ÀÈ",
+            "Emoji 📬",
+        ] {
+            let encoded = encode_mailbox_name(name);
+            assert!(encoded.is_ascii(), "{} -> {}", name, encoded);
+            assert_eq!(decode_mailbox_name(&encoded), name);
+        }
+    }
+
+    #[test]
+    fn keeps_invalid_encodings_unchanged() {
+        // Sequenza non terminata e base64 non valido
+        assert_eq!(decode_mailbox_name("Cartella &AOk"), "Cartella &AOk");
+        assert_eq!(decode_mailbox_name("Cartella &*!-"), "Cartella &*!-");
+    }
+}
@@ -3,7 +3,18 @@
 
 mod commands;
 
-use commands::imap::{sync_folders, sync_messages, mark_message_read, move_message, delete_message};
+use tauri::Manager;
+
+use commands::account::{list_accounts, remove_account, save_account, AccountManager};
+use commands::attachments::{fetch_attachment, save_attachment};
+use commands::discovery::discover_account_settings;
+use commands::idle::{start_idle, stop_idle, IdleManager};
+use commands::imap::{
+    sync_folders, create_folder, rename_folder, delete_folder, subscribe_folder, unsubscribe_folder,
+    sync_messages, get_threads, fetch_message_body, mark_message_read, set_flags, move_message, delete_message, purge_message, empty_trash,
+};
+use commands::oauth::{import_oauth_account, oauth_login};
+use commands::pool::ImapPool;
 use commands::smtp::send_email;
 use commands::system::open_url_in_browser;
 
@@ -15,21 +26,50 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .invoke_handler(tauri::generate_handler![
+            save_account,
+            list_accounts,
+            remove_account,
+            oauth_login,
+            import_oauth_account,
+            discover_account_settings,
             sync_folders,
+            create_folder,
+            rename_folder,
+            delete_folder,
+            subscribe_folder,
+            unsubscribe_folder,
             sync_messages,
+            get_threads,
+            fetch_message_body,
+            fetch_attachment,
+            save_attachment,
             mark_message_read,
+            set_flags,
             move_message,
             delete_message,
+            purge_message,
+            empty_trash,
+            start_idle,
+            stop_idle,
             send_email,
             open_url_in_browser,
         ])
-        .setup(|_app| {
+        .setup(|app| {
             // La trasparenza e il blur sono gestiti da:
             // 1. transparent: true in tauri.conf.json per la trasparenza base
             // 2. backdrop-filter: blur() in CSS per l'effetto blur
             // 3. decorations: true e titleBarStyle: "Overlay" per bordi arrotondati e titlebar trasparente
             
             // Inizializza il database e altre configurazioni all'avvio
+            
+            // Account e credenziali gestiti dal backend
+            app.manage(AccountManager::new(app.handle().clone()));
+            
+            // Pool di sessioni IMAP condiviso dai comandi
+            app.manage(ImapPool::default());
+            
+            // Task IMAP IDLE in background per le notifiche push
+            app.manage(IdleManager::default());
             Ok(())
         })
         .run(tauri::generate_context!())
@@ -3,7 +3,6 @@ import ReactDOM from 'react-dom/client';
 import { QueryClient, QueryClientProvider } from '@tanstack/react-query';
 import { BrowserRouter, Routes, Route } from 'react-router-dom';
 import App from './ui/App';
-import { OAuthCallback } from './ui/pages/OAuthCallback';
 import './styles/index.css';
 
 const queryClient = new QueryClient({
@@ -20,7 +19,6 @@ ReactDOM.createRoot(document.getElementById('root')!).render(
     <QueryClientProvider client={queryClient}>
       <BrowserRouter>
         <Routes>
-          <Route path="/oauth/callback" element={<OAuthCallback />} />
           <Route path="*" element={<App />} />
         </Routes>
       </BrowserRouter>
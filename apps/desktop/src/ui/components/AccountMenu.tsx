@@ -9,9 +9,8 @@ import { useMailStore } from '../store/useMailStore';
 import { useAccounts, useAddAccount, useRemoveAccount } from '../hooks/useAccounts';
 import { Avatar } from '@mail-client/ui-kit';
 import { cn } from '@mail-client/ui-kit';
-import { getOAuthUrl, exchangeOAuthCode, getUserInfo, getOAuthRedirectUri, accountStorage } from '@mail-client/core';
+import { oauthLoginTauri, accountStorage } from '@mail-client/core';
 import type { AccountProvider } from '@mail-client/core';
-import { OAuthUrlInputModal } from './OAuthUrlInputModal';
 
 export const AccountMenu: React.FC = () => {
   const { accounts, currentAccountId, setCurrentAccount, setIsLoggingOut } = useMailStore();
@@ -21,8 +20,6 @@ export const AccountMenu: React.FC = () => {
   
   const [isAddingAccount, setIsAddingAccount] = useState(false);
   const [showProviderSelect, setShowProviderSelect] = useState(false);
-  const [showOAuthModal, setShowOAuthModal] = useState(false);
-  const [currentProvider, setCurrentProvider] = useState<AccountProvider | null>(null);
 
   // Helper per mostrare dialog/alert
   const showDialog = async (message: string, title: string = 'Info', kind: 'info' | 'warning' | 'error' = 'info') => {
@@ -44,246 +41,29 @@ export const AccountMenu: React.FC = () => {
   const handleAddAccount = async (provider: AccountProvider) => {
     try {
       console.log('[AccountMenu] Aggiunta account iniziata per provider:', provider);
-      console.log('[AccountMenu] Window object:', typeof window !== 'undefined' ? 'available' : 'not available');
-      console.log('[AccountMenu] __TAURI__:', typeof window !== 'undefined' ? (window as any).__TAURI__ : 'N/A');
-      console.log('[AccountMenu] __TAURI_INTERNALS__:', typeof window !== 'undefined' ? (window as any).__TAURI_INTERNALS__ : 'N/A');
-      
       setIsAddingAccount(true);
       setShowProviderSelect(false);
 
-      // Verifica se siamo in Tauri
       const isTauri = typeof window !== 'undefined' && 
                       ((window as any).__TAURI__ !== undefined || 
                        (window as any).__TAURI_INTERNALS__ !== undefined);
-
-      console.log('[AccountMenu] Is Tauri:', isTauri);
-
-      if (isTauri) {
-        // In Tauri, usa WebviewWindow per aprire OAuth dentro l'app e intercettare automaticamente il redirect
-        console.log('[AccountMenu] Tauri rilevato, uso WebviewWindow per OAuth');
-        const authUrl = getOAuthUrl(provider);
-        console.log('[AccountMenu] URL di autorizzazione:', authUrl);
-        
-        try {
-          // Prova a usare WebviewWindow per aprire OAuth in una finestra Tauri
-          const windowModule = await new Function('return import("@tauri-apps/api/window")')();
-          const { WebviewWindow } = windowModule;
-          
-          console.log('[AccountMenu] Creo WebviewWindow per OAuth');
-          const oauthWindow = new WebviewWindow('oauth', {
-            url: authUrl,
-            title: `${provider === 'gmail' ? 'Google' : 'Microsoft'} Login`,
-            width: 600,
-            height: 700,
-            visible: true,
-            focus: true,
-            center: true,
-            resizable: false,
-            decorations: true,
-          });
-          
-          // Aspetta che la finestra sia creata
-          await oauthWindow.once('tauri://created');
-          console.log('[AccountMenu] WebviewWindow creata');
-          
-          // Intercetta la navigazione per catturare il redirect
-          // In Tauri v2, possiamo usare eventi di navigazione
-          return new Promise<void>((resolve, reject) => {
-            let hasReceivedCode = false;
-            const redirectUri = 'http://localhost:1420/oauth/callback';
-            
-            console.log('[AccountMenu] Aspetto redirect a:', redirectUri);
-            
-            // Listener per intercettare il redirect usando eventi di navigazione
-            const navigationHandler = async (url: string) => {
-              console.log('[AccountMenu] Navigazione rilevata:', url);
-              
-              if (url && url.includes('/oauth/callback')) {
-                console.log('[AccountMenu] Redirect intercettato!');
-                hasReceivedCode = true;
-                
-                // Estrai il codice dall'URL
-                try {
-                  const urlObj = new URL(url);
-                  const code = urlObj.searchParams.get('code');
-                  const error = urlObj.searchParams.get('error');
-                  
-                  if (error) {
-                    oauthWindow.close();
-                    reject(new Error(`Errore OAuth: ${error}`));
-                    return;
-                  }
-                  
-                  if (!code) {
-                    oauthWindow.close();
-                    reject(new Error('Codice di autorizzazione non trovato nell\'URL'));
-                    return;
-                  }
-                  
-                  console.log('[AccountMenu] Codice estratto, chiudo finestra e completo login');
-                  oauthWindow.close();
-                  
-                  // Completa il setup dell'account
-                  await completeAccountSetup(provider, code);
-                  resolve();
-                } catch (err) {
-                  oauthWindow.close();
-                  reject(new Error(`Errore nel parsing dell'URL: ${err instanceof Error ? err.message : 'Errore sconosciuto'}`));
-                }
-              }
-            };
-            
-            // Usa l'evento di navigazione della webview
-            oauthWindow.listen('tauri://navigation', (event: any) => {
-              if (event.payload && event.payload.url) {
-                navigationHandler(event.payload.url);
-              }
-            });
-            
-            // Fallback: controlla periodicamente l'URL corrente
-            const checkInterval = setInterval(async () => {
-              try {
-                const currentUrl = await oauthWindow.url();
-                if (currentUrl) {
-                  await navigationHandler(currentUrl);
-                }
-              } catch (err) {
-                // Ignora errori di accesso all'URL
-              }
-            }, 500);
-            
-            // Timeout dopo 5 minuti
-            setTimeout(() => {
-              if (!hasReceivedCode) {
-                clearInterval(checkInterval);
-                oauthWindow.close();
-                reject(new Error('Timeout durante l\'autorizzazione OAuth'));
-              }
-            }, 5 * 60 * 1000);
-            
-            // Listener per quando la finestra viene chiusa manualmente
-            oauthWindow.once('tauri://close-requested', () => {
-              if (!hasReceivedCode) {
-                clearInterval(checkInterval);
-                reject(new Error('Finestra OAuth chiusa dall\'utente'));
-              }
-            });
-          });
-        } catch (windowError) {
-          console.warn('[AccountMenu] WebviewWindow non disponibile, uso browser esterno:', windowError);
-          
-          // Fallback: usa browser esterno e chiedi all'utente di incollare l'URL
-          // NOTA: Questo è necessario perché il redirect URI funziona solo dentro l'app Tauri
-          try {
-            console.log('[AccountMenu] Provo con comando Rust personalizzato...');
-            const { invoke } = await import('@tauri-apps/api/core');
-            await invoke('open_url_in_browser', { url: authUrl });
-            console.log('[AccountMenu] Browser aperto con comando Rust personalizzato');
-            
-            // Aspetta un momento per assicurarsi che il browser si apra
-            await new Promise(resolve => setTimeout(resolve, 1000));
-            
-            // Mostra il modal per inserire l'URL
-            // IMPORTANTE: Quando usi il browser esterno, Google reindirizzerà a localhost:1420
-            // che non esiste nel browser esterno. Devi copiare l'URL COMPLETO dalla barra degli indirizzi
-            // PRIMA che Google tenti il redirect, oppure usare un redirect URI diverso.
-            console.log('[AccountMenu] Mostro modal OAuth');
-            setCurrentProvider(provider);
-            setShowOAuthModal(true);
-          } catch (rustError) {
-            console.error('[AccountMenu] Errore nell\'apertura del browser:', rustError);
-            throw new Error(`Errore nell'apertura del browser: ${rustError instanceof Error ? rustError.message : 'Errore sconosciuto'}`);
-          }
-        }
-      } else {
-        // In browser, usa il flusso normale con window.open
-        console.log('[AccountMenu] Browser normale, uso window.open');
-        const authUrl = getOAuthUrl(provider);
-        const oauthWindow = window.open(authUrl, 'oauth', 'width=600,height=700');
-        
-        if (!oauthWindow) {
-          throw new Error('Impossibile aprire la finestra OAuth. Verifica che i popup non siano bloccati.');
-        }
-
-        // Aspetta il codice dalla finestra OAuth
-        const code = await new Promise<string>((resolve, reject) => {
-          const handleMessage = (event: MessageEvent) => {
-            if (event.data && event.data.type === 'oauth-code') {
-              window.removeEventListener('message', handleMessage);
-              resolve(event.data.code);
-            } else if (event.data && event.data.type === 'oauth-error') {
-              window.removeEventListener('message', handleMessage);
-              reject(new Error(`Errore OAuth: ${event.data.error}`));
-            }
-          };
-          window.addEventListener('message', handleMessage);
-        });
-
-        await completeAccountSetup(provider, code);
+      if (!isTauri) {
+        throw new Error('Il login OAuth2 è disponibile solo nell\'app desktop');
       }
-    } catch (error) {
-      console.error('[AccountMenu] Errore durante l\'aggiunta dell\'account:', error);
-      await showDialog(`Errore durante l'aggiunta dell'account: ${error instanceof Error ? error.message : 'Errore sconosciuto'}`, 'Errore', 'error');
-      setIsAddingAccount(false);
-    }
-  };
 
-  const handleOAuthUrlSubmit = async (url: string) => {
-    if (!currentProvider) return;
+      // Il backend apre il browser di sistema, riceve il redirect su loopback,
+      // verifica le credenziali con il server IMAP e registra l'account
+      const account = await oauthLoginTauri(provider);
+      console.log('[AccountMenu] Login completato:', { id: account.id, email: account.email });
 
-    try {
-      console.log('[AccountMenu] URL OAuth ricevuto:', url);
-      
-      // Estrai il codice dall'URL
-      const parsedUrl = new URL(url);
-      const code = parsedUrl.searchParams.get('code');
-      
-      if (!code) {
-        throw new Error('Codice non trovato nell\'URL fornito');
-      }
-
-      setShowOAuthModal(false);
-      await completeAccountSetup(currentProvider, code);
-    } catch (error) {
-      console.error('[AccountMenu] Errore nel parsing dell\'URL:', error);
-      await showDialog(`Errore: ${error instanceof Error ? error.message : 'Errore sconosciuto'}`, 'Errore', 'error');
-    }
-  };
-
-  const completeAccountSetup = async (provider: AccountProvider, code: string) => {
-    try {
-      console.log('[AccountMenu] Scambio codice OAuth...');
-      // Scambia il codice con i token
-      const tokens = await exchangeOAuthCode(provider, code);
-      console.log('[AccountMenu] Token OAuth ottenuti:', { hasAccessToken: !!tokens.accessToken, hasRefreshToken: !!tokens.refreshToken });
-      
-      console.log('[AccountMenu] Ottengo informazioni utente...');
-      // Ottieni le informazioni dell'utente
-      const userInfo = await getUserInfo(provider, tokens.accessToken);
-      console.log('[AccountMenu] Informazioni utente ottenute:', { email: userInfo.email, name: userInfo.name });
-
-      // Crea l'account
-      const account = {
-        id: `${provider}-${userInfo.email}`,
-        email: userInfo.email,
-        provider,
-        displayName: userInfo.name,
-        tokens,
-        createdAt: Date.now(),
-        updatedAt: Date.now(),
-      };
-
-      console.log('[AccountMenu] Salvataggio account...');
-      // Salva l'account
       await addAccountMutation.mutateAsync(account);
       console.log('[AccountMenu] Account salvato con successo');
       
       // Seleziona automaticamente il nuovo account
       setCurrentAccount(account.id);
-      setCurrentProvider(null);
     } catch (error) {
-      console.error('[AccountMenu] Errore durante il completamento dell\'account:', error);
-      throw error;
+      console.error('[AccountMenu] Errore durante l\'aggiunta dell\'account:', error);
+      await showDialog(`Errore durante l'aggiunta dell'account: ${error instanceof Error ? error.message : 'Errore sconosciuto'}`, 'Errore', 'error');
     } finally {
       setIsAddingAccount(false);
     }
@@ -557,20 +337,6 @@ export const AccountMenu: React.FC = () => {
         </DropdownMenu.Content>
       </DropdownMenu.Portal>
     </DropdownMenu.Root>
-
-    {/* Modal per inserire l'URL OAuth */}
-    {currentProvider && (
-      <OAuthUrlInputModal
-        open={showOAuthModal}
-        onClose={() => {
-          setShowOAuthModal(false);
-          setCurrentProvider(null);
-          setIsAddingAccount(false);
-        }}
-        onSubmit={handleOAuthUrlSubmit}
-        provider={currentProvider}
-      />
-    )}
     </div>
   );
 };
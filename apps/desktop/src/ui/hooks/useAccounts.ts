@@ -3,9 +3,14 @@
  */
 
 import { useQuery, useMutation, useQueryClient } from '@tanstack/react-query';
-import { accountStorage } from '@mail-client/core';
+import { accountStorage, registerMissingAccountsTauri, removeAccountTauri } from '@mail-client/core';
 import { useMailStore } from '../store/useMailStore';
 
+// Account e credenziali vanno registrati nel backend solo quando l'app gira in Tauri
+const isTauri = () =>
+  typeof window !== 'undefined' &&
+  ((window as any).__TAURI__ !== undefined || (window as any).__TAURI_INTERNALS__ !== undefined);
+
 export const useAccounts = () => {
   const { setAccounts, setCurrentAccount, currentAccountId } = useMailStore();
   
@@ -16,6 +21,15 @@ export const useAccounts = () => {
       const accounts = await accountStorage.getAll();
       console.log('[useAccounts] Account caricati dal database:', accounts.length, accounts.map(a => ({ id: a.id, email: a.email })));
       
+      // I comandi Rust leggono configurazione e credenziali dal backend: registra gli account mancanti
+      if (isTauri()) {
+        try {
+          await registerMissingAccountsTauri(accounts);
+        } catch (error) {
+          console.error('[useAccounts] Errore nella registrazione degli account nel backend:', error);
+        }
+      }
+      
       const currentState = useMailStore.getState();
       console.log('[useAccounts] Stato corrente:', {
         accountsInStore: currentState.accounts.length,
@@ -63,6 +77,7 @@ export const useAddAccount = () => {
   const { addAccount } = useMailStore();
   
   return useMutation({
+    // L'account è già registrato nel backend da oauth_login
     mutationFn: async (account: Parameters<typeof accountStorage.save>[0]) => {
       await accountStorage.save(account);
       addAccount(account);
@@ -82,6 +97,11 @@ export const useRemoveAccount = () => {
     mutationFn: async (id: string) => {
       console.log('[useRemoveAccount] Rimozione account:', id);
       
+      // Rimuovi credenziali e configurazione dal backend
+      if (isTauri()) {
+        await removeAccountTauri(id);
+      }
+      
       // Rimuovi dal database PRIMA di aggiornare lo store
       await accountStorage.delete(id);
       
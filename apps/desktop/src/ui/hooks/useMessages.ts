@@ -4,6 +4,7 @@
 
 import { useQuery, useMutation, useQueryClient } from '@tanstack/react-query';
 import { messageStorage, syncMessages, syncFolders, markMessageReadTauri, deleteMessageTauri, moveMessageTauri } from '@mail-client/core';
+import type { UidResult } from '@mail-client/core';
 import { useMailStore } from '../store/useMailStore';
 
 export const useMessages = (folderId: string | null) => {
@@ -85,7 +86,8 @@ export const useMessages = (folderId: string | null) => {
           if (account && folder) {
             console.log('[useMessages] Nessun messaggio trovato, sincronizzazione...');
             try {
-              const syncedMessages = await syncMessages(account, folder.path);
+              // syncMessages salva i messaggi e lo stato della cartella nello storage locale
+              await syncMessages(account, folder);
               
               // Verifica di nuovo che l'account sia ancora valido dopo la sincronizzazione
               const stateAfterSync = useMailStore.getState();
@@ -94,22 +96,7 @@ export const useMessages = (folderId: string | null) => {
                 return [];
               }
               
-              // Aggiorna i messaggi con il folderId corretto
-              const messagesWithFolderId = syncedMessages.map(msg => ({
-                ...msg,
-                folderId: realFolderId,
-              }));
-              
-              // Salva i messaggi sincronizzati
-              for (const message of messagesWithFolderId) {
-                try {
-                  await messageStorage.save(message);
-                } catch (saveError) {
-                  console.error('[useMessages] Errore nel salvataggio del messaggio:', saveError);
-                }
-              }
-              
-              messages = messagesWithFolderId;
+              messages = await messageStorage.getByFolder(realFolderId);
               console.log('[useMessages] Messaggi sincronizzati:', messages.length);
             } catch (syncError) {
               console.error('[useMessages] Errore nella sincronizzazione:', syncError);
@@ -200,7 +187,8 @@ export const useMessages = (folderId: string | null) => {
             }
             
             try {
-              const syncedMessages = await syncMessages(account, targetFolder.path);
+              // syncMessages salva i messaggi e lo stato della cartella nello storage locale
+              await syncMessages(account, targetFolder);
               
               // Verifica di nuovo che l'account sia ancora valido dopo la sincronizzazione
               const stateAfterSync = useMailStore.getState();
@@ -209,22 +197,7 @@ export const useMessages = (folderId: string | null) => {
                 return [];
               }
               
-              // Aggiorna i messaggi con il folderId corretto
-              const messagesWithFolderId = syncedMessages.map(msg => ({
-                ...msg,
-                folderId: targetFolder.id,
-              }));
-              
-              // Salva i messaggi sincronizzati
-              for (const message of messagesWithFolderId) {
-                try {
-                  await messageStorage.save(message);
-                } catch (saveError) {
-                  console.error('[useMessages] Errore nel salvataggio del messaggio:', saveError);
-                }
-              }
-              
-              folderMessages = messagesWithFolderId;
+              folderMessages = await messageStorage.getByFolder(targetFolder.id);
               console.log('[useMessages] Messaggi sincronizzati per', targetFolder.name, ':', folderMessages.length);
             } catch (syncError) {
               console.error('[useMessages] Errore nella sincronizzazione dei messaggi:', syncError);
@@ -333,19 +306,9 @@ export const useSyncMessages = () => {
       
       for (const folder of syncedFolders) {
         console.log('[useSyncMessages] Sincronizzazione cartella:', folder.name);
-        const messages = await syncMessages(account, folder.path);
-        
-        // Aggiorna i messaggi con il folderId corretto
-        const messagesWithFolderId = messages.map(msg => ({
-          ...msg,
-          folderId: folder.id,
-        }));
-        
-        // Salva i messaggi nello storage
-        for (const message of messagesWithFolderId) {
-          await messageStorage.save(message);
-          allMessages.push(message);
-        }
+        // Solo le differenze rispetto all'ultima sync: nuovi messaggi, flag modificati e rimossi
+        const messages = await syncMessages(account, folder);
+        allMessages.push(...messages);
         
         // Il conteggio viene aggiornato automaticamente quando salviamo i messaggi
       }
@@ -389,14 +352,20 @@ export const useMarkAsRead = () => {
       }
 
       // Aggiorna sul server IMAP
+      let result: UidResult | undefined;
       try {
         if (typeof window !== 'undefined' && (window as any).__TAURI__) {
-          await markMessageReadTauri(account, folder.path, message.uid, read);
+          [result] = await markMessageReadTauri(account, folder.path, [message.uid], read);
         }
       } catch (error) {
         console.error('[useMarkAsRead] Errore nella sincronizzazione IMAP:', error);
         // Continua comunque con l'aggiornamento locale
       }
+      
+      // Il server ha risposto ma ha rifiutato questo UID (es. messaggio non più presente)
+      if (result && !result.success) {
+        throw new Error(result.error ?? 'Aggiornamento del messaggio non riuscito');
+      }
 
       // Aggiorna nel database locale
       await messageStorage.markAsRead(id, read);
@@ -434,14 +403,21 @@ export const useDeleteMessage = () => {
       }
 
       // Elimina sul server IMAP
+      let result: UidResult | undefined;
       try {
         if (typeof window !== 'undefined' && (window as any).__TAURI__) {
-          await deleteMessageTauri(account, folder.path, message.uid);
+          [result] = await deleteMessageTauri(account, folder.path, [message.uid]);
         }
       } catch (error) {
         console.error('[useDeleteMessage] Errore nella sincronizzazione IMAP:', error);
         // Continua comunque con l'eliminazione locale
       }
+      
+      // Se il messaggio non è arrivato nel Cestino resta nella lista; se è stato copiato
+      // ma non rimosso dalla sorgente non va ripetuto, la prossima sync lo riallinea
+      if (result && !result.success && !result.copied) {
+        throw new Error(result.error ?? 'Eliminazione del messaggio non riuscita');
+      }
 
       // Elimina dal database locale
       await messageStorage.delete(id);
@@ -484,18 +460,31 @@ export const useMoveMessage = () => {
       }
 
       // Sposta sul server IMAP
+      let result: UidResult | undefined;
       try {
         if (typeof window !== 'undefined' && (window as any).__TAURI__) {
-          await moveMessageTauri(account, folder.path, message.uid, targetFolder.path);
+          const moved = await moveMessageTauri(account, folder.path, [message.uid], targetFolder.path);
+          result = moved.results.find((r) => r.uid === message.uid);
         }
       } catch (error) {
         console.error('[useMoveMessage] Errore nella sincronizzazione IMAP:', error);
         throw error;
       }
+      
+      if (result && !result.success) {
+        if (result.copied) {
+          // La copia nella destinazione esiste già: ripetere lo spostamento creerebbe un duplicato
+          throw new Error(`Messaggio copiato in ${targetFolder.name} ma non rimosso dalla cartella originale: ${result.error ?? 'errore sconosciuto'}`);
+        }
+        throw new Error(result.error ?? 'Spostamento del messaggio non riuscito');
+      }
 
-      // Aggiorna nel database locale
-      await messageStorage.update(id, { folderId: targetFolderId });
-      updateMessage(id, { folderId: targetFolderId });
+      // Aggiorna nel database locale, con l'UID assegnato dalla cartella di destinazione
+      const updates = result?.targetUid !== undefined
+        ? { folderId: targetFolderId, uid: result.targetUid }
+        : { folderId: targetFolderId };
+      await messageStorage.update(id, updates);
+      updateMessage(id, updates);
     },
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: ['messages'] });
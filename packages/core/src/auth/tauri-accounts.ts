@@ -0,0 +1,99 @@
+/**
+ * Wrapper TypeScript per i comandi Tauri di gestione account.
+ * Il backend conserva configurazione e credenziali: gli altri comandi ricevono solo l'accountId.
+ */
+
+import { invoke } from '@tauri-apps/api/core';
+import type { Account } from '../types';
+import { toSessionCommandError } from '../imap/tauri-imap';
+
+interface ServerConfig {
+  host: string;
+  port: number;
+  security: 'tls' | 'starttls' | 'plain';
+}
+
+/**
+ * Configurazione dell'account così come serializzata dal comando Rust (AccountConfig)
+ */
+export interface AccountConfig {
+  email: string;
+  username: string | null;
+  imap: ServerConfig;
+  smtp: ServerConfig;
+  auth: 'xoauth2' | 'oauthbearer' | 'plain' | 'login';
+}
+
+/**
+ * Parametri usati dal backend per rinnovare l'access token (OAuthSettings)
+ */
+export interface OAuthSettings {
+  token_url: string;
+  client_id: string;
+  client_secret: string | null;
+}
+
+/**
+ * Account registrato nel backend (StoredAccount)
+ */
+export interface StoredAccount {
+  id: string;
+  config: AccountConfig;
+  oauth: OAuthSettings | null;
+}
+
+/**
+ * Registra nel backend un account OAuth2 salvato in locale con i suoi token.
+ * Server ed endpoint OAuth2 vengono dal backend in base al provider.
+ */
+export const importOAuthAccountTauri = async (account: Account): Promise<void> => {
+  try {
+    await invoke('import_oauth_account', {
+      provider: account.provider,
+      email: account.email,
+      secret: {
+        type: 'oauth',
+        access_token: account.tokens.accessToken,
+        refresh_token: account.tokens.refreshToken || null,
+        // Il backend usa i secondi Unix
+        expires_at: Math.floor(account.tokens.expiresAt / 1000),
+      },
+    });
+  } catch (error) {
+    console.error('[Accounts Tauri] Errore nella registrazione dell\'account:', error);
+    throw toSessionCommandError(error);
+  }
+};
+
+/**
+ * Elenca gli account registrati nel backend
+ */
+export const listAccountsTauri = async (): Promise<StoredAccount[]> => {
+  return invoke<StoredAccount[]>('list_accounts');
+};
+
+/**
+ * Rimuove l'account e le sue credenziali dal backend
+ */
+export const removeAccountTauri = async (accountId: string): Promise<void> => {
+  await invoke('remove_account', { accountId });
+};
+
+/**
+ * Registra nel backend gli account salvati in locale che non vi risultano ancora
+ * (aggiunti prima che login e credenziali passassero dal backend).
+ * Un account che non è possibile registrare non blocca gli altri.
+ */
+export const registerMissingAccountsTauri = async (accounts: Account[]): Promise<void> => {
+  const registered = new Set((await listAccountsTauri()).map((account) => account.id));
+
+  // Gli account aggiunti con oauth_login non hanno token in locale: sono già nel backend
+  for (const account of accounts.filter((a) => !registered.has(a.id) && a.tokens.accessToken)) {
+    try {
+      console.log('[Accounts Tauri] Registrazione account nel backend:', account.id);
+      await importOAuthAccountTauri(account);
+    } catch (error) {
+      console.error('[Accounts Tauri] Impossibile registrare l\'account:', account.id, error);
+    }
+  }
+};
@@ -4,7 +4,8 @@
  * Questa logica dovrebbe essere spostata in comandi Rust di Tauri.
  */
 
-import type { Account, MailMessage, MailFolder } from '../types';
+import type { Account, MailMessage, MailFolder, SyncDelta } from '../types';
+import { messageStorage, syncStateStorage } from '../storage/storage';
 
 export interface ImapConfig {
   host: string;
@@ -170,60 +171,110 @@ export const syncFolders = async (account: Account): Promise<MailFolder[]> => {
 };
 
 /**
- * Sincronizza i messaggi di una cartella
- * Usa i comandi Tauri se disponibili, altrimenti fallback a mock
+ * Applica allo storage locale le differenze restituite da sync_messages
+ * e salva il nuovo stato della cartella solo a operazione completata
+ */
+const applySyncDelta = async (folder: MailFolder, delta: SyncDelta): Promise<MailMessage[]> => {
+  if (delta.fullResync) {
+    await messageStorage.deleteByFolder(folder.id);
+  } else {
+    const index = await messageStorage.getUidIndex(folder.id);
+    for (const uid of delta.vanished) {
+      const id = index.get(uid);
+      if (id) {
+        await messageStorage.delete(id);
+      }
+    }
+    for (const update of delta.changedFlags) {
+      const id = index.get(update.uid);
+      if (id) {
+        await messageStorage.update(id, {
+          flags: update.flags,
+          isRead: update.isRead,
+          isStarred: update.isStarred,
+          isImportant: update.isImportant,
+        });
+      }
+    }
+  }
+  
+  const messages = delta.newMessages.map((msg) => ({ ...msg, folderId: folder.id }));
+  for (const message of messages) {
+    await messageStorage.save(message);
+  }
+  
+  await syncStateStorage.save(folder.id, delta.state);
+  return messages;
+};
+
+/**
+ * Sincronizza i messaggi di una cartella e li salva nello storage locale.
+ * Usa i comandi Tauri se disponibili (sincronizzazione incrementale a partire
+ * dallo stato salvato), altrimenti fallback a mock.
+ * Restituisce i nuovi messaggi, già associati alla cartella.
  */
 export const syncMessages = async (
   account: Account,
-  folderPath: string,
+  folder: MailFolder,
   since?: Date
 ): Promise<MailMessage[]> => {
-  // Prova a usare i comandi Tauri se disponibili
+  const folderPath = folder.path;
+  
+  // Controlla se siamo in ambiente Tauri - verifica che invoke sia disponibile e funzionante
+  let isTauri = false;
   try {
-    // Controlla se siamo in ambiente Tauri - verifica che invoke sia disponibile e funzionante
-    let isTauri = false;
-    try {
-      // Prova a importare invoke direttamente
-      const { invoke } = await import('@tauri-apps/api/core');
-      if (invoke && typeof invoke === 'function') {
-        // Verifica che window.__TAURI__ esista (necessario per Tauri)
-        if (typeof window !== 'undefined' && (window as any).__TAURI__) {
-          isTauri = true;
-        }
+    // Prova a importare invoke direttamente
+    const { invoke } = await import('@tauri-apps/api/core');
+    if (invoke && typeof invoke === 'function') {
+      // Verifica che window.__TAURI__ esista (necessario per Tauri)
+      if (typeof window !== 'undefined' && (window as any).__TAURI__) {
+        isTauri = true;
       }
-    } catch {
-      // Se l'import fallisce, non siamo in Tauri
-      isTauri = false;
-    }
-    
-    // Fallback: controlla anche window.__TAURI__
-    if (!isTauri && typeof window !== 'undefined') {
-      isTauri = (window as any).__TAURI__ !== undefined || 
-                (window as any).__TAURI_INTERNALS__ !== undefined;
-    }
-    
-    console.log('[IMAP] Controllo Tauri per syncMessages:', { 
-      isTauri, 
-      hasWindow: typeof window !== 'undefined',
-      hasTAURI: typeof window !== 'undefined' ? (window as any).__TAURI__ !== undefined : false,
-    });
-    
-    if (isTauri) {
-      console.log('[IMAP] Tauri disponibile, uso comandi Rust per sincronizzare messaggi');
-      const { syncMessagesTauri } = await import('./tauri-imap');
-      const messages = await syncMessagesTauri(account, folderPath, since);
-      console.log('[IMAP] Messaggi sincronizzati da Rust:', messages.length);
-      return messages;
-    } else {
-      console.warn('[IMAP] Tauri non disponibile, uso mock. Assicurati di usare "pnpm tauri:dev" invece di "pnpm dev"');
     }
-  } catch (error) {
-    console.error('[IMAP] Errore nei comandi Tauri, uso mock:', error);
+  } catch {
+    // Se l'import fallisce, non siamo in Tauri
+    isTauri = false;
   }
   
+  // Fallback: controlla anche window.__TAURI__
+  if (!isTauri && typeof window !== 'undefined') {
+    isTauri = (window as any).__TAURI__ !== undefined || 
+              (window as any).__TAURI_INTERNALS__ !== undefined;
+  }
+  
+  console.log('[IMAP] Controllo Tauri per syncMessages:', { 
+    isTauri, 
+    hasWindow: typeof window !== 'undefined',
+    hasTAURI: typeof window !== 'undefined' ? (window as any).__TAURI__ !== undefined : false,
+  });
+  
+  if (isTauri) {
+    // In Tauri gli errori vanno propagati: con i mock lo stato salvato non avanzerebbe
+    // ma l'utente vedrebbe messaggi finti al posto dell'errore
+    console.log('[IMAP] Tauri disponibile, uso comandi Rust per sincronizzare messaggi');
+    const { syncMessagesTauri } = await import('./tauri-imap');
+    const state = await syncStateStorage.get(folder.id);
+    const knownUids = state ? [...(await messageStorage.getUidIndex(folder.id)).keys()] : undefined;
+    const delta = await syncMessagesTauri(account, folder, { since, state, knownUids });
+    const messages = await applySyncDelta(folder, delta);
+    console.log('[IMAP] Messaggi sincronizzati da Rust:', messages.length);
+    return messages;
+  }
+  console.warn('[IMAP] Tauri non disponibile, uso mock. Assicurati di usare "pnpm tauri:dev" invece di "pnpm dev"');
+  
   // Fallback a mock per sviluppo/test
   console.log('[IMAP Mock] Sincronizzazione messaggi per cartella:', folderPath, 'account:', account.email);
-  
+  const messages = mockMessagesForFolder(account, folderPath).map((msg) => ({ ...msg, folderId: folder.id }));
+  for (const message of messages) {
+    await messageStorage.save(message);
+  }
+  return messages;
+};
+
+/**
+ * Messaggi mock per testare l'UI senza Tauri
+ */
+const mockMessagesForFolder = (account: Account, folderPath: string): MailMessage[] => {
   // Messaggi mock per testare l'UI
   // Determina il folderId corretto in base al folderPath
   let folderId = `${account.id}-inbox`;
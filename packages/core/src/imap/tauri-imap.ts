@@ -3,18 +3,47 @@
  */
 
 import { invoke } from '@tauri-apps/api/core';
-import type { Account, MailFolder, MailMessage } from '../types';
-import { getAccountWithValidToken } from '../auth/token-refresh';
+import type { Account, FolderSyncState, MailAddress, MailFolder, MailMessage, MoveResult, SessionError, SyncDelta, UidResult } from '../types';
+
+/**
+ * Testo leggibile di un SessionError, con un rifiuto per ogni metodo di autenticazione provato
+ */
+export const describeSessionError = (error: SessionError): string => {
+  if (error.kind !== 'authentication') {
+    return error.details;
+  }
+  const failures = error.details.map((failure) => {
+    const status = failure.status ? ` (status ${failure.status})` : '';
+    return `${failure.method.toUpperCase()} rifiutato: ${failure.message}${status}`;
+  });
+  return `Errore nell'autenticazione: ${failures.join('; ')}`;
+};
+
+/**
+ * Errore dei comandi che aprono una sessione IMAP, con i dettagli strutturati del backend
+ */
+export class SessionCommandError extends Error {
+  constructor(public readonly error: SessionError) {
+    super(describeSessionError(error));
+    this.name = 'SessionCommandError';
+  }
+}
+
+const isSessionError = (error: unknown): error is SessionError =>
+  typeof error === 'object' && error !== null && 'kind' in error && 'details' in error;
+
+/**
+ * Converte l'errore di invoke in SessionCommandError se il comando ha restituito un SessionError
+ */
+export const toSessionCommandError = (error: unknown): unknown =>
+  isSessionError(error) ? new SessionCommandError(error) : error;
 
 /**
  * Sincronizza le cartelle di un account usando il comando Tauri
  */
 export const syncFoldersTauri = async (account: Account): Promise<MailFolder[]> => {
-  // Assicura che il token sia valido
-  const accountWithValidToken = await getAccountWithValidToken(account.id);
-  
   try {
-    console.log('[IMAP Tauri] Chiamata sync_folders per account:', accountWithValidToken.email);
+    console.log('[IMAP Tauri] Chiamata sync_folders per account:', account.email);
     
     // Verifica che invoke sia disponibile e funzionante
     const { invoke } = await import('@tauri-apps/api/core');
@@ -23,33 +52,115 @@ export const syncFoldersTauri = async (account: Account): Promise<MailFolder[]>
     }
     
     const folders = await invoke<MailFolder[]>('sync_folders', {
-      accountId: accountWithValidToken.id,
-      email: accountWithValidToken.email,
-      provider: accountWithValidToken.provider,
-      accessToken: accountWithValidToken.tokens.accessToken,
+      accountId: account.id,
     });
     
     console.log('[IMAP Tauri] Risposta sync_folders:', folders.length, 'cartelle');
     return folders;
   } catch (error) {
     console.error('[IMAP Tauri] Errore nella sincronizzazione delle cartelle:', error);
-    throw error;
+    throw toSessionCommandError(error);
   }
 };
 
 /**
- * Sincronizza i messaggi di una cartella usando il comando Tauri
+ * Messaggio così come serializzato dal comando Rust (campi snake_case)
+ */
+interface RawMailMessage {
+  id: string;
+  account_id: string;
+  folder_id: string;
+  uid: number;
+  message_id: string;
+  subject: string;
+  from_name: string | null;
+  from_address: string;
+  to_addresses: MailAddress[];
+  cc_addresses: MailAddress[] | null;
+  bcc_addresses: MailAddress[] | null;
+  date: number;
+  text: string | null;
+  html: string | null;
+  flags: string[];
+  is_read: boolean;
+  is_starred: boolean;
+  is_important: boolean;
+  thread_id: string | null;
+  in_reply_to: string | null;
+  references: string[] | null;
+  synced_at: number;
+}
+
+interface RawFolderSyncState {
+  uid_validity: number;
+  uid_next: number;
+  highest_modseq: number | null;
+}
+
+interface RawSyncDelta {
+  state: RawFolderSyncState;
+  full_resync: boolean;
+  new_messages: RawMailMessage[];
+  changed_flags: {
+    uid: number;
+    flags: string[];
+    keywords: string[];
+    is_read: boolean;
+    is_starred: boolean;
+    is_important: boolean;
+  }[];
+  vanished: number[];
+}
+
+const toMailMessage = (raw: RawMailMessage): MailMessage => ({
+  id: raw.id,
+  accountId: raw.account_id,
+  folderId: raw.folder_id,
+  uid: raw.uid,
+  messageId: raw.message_id,
+  subject: raw.subject,
+  from: {
+    name: raw.from_name ?? undefined,
+    address: raw.from_address,
+  },
+  to: raw.to_addresses,
+  cc: raw.cc_addresses ?? undefined,
+  bcc: raw.bcc_addresses ?? undefined,
+  date: new Date(raw.date),
+  text: raw.text ?? undefined,
+  html: raw.html ?? undefined,
+  // Gli allegati vengono scaricati su richiesta con fetch_attachment
+  attachments: [],
+  flags: raw.flags,
+  isRead: raw.is_read,
+  isStarred: raw.is_starred,
+  isImportant: raw.is_important,
+  threadId: raw.thread_id ?? undefined,
+  inReplyTo: raw.in_reply_to ?? undefined,
+  references: raw.references ?? undefined,
+  syncedAt: raw.synced_at,
+});
+
+export interface SyncMessagesOptions {
+  /** Limita la prima sincronizzazione ai messaggi successivi a questa data */
+  since?: Date;
+  /** Stato restituito dalla sync precedente; senza stato la sincronizzazione è completa */
+  state?: FolderSyncState | null;
+  /** UID presenti in locale, per rilevare i messaggi rimossi se il server non supporta QRESYNC */
+  knownUids?: number[];
+}
+
+/**
+ * Sincronizza i messaggi di una cartella usando il comando Tauri.
+ * Restituisce solo le differenze rispetto a `options.state`.
  */
 export const syncMessagesTauri = async (
   account: Account,
-  folderPath: string,
-  since?: Date
-): Promise<MailMessage[]> => {
-  // Assicura che il token sia valido
-  const accountWithValidToken = await getAccountWithValidToken(account.id);
-  
+  folder: MailFolder,
+  options: SyncMessagesOptions = {}
+): Promise<SyncDelta> => {
   try {
-    console.log('[IMAP Tauri] Chiamata sync_messages per cartella:', folderPath);
+    console.log('[IMAP Tauri] Chiamata sync_messages per cartella:', folder.path);
     
     // Verifica che invoke sia disponibile e funzionante
     const { invoke: invokeFn } = await import('@tauri-apps/api/core');
@@ -57,44 +168,86 @@ export const syncMessagesTauri = async (
       throw new Error('invoke non disponibile - non siamo in ambiente Tauri');
     }
     
-    const messages = await invokeFn<MailMessage[]>('sync_messages', {
-      accountId: accountWithValidToken.id,
-      folderId: '', // Sarà determinato dal frontend
-      folderPath,
-      email: accountWithValidToken.email,
-      provider: accountWithValidToken.provider,
-      accessToken: accountWithValidToken.tokens.accessToken,
-      since: since ? since.getTime() : null,
+    const state = options.state;
+    const delta = await invokeFn<RawSyncDelta>('sync_messages', {
+      accountId: account.id,
+      folderId: folder.id,
+      folderPath: folder.path,
+      since: options.since ? options.since.getTime() : null,
+      state: state
+        ? {
+            uid_validity: state.uidValidity,
+            uid_next: state.uidNext,
+            highest_modseq: state.highestModseq ?? null,
+          }
+        : null,
+      knownUids: options.knownUids ?? null,
     });
     
-    console.log('[IMAP Tauri] Risposta sync_messages:', messages.length, 'messaggi');
-    return messages;
+    console.log(
+      '[IMAP Tauri] Risposta sync_messages:',
+      delta.new_messages.length, 'nuovi,',
+      delta.changed_flags.length, 'flag modificati,',
+      delta.vanished.length, 'rimossi'
+    );
+    return {
+      state: {
+        uidValidity: delta.state.uid_validity,
+        uidNext: delta.state.uid_next,
+        highestModseq: delta.state.highest_modseq ?? undefined,
+      },
+      fullResync: delta.full_resync,
+      newMessages: delta.new_messages.map(toMailMessage),
+      changedFlags: delta.changed_flags.map((update) => ({
+        uid: update.uid,
+        flags: update.flags,
+        keywords: update.keywords,
+        isRead: update.is_read,
+        isStarred: update.is_starred,
+        isImportant: update.is_important,
+      })),
+      vanished: delta.vanished,
+    };
   } catch (error) {
     console.error('[IMAP Tauri] Errore nella sincronizzazione dei messaggi:', error);
     throw error;
   }
 };
 
+interface RawUidResult {
+  uid: number;
+  success: boolean;
+  error: string | null;
+  target_uid: number | null;
+  copied: boolean;
+}
+
+const toUidResult = (raw: RawUidResult): UidResult => ({
+  uid: raw.uid,
+  success: raw.success,
+  error: raw.error ?? undefined,
+  targetUid: raw.target_uid ?? undefined,
+  copied: raw.copied,
+});
+
 /**
- * Marca un messaggio come letto/non letto usando il comando Tauri
+ * Marca i messaggi come letti/non letti usando il comando Tauri.
+ * Restituisce l'esito per ogni UID.
  */
 export const markMessageReadTauri = async (
   account: Account,
   folderPath: string,
-  uid: number,
+  uids: number[],
   read: boolean
-): Promise<void> => {
-  // Assicura che il token sia valido
-  const accountWithValidToken = await getAccountWithValidToken(account.id);
-  
+): Promise<UidResult[]> => {
   try {
-    await invoke('mark_message_read', {
-      accountId: accountWithValidToken.id,
+    const results = await invoke<RawUidResult[]>('mark_message_read', {
+      accountId: account.id,
       folderPath,
-      uid,
+      uids,
       read,
-      accessToken: accountWithValidToken.tokens.accessToken,
     });
+    return results.map(toUidResult);
   } catch (error) {
     console.error('[IMAP Tauri] Errore nel marcare il messaggio come letto:', error);
     throw error;
@@ -102,27 +255,26 @@ export const markMessageReadTauri = async (
 };
 
 /**
- * Sposta un messaggio usando il comando Tauri
+ * Sposta i messaggi usando il comando Tauri.
+ * Per ogni UID restituisce l'esito e, se noto, l'UID nella cartella di destinazione.
  */
 export const moveMessageTauri = async (
   account: Account,
   folderPath: string,
-  uid: number,
+  uids: number[],
   targetFolder: string
-): Promise<void> => {
-  // Assicura che il token sia valido
-  const accountWithValidToken = await getAccountWithValidToken(account.id);
-  
+): Promise<MoveResult> => {
   try {
-    await invoke('move_message', {
-      accountId: accountWithValidToken.id,
+    const result = await invoke<{ target_uid_validity: number | null; results: RawUidResult[] }>('move_message', {
+      accountId: account.id,
       folderPath,
-      uid,
+      uids,
       targetFolder,
-      email: accountWithValidToken.email,
-      provider: accountWithValidToken.provider,
-      accessToken: accountWithValidToken.tokens.accessToken,
     });
+    return {
+      targetUidValidity: result.target_uid_validity ?? undefined,
+      results: result.results.map(toUidResult),
+    };
   } catch (error) {
     console.error('[IMAP Tauri] Errore nello spostamento del messaggio:', error);
     throw error;
@@ -130,28 +282,23 @@ export const moveMessageTauri = async (
 };
 
 /**
- * Elimina un messaggio usando il comando Tauri
+ * Elimina i messaggi (spostandoli nel Cestino) usando il comando Tauri.
+ * Restituisce l'esito per ogni UID.
  */
 export const deleteMessageTauri = async (
   account: Account,
   folderPath: string,
-  uid: number
-): Promise<void> => {
-  // Assicura che il token sia valido
-  const accountWithValidToken = await getAccountWithValidToken(account.id);
-  
+  uids: number[]
+): Promise<UidResult[]> => {
   try {
-    await invoke('delete_message', {
-      accountId: accountWithValidToken.id,
+    const results = await invoke<RawUidResult[]>('delete_message', {
+      accountId: account.id,
       folderPath,
-      uid,
-      email: accountWithValidToken.email,
-      provider: accountWithValidToken.provider,
-      accessToken: accountWithValidToken.tokens.accessToken,
+      uids,
     });
+    return results.map(toUidResult);
   } catch (error) {
     console.error('[IMAP Tauri] Errore nell\'eliminazione del messaggio:', error);
     throw error;
   }
 };
-
@@ -4,8 +4,7 @@
 
 export * from './types';
 export * from './auth/oauth';
-export { getOAuthUrl, exchangeOAuthCode, getOAuthRedirectUri } from './auth/oauth';
-export * from './auth/token-refresh';
+export * from './auth/tauri-accounts';
 export * from './imap/imap';
 export * from './imap/tauri-imap';
 export * from './smtp/smtp';
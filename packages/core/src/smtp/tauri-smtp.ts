@@ -4,21 +4,14 @@
 
 import { invoke } from '@tauri-apps/api/core';
 import type { Account, ComposeMessage } from '../types';
-import { getAccountWithValidToken } from '../auth/token-refresh';
 
 /**
  * Invia un'email usando il comando Tauri SMTP
  */
 export const sendEmailTauri = async (account: Account, message: ComposeMessage): Promise<void> => {
-  // Assicura che il token sia valido
-  const accountWithValidToken = await getAccountWithValidToken(account.id);
-
   try {
     await invoke('send_email', {
-      accountId: accountWithValidToken.id,
-      email: accountWithValidToken.email,
-      provider: accountWithValidToken.provider,
-      accessToken: accountWithValidToken.tokens.accessToken,
+      accountId: account.id,
       message: {
         to: message.to.map((addr: string | { address: string }) => (typeof addr === 'string' ? addr : addr.address)),
         cc: message.cc?.map((addr: string | { address: string }) => (typeof addr === 'string' ? addr : addr.address)),
@@ -4,7 +4,7 @@
 
 import { eq } from 'drizzle-orm';
 import { getDb, schema } from './db';
-import type { Account, MailMessage, MailFolder, AppSettings, OAuthTokens } from '../types';
+import type { Account, MailMessage, MailFolder, AppSettings, OAuthTokens, FolderSyncState } from '../types';
 import { encrypt, decrypt } from '../utils/encryption';
 
 /**
@@ -453,6 +453,9 @@ export const messageStorage = {
     if (updates.folderId !== undefined) {
       updateData.folderId = updates.folderId;
     }
+    if (updates.uid !== undefined) {
+      updateData.uid = updates.uid;
+    }
     if (updates.isRead !== undefined) {
       updateData.isRead = updates.isRead ? 1 : 0;
     }
@@ -478,6 +481,28 @@ export const messageStorage = {
     await db.delete(schema.messages).where(eq(schema.messages.id, id));
   },
 
+  /**
+   * Mappa UID -> id dei messaggi salvati per una cartella,
+   * usata per applicare flag modificati e UID rimossi restituiti da sync_messages
+   */
+  async getUidIndex(folderId: string): Promise<Map<number, string>> {
+    const db = await getDb();
+    const results = await db.select()
+      .from(schema.messages)
+      .where(eq(schema.messages.folderId, folderId));
+
+    const index = new Map<number, string>();
+    for (const row of results as any[]) {
+      index.set(row.uid, row.id);
+    }
+    return index;
+  },
+
+  async deleteByFolder(folderId: string): Promise<void> {
+    const db = await getDb();
+    await db.delete(schema.messages).where(eq(schema.messages.folderId, folderId));
+  },
+
   async getThreadMessages(messageId: string): Promise<MailMessage[]> {
     const db = await getDb();
     
@@ -585,6 +610,39 @@ export const messageStorage = {
   },
 };
 
+/**
+ * Stato di sincronizzazione per cartella (UIDVALIDITY, UIDNEXT, HIGHESTMODSEQ),
+ * salvato nella tabella settings con chiave `sync_state:<folderId>`
+ */
+export const syncStateStorage = {
+  async get(folderId: string): Promise<FolderSyncState | null> {
+    const db = await getDb();
+    const result = await db.select()
+      .from(schema.settings)
+      .where(eq(schema.settings.key, `sync_state:${folderId}`))
+      .limit(1);
+
+    if (result.length === 0) {
+      return null;
+    }
+
+    return JSON.parse(result[0].value);
+  },
+
+  async save(folderId: string, state: FolderSyncState): Promise<void> {
+    const db = await getDb();
+    await db.insert(schema.settings).values({
+      key: `sync_state:${folderId}`,
+      value: JSON.stringify(state),
+    }).onConflictDoUpdate({
+      target: schema.settings.key,
+      set: {
+        value: JSON.stringify(state),
+      },
+    });
+  },
+};
+
 /**
  * Gestione impostazioni
  */
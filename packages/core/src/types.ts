@@ -80,6 +80,79 @@ export interface ComposeMessage {
   inReplyTo?: string;
 }
 
+/**
+ * Stato di sincronizzazione di una cartella, da ripassare a sync_messages alla sync successiva
+ */
+export interface FolderSyncState {
+  uidValidity: number;
+  uidNext: number;
+  highestModseq?: number;
+}
+
+export interface FlagUpdate {
+  uid: number;
+  flags: string[];
+  keywords: string[];
+  isRead: boolean;
+  isStarred: boolean;
+  isImportant: boolean;
+}
+
+/**
+ * Differenze restituite da sync_messages.
+ * Se fullResync è true i messaggi locali della cartella vanno sostituiti con newMessages.
+ */
+export interface SyncDelta {
+  state: FolderSyncState;
+  fullResync: boolean;
+  newMessages: MailMessage[];
+  changedFlags: FlagUpdate[];
+  vanished: number[];
+}
+
+/**
+ * Esito per singolo UID dei comandi che modificano più messaggi
+ */
+export interface UidResult {
+  uid: number;
+  success: boolean;
+  error?: string;
+  /** UID nella cartella di destinazione (spostamenti), se noto */
+  targetUid?: number;
+  /**
+   * Spostamenti: il messaggio è già nella destinazione. Con success false la rimozione
+   * dalla sorgente è fallita e lo spostamento non va ripetuto, per non creare duplicati.
+   */
+  copied: boolean;
+}
+
+export interface MoveResult {
+  targetUidValidity?: number;
+  results: UidResult[];
+}
+
+/**
+ * Rifiuto dell'autenticazione con i dettagli restituiti dal server IMAP
+ */
+export interface AuthError {
+  method: 'xoauth2' | 'oauthbearer' | 'plain' | 'login';
+  message: string;
+  /** Payload SASL dell'errore (JSON per XOAUTH2 e OAUTHBEARER) */
+  payload: string | null;
+  /** Campo "status" del payload (es. "401" per Gmail) */
+  status: string | null;
+  /** Scope OAuth2 richiesto dal server */
+  scope: string | null;
+}
+
+/**
+ * Errore restituito da sync_folders, save_account, oauth_login e import_oauth_account
+ */
+export type SessionError =
+  | { kind: 'connection'; details: string }
+  | { kind: 'authentication'; details: AuthError[] }
+  | { kind: 'other'; details: string };
+
 export interface SyncStatus {
   accountId: string;
   folderId: string;